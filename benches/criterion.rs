@@ -3,7 +3,7 @@ use color_eyre::eyre::WrapErr;
 use criterion::{Criterion, criterion_group, criterion_main};
 use fs_err::File;
 use hdt::triples::*;
-use hdt::{Hdt, IdKind};
+use hdt::{Dictionary, Hdt, IdKind};
 use sophia::api::graph::Graph;
 use sophia::api::term::matcher::Any;
 use sophia::api::term::{IriRef, SimpleTerm};
@@ -0,0 +1,375 @@
+//! Evaluation of basic graph patterns (BGPs), i.e. conjunctions of triple patterns, on triple IDs.
+//! Constants are translated to IDs once, all joins operate on IDs and only the final bindings are translated to strings.
+//!
+//! The patterns are joined greedily, starting with the pattern with the lowest cardinality according to [`Hdt::estimate_count`]
+//! and continuing with the cheapest pattern that shares a variable with the already joined ones.
+//! Depending on the number of intermediate solutions, each pattern is either joined by looking up each solution in the triples index
+//! or evaluated on its own and joined with a merge join on a single shared variable or a hash join on multiple ones.
+//!
+//! IDs depend on the position in the triple, so a variable that occurs both as subject and as object
+//! can only be bound to a shared term, whose subject and object IDs are identical.
+use crate::four_sect_dict::ExtractError;
+use crate::triples::{Id, TripleId};
+use crate::{Dictionary, Hdt, IdKind};
+use std::collections::HashMap;
+
+/// Subject, predicate or object of a triple pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternTerm {
+    /// variable name without the leading question mark
+    Variable(String),
+    /// term in the string representation of the dictionary, see [`crate::Dictionary`]
+    Constant(String),
+}
+
+impl From<&str> for PatternTerm {
+    /// Strings starting with a question mark are variables, all others are constants.
+    fn from(s: &str) -> Self {
+        match s.strip_prefix('?') {
+            Some(name) => PatternTerm::Variable(name.to_owned()),
+            None => PatternTerm::Constant(s.to_owned()),
+        }
+    }
+}
+
+/// Subject, predicate and object of a triple pattern.
+pub type TriplePattern = [PatternTerm; 3];
+
+/// Solutions of a basic graph pattern, each of which binds all variables to IDs.
+#[derive(Debug, Clone)]
+pub struct Bindings {
+    /// variable names in the order of their first occurrence, which is also the order of the values in each row
+    pub variables: Vec<String>,
+    /// kind of the IDs of each variable, which is the position of its first occurrence in the join order
+    pub kinds: Vec<IdKind>,
+    /// one row of IDs for each solution
+    pub rows: Vec<Vec<Id>>,
+}
+
+impl Bindings {
+    /// Number of solutions.
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Whether there are no solutions.
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Translates the IDs of each solution to strings in the order of [`Bindings::variables`].
+    pub fn strings<'a>(
+        &'a self, hdt: &'a Hdt,
+    ) -> impl Iterator<Item = core::result::Result<Vec<String>, ExtractError>> + 'a {
+        self.rows.iter().map(move |row| {
+            row.iter().zip(&self.kinds).map(|(id, kind)| hdt.dict.id_to_string(*id, *kind)).collect()
+        })
+    }
+}
+
+/// A triple pattern whose constants are translated to IDs and whose variables are indices.
+struct Compiled {
+    ids: TripleId,
+    vars: [Option<usize>; 3],
+    estimate: usize,
+}
+
+impl Compiled {
+    /// distinct variables in order of their position
+    fn distinct_vars(&self) -> Vec<usize> {
+        let mut vars: Vec<usize> = Vec::with_capacity(3);
+        for v in self.vars.into_iter().flatten() {
+            if !vars.contains(&v) {
+                vars.push(v);
+            }
+        }
+        vars
+    }
+}
+
+/// Extends a solution with the values of the new variables.
+fn concat(left: &[Id], right: &[Id]) -> Vec<Id> {
+    [left, right].concat()
+}
+
+/// Intermediate solutions during the evaluation.
+struct Evaluator<'a> {
+    hdt: &'a Hdt,
+    /// kind of each variable, assigned when it is first bound
+    kinds: Vec<Option<IdKind>>,
+    /// column of each bound variable
+    columns: Vec<Option<usize>>,
+    rows: Vec<Vec<Id>>,
+}
+
+impl Evaluator<'_> {
+    /// Converts an ID between kinds, 0 if the term does not exist in the target kind.
+    fn convert(&self, id: Id, from: IdKind, to: IdKind) -> Id {
+        match (from, to) {
+            (IdKind::Subject, IdKind::Subject)
+            | (IdKind::Predicate, IdKind::Predicate)
+            | (IdKind::Object, IdKind::Object) => id,
+            // only shared terms have both a subject and an object ID, which are identical
+            (IdKind::Subject, IdKind::Object) | (IdKind::Object, IdKind::Subject) => {
+                if id <= self.hdt.dict.num_shared() { id } else { 0 }
+            }
+            // predicates are numbered separately
+            _ => self.hdt.dict.id_to_string(id, from).map_or(0, |s| self.hdt.dict.string_to_id(&s, to)),
+        }
+    }
+
+    fn kind(&self, var: usize) -> IdKind {
+        self.kinds[var].expect("variable kind is assigned before scanning")
+    }
+
+    /// Values of the `out` variables for each triple that matches the pattern, where the variables in `subst` are replaced with the given values.
+    fn scan(&self, p: &Compiled, subst: &[(usize, Id)], out: &[usize]) -> Vec<Vec<Id>> {
+        let mut ids = p.ids;
+        for (i, var) in p.vars.iter().enumerate() {
+            let Some(var) = var else { continue };
+            if let Some((_, value)) = subst.iter().find(|(v, _)| v == var) {
+                ids[i] = self.convert(*value, self.kind(*var), IdKind::KINDS[i]);
+                if ids[i] == 0 {
+                    return Vec::new();
+                }
+            }
+        }
+        self.hdt
+            .triple_ids_with_id_pattern(ids)
+            .filter_map(|t| {
+                // all positions of a variable must refer to the same term
+                let mut values: [(usize, Id); 3] = [(usize::MAX, 0); 3];
+                for (i, var) in p.vars.iter().enumerate() {
+                    let Some(var) = var else { continue };
+                    let value = self.convert(t[i], IdKind::KINDS[i], self.kind(*var));
+                    if value == 0 || values.iter().any(|(v, x)| v == var && *x != value) {
+                        return None;
+                    }
+                    values[i] = (*var, value);
+                }
+                Some(out.iter().map(|o| values.iter().find(|(v, _)| v == o).unwrap().1).collect())
+            })
+            .collect()
+    }
+
+    fn join(&mut self, p: &Compiled) {
+        let vars = p.distinct_vars();
+        let (shared, new): (Vec<usize>, Vec<usize>) = vars.into_iter().partition(|v| self.columns[*v].is_some());
+        for (i, var) in p.vars.iter().enumerate() {
+            if let Some(var) = var {
+                self.kinds[*var].get_or_insert(IdKind::KINDS[i]);
+            }
+        }
+        let left = std::mem::take(&mut self.rows);
+        let shared_columns: Vec<usize> = shared.iter().map(|v| self.columns[*v].unwrap()).collect();
+        self.rows = if shared.is_empty() {
+            // cross product, which also starts the evaluation with the initial solution that binds no variables
+            let right = self.scan(p, &[], &new);
+            left.iter().flat_map(|l| right.iter().map(|r| concat(l, r))).collect()
+        } else if left.len() < p.estimate {
+            // index nested loop join: few solutions, so look up each one
+            let mut rows = Vec::new();
+            for l in &left {
+                let subst: Vec<(usize, Id)> =
+                    shared.iter().zip(&shared_columns).map(|(v, c)| (*v, l[*c])).collect();
+                rows.extend(self.scan(p, &subst, &new).into_iter().map(|r| concat(l, &r)));
+            }
+            rows
+        } else if let [column] = shared_columns[..] {
+            // merge join on the single shared variable
+            let mut left = left;
+            let mut right = self.scan(p, &[], &[shared.clone(), new].concat());
+            left.sort_unstable_by_key(|l| l[column]);
+            right.sort_unstable_by_key(|r| r[0]);
+            let mut rows = Vec::new();
+            let (mut i, mut j) = (0, 0);
+            while i < left.len() && j < right.len() {
+                let key = left[i][column];
+                match key.cmp(&right[j][0]) {
+                    std::cmp::Ordering::Less => i += 1,
+                    std::cmp::Ordering::Greater => j += 1,
+                    std::cmp::Ordering::Equal => {
+                        let i_end = i + left[i..].iter().take_while(|l| l[column] == key).count();
+                        let j_end = j + right[j..].iter().take_while(|r| r[0] == key).count();
+                        for l in &left[i..i_end] {
+                            rows.extend(right[j..j_end].iter().map(|r| concat(l, &r[1..])));
+                        }
+                        (i, j) = (i_end, j_end);
+                    }
+                }
+            }
+            rows
+        } else {
+            // hash join on the shared variables
+            let right = self.scan(p, &[], &[shared.clone(), new.clone()].concat());
+            let mut table: HashMap<&[Id], Vec<&[Id]>> = HashMap::new();
+            for r in &right {
+                let (key, values) = r.split_at(shared.len());
+                table.entry(key).or_default().push(values);
+            }
+            let mut rows = Vec::new();
+            for l in &left {
+                let key: Vec<Id> = shared_columns.iter().map(|c| l[*c]).collect();
+                if let Some(matches) = table.get(key.as_slice()) {
+                    rows.extend(matches.iter().map(|r| concat(l, r)));
+                }
+            }
+            rows
+        };
+        for var in new {
+            self.columns[var] = Some(self.columns.iter().flatten().count());
+        }
+    }
+}
+
+impl Hdt {
+    /// Evaluates a basic graph pattern, i.e. all solutions that match each of the triple patterns, on the triple IDs.
+    /// Unlike evaluating each pattern with [`Hdt::triples_with_pattern`] and joining the results, no string is created before the final translation of the bindings.
+    /// # Example
+    /// ```
+    /// use hdt::Hdt;
+    /// use hdt::bgp::PatternTerm;
+    /// let file = std::fs::File::open("tests/resources/snikmeta.hdt").unwrap();
+    /// let hdt = Hdt::read(std::io::BufReader::new(file)).unwrap();
+    /// let label = "http://www.w3.org/2000/01/rdf-schema#label";
+    /// let patterns = [["?s", label, "\"top class\"@en"], ["?s", label, "?label"]].map(|p| p.map(PatternTerm::from));
+    /// let bindings = hdt.evaluate_bgp(&patterns);
+    /// for solution in bindings.strings(&hdt) {
+    ///     println!("{:?}", solution.unwrap());
+    /// }
+    /// ```
+    pub fn evaluate_bgp(&self, patterns: &[TriplePattern]) -> Bindings {
+        let mut names: Vec<String> = Vec::new();
+        let mut compiled = Vec::with_capacity(patterns.len());
+        let mut missing = false;
+        for pattern in patterns {
+            let mut ids = [0; 3];
+            let mut vars = [None; 3];
+            for (i, term) in pattern.iter().enumerate() {
+                match term {
+                    PatternTerm::Constant(c) => {
+                        ids[i] = self.dict.string_to_id(c, IdKind::KINDS[i]);
+                        missing |= ids[i] == 0;
+                    }
+                    PatternTerm::Variable(v) => {
+                        vars[i] = Some(names.iter().position(|n| n == v).unwrap_or_else(|| {
+                            names.push(v.clone());
+                            names.len() - 1
+                        }));
+                    }
+                }
+            }
+            let estimate = self.estimate_count(ids).estimate;
+            missing |= estimate == 0;
+            compiled.push(Compiled { ids, vars, estimate });
+        }
+        let mut evaluator = Evaluator {
+            hdt: self,
+            kinds: vec![None; names.len()],
+            columns: vec![None; names.len()],
+            rows: vec![vec![]],
+        };
+        if missing {
+            // a constant does not exist or a pattern has no match
+            evaluator.rows.clear();
+        }
+        let mut remaining: Vec<Compiled> = compiled;
+        while !remaining.is_empty() && !evaluator.rows.is_empty() {
+            // prefer patterns that share a variable with the previous ones to avoid cross products
+            let next = (0..remaining.len())
+                .min_by_key(|i| {
+                    let p = &remaining[*i];
+                    let connected = p.vars.iter().flatten().any(|v| evaluator.columns[*v].is_some());
+                    (!connected, p.estimate)
+                })
+                .unwrap();
+            let p = remaining.swap_remove(next);
+            evaluator.join(&p);
+        }
+        // order the columns by first occurrence in the query
+        let kinds: Vec<IdKind> = (0..names.len()).map(|v| evaluator.kinds[v].unwrap_or(IdKind::Subject)).collect();
+        let rows = if evaluator.rows.is_empty() {
+            Vec::new()
+        } else {
+            let columns: Vec<usize> = evaluator.columns.iter().map(|c| c.unwrap()).collect();
+            evaluator.rows.into_iter().map(|row| columns.iter().map(|c| row[*c]).collect()).collect()
+        };
+        Bindings { variables: names, kinds, rows }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hdt::tests::snikmeta;
+    use crate::tests::init;
+    use pretty_assertions::assert_eq;
+
+    const TYPE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+    const LABEL: &str = "http://www.w3.org/2000/01/rdf-schema#label";
+
+    /// Joins the string results of each pattern in the given order.
+    fn naive(hdt: &Hdt, patterns: &[TriplePattern], variables: &[String]) -> Vec<Vec<String>> {
+        let mut solutions: Vec<HashMap<String, String>> = vec![HashMap::new()];
+        for pattern in patterns {
+            let mut next = Vec::new();
+            for solution in &solutions {
+                let terms: Vec<Option<String>> = pattern
+                    .iter()
+                    .map(|t| match t {
+                        PatternTerm::Constant(c) => Some(c.clone()),
+                        PatternTerm::Variable(v) => solution.get(v).cloned(),
+                    })
+                    .collect();
+                'triples: for triple in
+                    hdt.triples_with_pattern(terms[0].as_deref(), terms[1].as_deref(), terms[2].as_deref())
+                {
+                    let mut extended = solution.clone();
+                    for (t, value) in pattern.iter().zip(&triple) {
+                        if let PatternTerm::Variable(v) = t {
+                            if extended.get(v).is_some_and(|x| **x != **value) {
+                                continue 'triples;
+                            }
+                            extended.insert(v.clone(), value.to_string());
+                        }
+                    }
+                    next.push(extended);
+                }
+            }
+            solutions = next;
+        }
+        let mut rows: Vec<Vec<String>> =
+            solutions.into_iter().map(|s| variables.iter().map(|v| s[v].clone()).collect()).collect();
+        rows.sort();
+        rows
+    }
+
+    #[test]
+    fn evaluate_bgp() -> color_eyre::Result<()> {
+        init();
+        let hdt = snikmeta()?;
+        let queries: Vec<Vec<[&str; 3]>> = vec![
+            vec![],
+            vec![["?s", TYPE, "?c"]],
+            // object variable joined as subject
+            vec![["?s", TYPE, "?c"], ["?c", LABEL, "?l"]],
+            vec![["?s", LABEL, "?l"], ["?s", TYPE, "?c"], ["?s", "?p", "?c"]],
+            vec![["?s", "?p", "?o"], ["?o", "?p2", "?x"]],
+            // predicate variable joined as subject
+            vec![["?s", "?p", "?o"], ["?p", "?q", "?r"]],
+            // repeated variable in one pattern
+            vec![["?x", "?p", "?x"]],
+            // cross product
+            vec![["http://www.snik.eu/ontology/meta/Top", LABEL, "?l"], ["?s", TYPE, "?c"]],
+            vec![["?s", "http://example.org/missing", "?o"], ["?s", TYPE, "?c"]],
+        ];
+        for query in queries {
+            let patterns: Vec<TriplePattern> = query.iter().map(|p| p.map(PatternTerm::from)).collect();
+            let bindings = hdt.evaluate_bgp(&patterns);
+            let mut actual = bindings.strings(&hdt).collect::<core::result::Result<Vec<_>, _>>()?;
+            actual.sort();
+            assert_eq!(actual, naive(&hdt, &patterns, &bindings.variables), "{query:?}");
+        }
+        Ok(())
+    }
+}
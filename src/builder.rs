@@ -0,0 +1,167 @@
+//! *This module is available only if HDT is built with the experimental `"nt"` feature.*
+use super::DEFAULT_BASE_IRI;
+use super::nt::{IndexPool, Result, clean_term};
+use crate::Hdt;
+use crate::triples::Order;
+use lasso::{Key, Spur, ThreadedRodeo};
+
+/// Builds an HDT from triples that are already in memory, without serializing them to an RDF file first.
+/// Terms use the same string representation as the dictionary, see [`crate::Dictionary`], but IRIs may also be given in angle brackets.
+/// Duplicate triples are removed.
+/// *This type is available only if HDT is built with the experimental `"nt"` feature.*
+/// # Example
+/// ```
+/// use hdt::HdtBuilder;
+/// let hdt = HdtBuilder::new()
+///     .add_triple("http://example.org/s", "http://example.org/p", "\"hello\"@en")
+///     .add_triple("<http://example.org/s>", "<http://example.org/p>", "_:b1")
+///     .build()
+///     .unwrap();
+/// assert_eq!(hdt.triples_all().count(), 2);
+/// ```
+pub struct HdtBuilder {
+    lasso: ThreadedRodeo<Spur>,
+    triples: Vec<[usize; 3]>,
+    order: Order,
+    base_iri: String,
+}
+
+impl Default for HdtBuilder {
+    fn default() -> Self {
+        HdtBuilder {
+            lasso: ThreadedRodeo::new(),
+            triples: Vec::new(),
+            order: Order::SPO,
+            base_iri: DEFAULT_BASE_IRI.to_owned(),
+        }
+    }
+}
+
+impl HdtBuilder {
+    /// Creates an empty builder for an HDT with SPO order.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the order of the triples section.
+    #[must_use]
+    pub const fn with_order(mut self, order: Order) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Sets the IRI that identifies the dataset in the header.
+    #[must_use]
+    pub fn with_base_iri(mut self, base_iri: impl Into<String>) -> Self {
+        self.base_iri = base_iri.into();
+        self
+    }
+
+    /// Adds a triple, for chaining calls.
+    #[must_use]
+    pub fn add_triple(mut self, s: impl AsRef<str>, p: impl AsRef<str>, o: impl AsRef<str>) -> Self {
+        self.insert(s, p, o);
+        self
+    }
+
+    /// Adds a triple.
+    pub fn insert(&mut self, s: impl AsRef<str>, p: impl AsRef<str>, o: impl AsRef<str>) {
+        let triple = [s.as_ref(), p.as_ref(), o.as_ref()].map(|term| {
+            let mut term = term.to_owned();
+            clean_term(&mut term);
+            self.lasso.get_or_intern(term).into_usize()
+        });
+        self.triples.push(triple);
+    }
+
+    /// Adds a Sophia triple.
+    /// # Errors
+    /// Returns an error of kind `InvalidInput` if a term is neither an IRI, a blank node nor a literal, such as a variable or a quoted triple.
+    #[cfg(feature = "sophia")]
+    pub fn insert_sophia<T: sophia::api::triple::Triple>(&mut self, triple: T) -> Result<()> {
+        let [s, p, o] = triple.spo().map(crate::hdt_graph::sophia_term_string);
+        self.insert(s?, p?, o?);
+        Ok(())
+    }
+
+    /// Number of added triples, including duplicates.
+    pub fn len(&self) -> usize {
+        self.triples.len()
+    }
+
+    /// Whether no triple has been added yet.
+    pub fn is_empty(&self) -> bool {
+        self.triples.is_empty()
+    }
+
+    /// Creates the dictionary, triples and header.
+    /// # Errors
+    /// Returns an error if the order is [`Order::Unknown`].
+    pub fn build(self) -> Result<Hdt> {
+        let pool = IndexPool::new(self.lasso, self.triples);
+        Hdt::from_pool(pool, self.order, &self.base_iri, None)
+    }
+}
+
+impl<S: AsRef<str>> Extend<[S; 3]> for HdtBuilder {
+    fn extend<I: IntoIterator<Item = [S; 3]>>(&mut self, iter: I) {
+        for [s, p, o] in iter {
+            self.insert(s, p, o);
+        }
+    }
+}
+
+impl Hdt {
+    /// Builds an HDT with SPO order from string triples in memory, see [`HdtBuilder`] for more options.
+    /// *This function is available only if HDT is built with the experimental `"nt"` feature.*
+    /// # Example
+    /// ```
+    /// let hdt = hdt::Hdt::from_triples([["http://example.org/s", "http://example.org/p", "\"o\""]]).unwrap();
+    /// assert_eq!(hdt.triples_all().count(), 1);
+    /// ```
+    pub fn from_triples<S: AsRef<str>>(triples: impl IntoIterator<Item = [S; 3]>) -> Result<Self> {
+        let mut builder = HdtBuilder::new();
+        builder.extend(triples);
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hdt::tests::{snikmeta, snikmeta_check};
+    use crate::tests::init;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn build() -> color_eyre::Result<()> {
+        init();
+        let snikmeta = snikmeta()?;
+        let triples: Vec<_> = snikmeta.triples_all().collect();
+        // terms as stored in the dictionary
+        let hdt = Hdt::from_triples(triples.iter().map(|t| t.clone().map(|s| s.to_string())))?;
+        snikmeta_check(&hdt)?;
+        assert_eq!(hdt.triples_all().collect::<Vec<_>>(), triples);
+        for order in [Order::OPS, Order::PSO] {
+            let mut builder = HdtBuilder::new().with_order(order);
+            builder.extend(triples.iter().rev().cloned());
+            assert_eq!(builder.len(), triples.len());
+            let hdt = builder.build()?;
+            assert_eq!(hdt.triples.order(), order);
+            snikmeta_check(&hdt)?;
+        }
+        #[cfg(feature = "sophia")]
+        {
+            use sophia::api::graph::Graph;
+            let mut builder = HdtBuilder::new();
+            for t in snikmeta.triples() {
+                builder.insert_sophia(t?)?;
+            }
+            assert_eq!(builder.build()?.triples_all().collect::<Vec<_>>(), triples);
+        }
+        let empty = HdtBuilder::new().with_base_iri("http://example.org/empty").build()?;
+        assert_eq!(empty.triples_all().count(), 0);
+        assert!(HdtBuilder::new().with_order(Order::Unknown).build().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,355 @@
+//! Cache files that store the triples of an HDT file together with their derived indexes and optionally the dictionary.
+//! A cache file starts with a magic number, the cache format version and the version of this library,
+//! followed by a fingerprint of the HDT file it was created from and a CRC32C checksum of the payload.
+//! Cache files that don't match the library or the HDT file or whose payload is corrupted are rebuilt.
+use super::{Hdt, Result};
+use crate::compression;
+use crate::containers::ControlInfo;
+use crate::header::Header;
+use crate::triples::{ObjectIndex, TriplesBitmap};
+use crate::{Dict, four_sect_dict};
+use bytesize::ByteSize;
+use log::{debug, warn};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Extension appended to the file name of the HDT file.
+pub(crate) const CACHE_EXT: &str = "index.v2-rust-cache";
+/// Identifies cache files of this library.
+const MAGIC: &[u8; 8] = b"HDTRSCAC";
+/// Incremented whenever the layout of cache files changes.
+const FORMAT_VERSION: u32 = 3;
+/// The serialized indexes may change between releases even if the layout of the cache file stays the same.
+const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Number of bytes at the start and at the end of the HDT file that are covered by the checksum of the fingerprint.
+const SAMPLE_SIZE: u64 = 1 << 20;
+/// Set if the dictionary follows the triples in the payload.
+const FLAG_DICT: u8 = 1;
+static CRC32: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISCSI);
+
+/// The error type for reading and writing cache files.
+#[derive(thiserror::Error, Debug)]
+pub enum CacheError {
+    #[error("IO error")]
+    Io(#[from] io::Error),
+    #[error("not a cache file of this library")]
+    InvalidMagic,
+    #[error("unsupported cache format version {0}, expected {FORMAT_VERSION}")]
+    FormatVersion(u32),
+    #[error("cache was written by another version of this library, expected {CRATE_VERSION}")]
+    CrateVersion,
+    #[error("the HDT file has changed since the cache was written")]
+    Stale,
+    #[error("Invalid CRC32C checksum {0}, expected {1}")]
+    InvalidCrc32Checksum(u32, u32),
+    #[error("failed to encode triples")]
+    Encode(#[from] bincode::error::EncodeError),
+    #[error("failed to decode triples")]
+    Decode(#[from] bincode::error::DecodeError),
+    #[error("failed to read or write dictionary")]
+    Dict(#[from] four_sect_dict::Error),
+}
+
+/// Where cache files are stored and what they contain.
+/// # Example
+/// ```no_run
+/// let options = hdt::CacheOptions { dir: Some("/var/cache/hdt".into()), dictionary: true };
+/// let path = std::path::Path::new("tests/resources/snikmeta.hdt");
+/// let hdt = hdt::Hdt::read_from_path_with_cache(path, &options).unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CacheOptions {
+    /// Directory of the cache files, which is created if necessary.
+    /// If `None`, the cache file is stored next to the HDT file.
+    pub dir: Option<PathBuf>,
+    /// Also store the dictionary, so that only the header is read from the HDT file when loading from the cache.
+    /// The cache file is then at least as large as the HDT file.
+    pub dictionary: bool,
+}
+
+/// Identifies the state of an HDT file without reading it completely.
+#[derive(Debug, PartialEq, Eq)]
+struct Fingerprint {
+    len: u64,
+    /// nanoseconds since the Unix epoch or 0 if not supported by the platform
+    modified: u128,
+    /// checksum of the first and last bytes
+    checksum: u32,
+}
+
+impl Fingerprint {
+    fn of(f: &Path) -> io::Result<Self> {
+        let mut file = File::open(f)?;
+        let meta = file.metadata()?;
+        let len = meta.len();
+        let modified =
+            meta.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map_or(0, |d| d.as_nanos());
+        let mut buf = Vec::new();
+        (&mut file).take(SAMPLE_SIZE).read_to_end(&mut buf)?;
+        if len > SAMPLE_SIZE {
+            file.seek(SeekFrom::Start(SAMPLE_SIZE.max(len - SAMPLE_SIZE)))?;
+            file.read_to_end(&mut buf)?;
+        }
+        Ok(Fingerprint { len, modified, checksum: CRC32.checksum(&buf) })
+    }
+
+    fn read(reader: &mut impl Read) -> io::Result<Self> {
+        Ok(Fingerprint {
+            len: u64::from_le_bytes(read_array(reader)?),
+            modified: u128::from_le_bytes(read_array(reader)?),
+            checksum: u32::from_le_bytes(read_array(reader)?),
+        })
+    }
+
+    fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(&self.len.to_le_bytes())?;
+        writer.write_all(&self.modified.to_le_bytes())?;
+        writer.write_all(&self.checksum.to_le_bytes())
+    }
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Computes the checksum of everything written through it.
+struct ChecksumWriter<W> {
+    inner: W,
+    digest: crc::Digest<'static, u32>,
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.digest.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reads the triples and, if contained, the dictionary from a cache file after validating it against the HDT file.
+fn read_cache(
+    path: &Path, source: &Fingerprint,
+) -> core::result::Result<(TriplesBitmap, Option<Dict>), CacheError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    if &read_array::<8>(&mut reader)? != MAGIC {
+        return Err(CacheError::InvalidMagic);
+    }
+    let format_version = u32::from_le_bytes(read_array(&mut reader)?);
+    if format_version != FORMAT_VERSION {
+        return Err(CacheError::FormatVersion(format_version));
+    }
+    let version_len = u64::from_le_bytes(read_array(&mut reader)?);
+    if version_len != CRATE_VERSION.len() as u64 {
+        return Err(CacheError::CrateVersion);
+    }
+    let mut version = vec![0; CRATE_VERSION.len()];
+    reader.read_exact(&mut version)?;
+    if version != CRATE_VERSION.as_bytes() {
+        return Err(CacheError::CrateVersion);
+    }
+    if Fingerprint::read(&mut reader)? != *source {
+        return Err(CacheError::Stale);
+    }
+    let [flags] = read_array(&mut reader)?;
+    let checksum = u32::from_le_bytes(read_array(&mut reader)?);
+    // validate the whole payload before decoding it, as corrupted lengths could cause huge allocations
+    let payload_start = reader.stream_position()?;
+    let mut digest = CRC32.digest();
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+        digest.update(buf);
+        let n = buf.len();
+        reader.consume(n);
+    }
+    let crc_calculated = digest.finalize();
+    if crc_calculated != checksum {
+        return Err(CacheError::InvalidCrc32Checksum(crc_calculated, checksum));
+    }
+    reader.seek(SeekFrom::Start(payload_start))?;
+    let mut triples: TriplesBitmap =
+        bincode::serde::decode_from_std_read(&mut reader, bincode::config::standard())?;
+    // builds the object index if the cache was written without it
+    triples.set_object_index(ObjectIndex::Eager);
+    let dict = if flags & FLAG_DICT == 0 { None } else { Some(Dict::read(&mut reader)?.validate()?) };
+    Ok((triples, dict))
+}
+
+/// Writes the cache file via a temporary file, so that an interrupted write never leaves an incomplete cache behind.
+fn write_cache(
+    path: &Path, source: &Fingerprint, triples: &TriplesBitmap, dict: Option<&Dict>,
+) -> core::result::Result<(), CacheError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    let mut writer = BufWriter::new(File::create(&tmp_path)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    writer.write_all(&(CRATE_VERSION.len() as u64).to_le_bytes())?;
+    writer.write_all(CRATE_VERSION.as_bytes())?;
+    source.write(&mut writer)?;
+    writer.write_all(&[if dict.is_some() { FLAG_DICT } else { 0 }])?;
+    let checksum_pos = writer.stream_position()?;
+    // placeholder for the checksum, which is only known after the payload is written
+    writer.write_all(&[0; 4])?;
+    let mut payload = ChecksumWriter { inner: writer, digest: CRC32.digest() };
+    bincode::serde::encode_into_std_write(triples, &mut payload, bincode::config::standard())?;
+    if let Some(dict) = dict {
+        dict.write(&mut payload)?;
+    }
+    let ChecksumWriter { inner: mut writer, digest } = payload;
+    writer.seek(SeekFrom::Start(checksum_pos))?;
+    writer.write_all(&digest.finalize().to_le_bytes())?;
+    writer.flush()?;
+    drop(writer);
+    fs::rename(tmp_path, path)?;
+    Ok(())
+}
+
+impl Hdt {
+    /// Creates an immutable HDT instance containing the dictionary and triples from the Path.
+    /// Will utilize a custom cache file next to the HDT file if it exists and is valid or create one otherwise,
+    /// see [`Hdt::read_from_path_with_cache`].
+    /// The file path must point to the beginning of the data of an HDT file,
+    /// which may be compressed with gzip, bzip2, Zstandard or xz, see [`crate::compression`].
+    /// Supported dictionary implementations are the FourSectionDictionary, including its PSFC variant, and the MultipleSectionDictionary of hdt-java,
+    /// each with DictionarySectionPlainFrontCoding sections.
+    /// The format is specified at <https://www.rdfhdt.org/hdt-binary-format/>, however there are some deviations.
+    /// The initial HDT specification at <http://www.w3.org/Submission/2011/03/> is outdated and not supported.
+    /// # Example
+    /// ```
+    /// let hdt = hdt::Hdt::read_from_path(std::path::Path::new("tests/resources/snikmeta.hdt")).unwrap();
+    /// ```
+    pub fn read_from_path(f: &Path) -> Result<Self> {
+        Self::read_from_path_with_cache(f, &CacheOptions::default())
+    }
+
+    /// Like [`Hdt::read_from_path`] but with the cache file located and filled according to the given options.
+    /// The cache contains the triples including the wavelet matrix and the object index and optionally the dictionary.
+    /// It is only used if it was written by the same version of this library for an HDT file with the same size,
+    /// modification time and checksum of its first and last megabyte and if its own checksum is valid.
+    /// Otherwise, the HDT file is read completely and the cache file is overwritten.
+    /// Failing to write the cache file is logged but not an error.
+    pub fn read_from_path_with_cache(f: &Path, options: &CacheOptions) -> Result<Self> {
+        let cache_path = Self::cache_path(f, options)?;
+        let source = Fingerprint::of(f)?;
+        let mut reader = compression::open(f)?;
+        ControlInfo::read(&mut reader)?;
+        let header = Header::read(&mut reader)?;
+        let cached = if cache_path.exists() {
+            debug!("loading cache from {}", cache_path.display());
+            read_cache(&cache_path, &source)
+                .inspect_err(|e| warn!("error loading cache {}, overwriting: {e}", cache_path.display()))
+                .ok()
+        } else {
+            None
+        };
+        let (dict, triples) = match cached {
+            Some((triples, Some(dict))) => (dict, triples),
+            Some((triples, None)) if !options.dictionary => (Dict::read(&mut reader)?.validate()?, triples),
+            _ => {
+                let unvalidated_dict = Dict::read(&mut reader)?;
+                let triples = TriplesBitmap::read_sect(&mut reader)?;
+                let dict = unvalidated_dict.validate()?;
+                debug!("saving cache to {}", cache_path.display());
+                let cached_dict = if options.dictionary { Some(&dict) } else { None };
+                if let Err(e) = write_cache(&cache_path, &source, &triples, cached_dict) {
+                    warn!("error trying to save cache to {}: {e}", cache_path.display());
+                }
+                (dict, triples)
+            }
+        };
+        let hdt = Hdt::from_sections(header, dict, triples);
+        debug!("HDT size in memory {}, details:", ByteSize(hdt.size_in_bytes() as u64));
+        debug!("{hdt:#?}");
+        Ok(hdt)
+    }
+
+    /// Path of the cache file for the HDT file at the given path.
+    /// Inside a cache directory, the file name contains a checksum of the absolute path of the HDT file,
+    /// so that HDT files with the same name in different directories don't share a cache file.
+    pub fn cache_path(f: &Path, options: &CacheOptions) -> io::Result<PathBuf> {
+        let abs_path = fs::canonicalize(f)?;
+        let name = abs_path.file_name().unwrap_or_default().to_string_lossy();
+        Ok(match &options.dir {
+            None => abs_path.with_file_name(format!("{name}.{CACHE_EXT}")),
+            Some(dir) => {
+                let path_checksum = CRC32.checksum(abs_path.as_os_str().as_encoded_bytes());
+                dir.join(format!("{name}.{path_checksum:08x}.{CACHE_EXT}"))
+            }
+        })
+    }
+
+    /// Writes the cache file for the HDT file at the given path, from which this HDT must have been loaded unchanged,
+    /// and returns the path of the cache file.
+    /// This cache file is usable only by this library and is not intended to be used with hdt-cpp or hdt-java.
+    pub fn write_cache(&self, f: &Path, options: &CacheOptions) -> core::result::Result<PathBuf, CacheError> {
+        let cache_path = Self::cache_path(f, options)?;
+        let dict = if options.dictionary { Some(&self.dict) } else { None };
+        write_cache(&cache_path, &Fingerprint::of(f)?, &self.triples, dict)?;
+        Ok(cache_path)
+    }
+
+    /// Removes the cache file of the HDT file at the given path, if it exists,
+    /// for example when the HDT file was replaced in a way that the fingerprint cannot detect.
+    /// Returns whether there was a cache file.
+    pub fn invalidate_cache(f: &Path, options: &CacheOptions) -> io::Result<bool> {
+        match fs::remove_file(Self::cache_path(f, options)?) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hdt::tests::snikmeta_check;
+    use crate::tests::init;
+    use color_eyre::Result;
+
+    #[test]
+    fn options() -> Result<()> {
+        init();
+        let path = Path::new("tests/resources/snikmeta.hdt");
+        let dir = std::env::temp_dir().join(format!("hdt-cache-test-{}", std::process::id()));
+        let options = CacheOptions { dir: Some(dir.clone()), dictionary: true };
+        let cache_path = Hdt::cache_path(path, &options)?;
+        assert!(cache_path.starts_with(&dir));
+        assert!(!Hdt::invalidate_cache(path, &options)?);
+        snikmeta_check(&Hdt::read_from_path_with_cache(path, &options)?)?;
+        let source = Fingerprint::of(path)?;
+        let (_, dict) = read_cache(&cache_path, &source)?;
+        assert!(dict.is_some());
+        snikmeta_check(&Hdt::read_from_path_with_cache(path, &options)?)?;
+        // a changed HDT file is detected
+        let changed = Fingerprint { checksum: source.checksum ^ 1, ..source };
+        assert!(matches!(read_cache(&cache_path, &changed), Err(CacheError::Stale)));
+        // corrupt the payload
+        let mut bytes = fs::read(&cache_path)?;
+        *bytes.last_mut().unwrap() ^= 1;
+        fs::write(&cache_path, bytes)?;
+        assert!(matches!(read_cache(&cache_path, &source), Err(CacheError::InvalidCrc32Checksum(..))));
+        // the corrupted cache is replaced
+        snikmeta_check(&Hdt::read_from_path_with_cache(path, &options)?)?;
+        read_cache(&cache_path, &source)?;
+        assert!(Hdt::invalidate_cache(path, &options)?);
+        assert!(!cache_path.exists());
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,331 @@
+//! Merging the dictionaries and triples of multiple HDTs without translating the triples to strings.
+//! 1. The terms of each input are streamed in byte order, so that a k-way merge yields all distinct terms in the order of the new dictionary sections.
+//! 2. Each input ID is mapped to the new ID of its term.
+//! 3. The remapped triples of all inputs are sorted and deduplicated into the new triples section.
+//! Only the terms that are used by the included triples are kept, so that the merging also serves to compact subsets of the triples.
+use super::{BLOCK_SIZE, DEFAULT_BASE_IRI, Result};
+use crate::dict_sect_pfc::{DictSectPFC, PfcBuilder};
+use crate::header::Header;
+use crate::mult_sect_dict::{LANG_STRING, NO_DATATYPE};
+use crate::triples::{Id, Order, TripleId, TriplesBitmap};
+use crate::{Dict, Dictionary, FourSectDict, Hdt, IdKind};
+use log::debug;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap};
+use std::ops::Range;
+
+/// Terms of one dictionary and kind in ascending byte order together with their IDs.
+pub(super) type Terms<'a> = Box<dyn Iterator<Item = (Vec<u8>, Id)> + 'a>;
+
+/// All terms of the given kind, including the shared ones for subjects and objects, in ascending byte order.
+pub(super) fn sorted_terms(dict: &Dict, kind: IdKind) -> Terms<'_> {
+    let streams: Vec<Terms> = match dict {
+        Dict::Four(d) | Dict::FourPsfc(d) => {
+            let offset = d.shared.num_strings;
+            let sects = match kind {
+                IdKind::Subject => vec![(&d.shared, 0), (&d.subjects, offset)],
+                IdKind::Predicate => vec![(&d.predicates, 0)],
+                IdKind::Object => vec![(&d.shared, 0), (&d.objects, offset)],
+            };
+            if matches!(dict, Dict::FourPsfc(_)) {
+                sects.into_iter().flat_map(|(sect, offset)| psfc_streams(sect, offset)).collect()
+            } else {
+                sects.into_iter().map(|(sect, offset)| Box::new(sect.iter().zip(offset + 1..)) as Terms).collect()
+            }
+        }
+        Dict::Mult(d) => {
+            let mut offset = d.shared.num_strings;
+            match kind {
+                IdKind::Subject => {
+                    vec![Box::new(d.shared.iter().zip(1..)), Box::new(d.subjects.iter().zip(offset + 1..))]
+                }
+                IdKind::Predicate => vec![Box::new(d.predicates.iter().zip(1..))],
+                IdKind::Object => {
+                    let mut streams: Vec<Terms> = vec![Box::new(d.shared.iter().zip(1..))];
+                    for (datatype, sect) in &d.objects {
+                        // literals are stored without their datatype, which is the same for the whole section
+                        let suffix = if datatype == NO_DATATYPE || datatype == LANG_STRING {
+                            Vec::new()
+                        } else {
+                            format!("^^{datatype}").into_bytes()
+                        };
+                        let terms = sect.iter().map(move |mut term| {
+                            term.extend_from_slice(&suffix);
+                            term
+                        });
+                        streams.push(Box::new(terms.zip(offset + 1..)));
+                        offset += sect.num_strings;
+                    }
+                    streams
+                }
+            }
+        }
+    };
+    // the streams are disjoint
+    Box::new(Merge::new(streams).map(|(term, sources)| (term, sources[0].1)))
+}
+
+/// Splits a section of a PSFC dictionary into streams whose terms are still in byte order after [`pref_to_lit`](crate::dictionary::pref_to_lit),
+/// which are the terms without a prefix and one stream for each language tag or datatype.
+fn psfc_streams(sect: &DictSectPFC, offset: Id) -> Vec<Terms<'_>> {
+    let ids = |ids: Range<Id>| sect.iter_from(ids.start).take(ids.len()).zip(ids.start + offset..);
+    // other terms start with '"', '<' or '_', so the terms prefixed with '@' or "^^" are contiguous
+    let prefixed = sect.prefix_range("@").start..sect.prefix_range("^^").end;
+    let mut streams: Vec<Terms> =
+        vec![Box::new(ids(1..prefixed.start)), Box::new(ids(prefixed.end..sect.num_strings + 1))];
+    let mut start = prefixed.start;
+    while start < prefixed.end {
+        let term = sect.iter_from(start).next().expect("ID is in the section");
+        // like pref_to_lit, moves the prefix before the first quote behind the label
+        let quote = term.iter().position(|&b| b == b'"');
+        let prefix = String::from_utf8_lossy(&term[..quote.map_or(term.len(), |q| q + 1)]);
+        let run = start..sect.prefix_range(&prefix).end.max(start + 1);
+        start = run.end;
+        let rotate = quote.unwrap_or(0);
+        streams.push(Box::new(ids(run).map(move |(mut term, id)| {
+            term.rotate_left(rotate);
+            (term, id)
+        })));
+    }
+    streams
+}
+
+/// K-way merge of term streams that yields each distinct term once, together with the stream indices and IDs it occurs with.
+pub(super) struct Merge<'a> {
+    streams: Vec<Terms<'a>>,
+    heap: BinaryHeap<Reverse<(Vec<u8>, usize, Id)>>,
+}
+
+impl<'a> Merge<'a> {
+    pub(super) fn new(streams: Vec<Terms<'a>>) -> Self {
+        let mut merge = Merge { heap: BinaryHeap::with_capacity(streams.len()), streams };
+        for i in 0..merge.streams.len() {
+            merge.advance(i);
+        }
+        merge
+    }
+
+    fn advance(&mut self, i: usize) {
+        if let Some((term, id)) = self.streams[i].next() {
+            self.heap.push(Reverse((term, i, id)));
+        }
+    }
+}
+
+impl Iterator for Merge<'_> {
+    type Item = (Vec<u8>, Vec<(usize, Id)>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((term, i, id)) = self.heap.pop()?;
+        self.advance(i);
+        let mut sources = vec![(i, id)];
+        while self.heap.peek().is_some_and(|Reverse((t, ..))| *t == term) {
+            let Reverse((_, i, id)) = self.heap.pop().unwrap();
+            self.advance(i);
+            sources.push((i, id));
+        }
+        Some((term, sources))
+    }
+}
+
+/// The triples of an HDT that should be included when merging.
+pub(super) struct Part<'a> {
+    pub(super) hdt: &'a Hdt,
+    /// IDs of the included triples in subject, predicate, object order or `None` to include all of them.
+    pub(super) triples: Option<Vec<TripleId>>,
+}
+
+impl Part<'_> {
+    fn triples(&self) -> Box<dyn Iterator<Item = TripleId> + '_> {
+        match &self.triples {
+            None => Box::new(self.hdt.triples.into_iter()),
+            Some(triples) => Box::new(triples.iter().copied()),
+        }
+    }
+
+    /// Which IDs of each kind occur in the included triples, `None` if all triples are included.
+    fn used(&self) -> Option<[Vec<bool>; 3]> {
+        let triples = self.triples.as_ref()?;
+        let mut used = IdKind::KINDS.map(|k| vec![false; self.hdt.dict.num_terms(k) + 1]);
+        for triple in triples {
+            for (flags, id) in used.iter_mut().zip(triple) {
+                flags[*id] = true;
+            }
+        }
+        Some(used)
+    }
+}
+
+/// Builds a new HDT with SPO order from the included triples of each part and the terms they use.
+pub(super) fn merge(parts: &[Part]) -> Result<Hdt> {
+    // the maps first hold shared IDs as 2*id and subject or object section IDs as 2*id+1,
+    // as the number of shared terms, which they are offset by, is only known at the end
+    let mut streams: Vec<Terms> = Vec::with_capacity(parts.len() * 2);
+    let mut stream_kinds = Vec::with_capacity(parts.len() * 2);
+    for (i, part) in parts.iter().enumerate() {
+        for kind in [IdKind::Subject, IdKind::Object] {
+            streams.push(sorted_terms(&part.hdt.dict, kind));
+            stream_kinds.push((i, kind));
+        }
+    }
+    let used: Vec<_> = parts.iter().map(Part::used).collect();
+    let is_used = |i: usize, kind: IdKind, id: Id| used[i].as_ref().is_none_or(|u| u[kind as usize][id]);
+    let mut maps: Vec<[Vec<Id>; 3]> =
+        parts.iter().map(|part| IdKind::KINDS.map(|k| vec![0; part.hdt.dict.num_terms(k) + 1])).collect();
+    let [mut shared, mut subjects, mut predicates, mut objects] = [(); 4].map(|()| PfcBuilder::new(BLOCK_SIZE));
+    for (term, sources) in Merge::new(streams) {
+        let sources: Vec<_> = sources
+            .into_iter()
+            .map(|(j, id)| (stream_kinds[j], id))
+            .filter(|((i, kind), id)| is_used(*i, *kind, *id))
+            .collect();
+        let is_subject = sources.iter().any(|((_, kind), _)| matches!(kind, IdKind::Subject));
+        let is_object = sources.iter().any(|((_, kind), _)| matches!(kind, IdKind::Object));
+        let new_id = match (is_subject, is_object) {
+            (true, true) => shared.push(&term) << 1,
+            (true, false) => (subjects.push(&term) << 1) | 1,
+            (false, true) => (objects.push(&term) << 1) | 1,
+            (false, false) => continue,
+        };
+        for ((i, kind), old_id) in sources {
+            maps[i][kind as usize][old_id] = new_id;
+        }
+    }
+    let shared = shared.finish();
+    let num_shared = shared.num_strings;
+    for [subject_map, _, object_map] in &mut maps {
+        for id in subject_map.iter_mut().chain(object_map.iter_mut()) {
+            *id = (*id >> 1) + (*id & 1) * num_shared;
+        }
+    }
+
+    let streams = parts.iter().map(|part| sorted_terms(&part.hdt.dict, IdKind::Predicate)).collect();
+    for (term, sources) in Merge::new(streams) {
+        let sources: Vec<_> = sources.into_iter().filter(|(i, id)| is_used(*i, IdKind::Predicate, *id)).collect();
+        if sources.is_empty() {
+            continue;
+        }
+        let new_id = predicates.push(&term);
+        for (i, old_id) in sources {
+            maps[i][IdKind::Predicate as usize][old_id] = new_id;
+        }
+    }
+    let dict = FourSectDict {
+        shared,
+        subjects: subjects.finish(),
+        predicates: predicates.finish(),
+        objects: objects.finish(),
+    };
+
+    let mut triples: Vec<[Id; 3]> = Vec::new();
+    for (part, map) in parts.iter().zip(&maps) {
+        triples.extend(part.triples().map(|[s, p, o]| [map[0][s], map[1][p], map[2][o]]));
+    }
+    triples.sort_unstable();
+    triples.dedup();
+    let num_triples = triples.len();
+    debug!("merged {} HDTs into {num_triples} triples", parts.len());
+    let triples = TriplesBitmap::from_coords(Order::SPO, triples);
+
+    let header = Header { format: "ntriples".to_owned(), length: 0, body: BTreeSet::new() };
+    let mut hdt = Hdt::from_sections(header, dict.into(), triples);
+    hdt.fill_header(DEFAULT_BASE_IRI, None, BLOCK_SIZE, num_triples)?;
+    Ok(hdt)
+}
+
+impl Hdt {
+    /// Merges multiple HDTs into a new one with SPO order, whose triples are the union of all input triples.
+    /// Each input dictionary is streamed only once and the triples are remapped by ID, so that unlike converting the N-Triples dump of each input, no triple is translated to strings.
+    /// Dictionaries other than the FourSectionDictionary are supported but need to be sorted in memory.
+    /// # Example
+    /// ```
+    /// use hdt::Hdt;
+    /// let file = std::fs::File::open("tests/resources/snikmeta.hdt").unwrap();
+    /// let hdt = Hdt::read(std::io::BufReader::new(file)).unwrap();
+    /// let merged = Hdt::cat(&[&hdt, &hdt]).unwrap();
+    /// assert_eq!(merged.triples.len(), hdt.triples.len());
+    /// ```
+    pub fn cat(hdts: &[&Hdt]) -> Result<Self> {
+        merge(&hdts.iter().map(|&hdt| Part { hdt, triples: None }).collect::<Vec<_>>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hdt::tests::{snikmeta, snikmeta_check};
+    use crate::tests::init;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn cat() -> color_eyre::Result<()> {
+        init();
+        let snikmeta = snikmeta()?;
+        let triples: Vec<_> = snikmeta.triples_all().collect();
+        for hdts in [vec![&snikmeta], vec![&snikmeta, &snikmeta]] {
+            let merged = Hdt::cat(&hdts)?;
+            snikmeta_check(&merged)?;
+            assert_eq!(merged.triples_all().collect::<Vec<_>>(), triples);
+        }
+        // overlapping parts, so that some terms only become shared when merged
+        #[cfg(feature = "nt")]
+        {
+            let n = triples.len();
+            let first = Hdt::from_triples(triples[..n * 2 / 3].iter().cloned())?;
+            let mut builder = crate::HdtBuilder::new().with_order(Order::OPS);
+            builder.extend(triples[n / 3..].iter().cloned());
+            let second = builder.build()?;
+            let merged = Hdt::cat(&[&second, &first])?;
+            snikmeta_check(&merged)?;
+            assert_eq!(merged.triples_all().collect::<Vec<_>>(), triples);
+        }
+        assert_eq!(Hdt::cat(&[])?.triples.len(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn sorted_terms_psfc_mult() -> color_eyre::Result<()> {
+        use crate::dictionary::lit_to_pref;
+        use crate::mult_sect_dict::{MultSectDict, split_type};
+        use std::collections::BTreeMap;
+
+        init();
+        let snikmeta = snikmeta()?;
+        let Dict::Four(four) = &snikmeta.dict else { panic!("snikmeta should have a four section dictionary") };
+        let strings = |sect: &DictSectPFC| -> Vec<String> {
+            (1..=sect.num_strings).map(|i| sect.extract(i).unwrap()).collect()
+        };
+        let compress =
+            |strings: &[String]| DictSectPFC::compress(&strings.iter().map(String::as_str).collect(), 16);
+        let prefixed = |sect: &DictSectPFC| {
+            compress(&strings(sect).iter().map(|s| lit_to_pref(s).into_owned()).collect::<Vec<_>>())
+        };
+        let psfc = Dict::FourPsfc(FourSectDict {
+            shared: prefixed(&four.shared),
+            subjects: prefixed(&four.subjects),
+            predicates: prefixed(&four.predicates),
+            objects: prefixed(&four.objects),
+        });
+        let mut by_type = BTreeMap::<String, Vec<String>>::new();
+        for s in strings(&four.objects) {
+            let (datatype, key) = split_type(&s);
+            by_type.entry(datatype.to_owned()).or_default().push(key.to_owned());
+        }
+        let mult = Dict::Mult(MultSectDict {
+            shared: compress(&strings(&four.shared)),
+            subjects: compress(&strings(&four.subjects)),
+            predicates: compress(&strings(&four.predicates)),
+            objects: by_type.into_iter().map(|(datatype, keys)| (datatype, compress(&keys))).collect(),
+        });
+        for dict in [&psfc, &mult] {
+            for kind in IdKind::KINDS {
+                let mut expected: Vec<_> = (1..=dict.num_terms(kind))
+                    .map(|id| (dict.id_to_string(id, kind).unwrap().into_bytes(), id))
+                    .collect();
+                expected.sort_unstable();
+                assert_eq!(sorted_terms(dict, kind).collect::<Vec<_>>(), expected, "{kind:?}");
+            }
+        }
+        Ok(())
+    }
+}
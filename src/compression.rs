@@ -0,0 +1,181 @@
+//! Transparent decompression of HDT and RDF files that are compressed with gzip, bzip2, Zstandard or xz.
+//! The compression is detected by the magic bytes at the start of the data, not by the file extension.
+//! Decompression of each format is only available if HDT is built with the corresponding feature
+//! `"gzip"`, `"bzip2"`, `"zstd"` or `"xz"`, which are all enabled by the `"compression"` feature.
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+/// Compression formats that are detected by their magic bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Uncompressed data.
+    None,
+    /// <https://www.rfc-editor.org/rfc/rfc1952>, usually with the extension `.gz`.
+    Gzip,
+    /// <https://sourceware.org/bzip2/>, usually with the extension `.bz2`.
+    Bzip2,
+    /// <https://www.rfc-editor.org/rfc/rfc8878>, usually with the extension `.zst`.
+    Zstd,
+    /// <https://tukaani.org/xz/format.html>, usually with the extension `.xz`.
+    Xz,
+}
+
+impl Compression {
+    /// Compression formats with their magic bytes.
+    const MAGIC: [(Compression, &'static [u8]); 4] = [
+        (Compression::Gzip, &[0x1f, 0x8b]),
+        (Compression::Bzip2, b"BZh"),
+        (Compression::Zstd, &[0x28, 0xb5, 0x2f, 0xfd]),
+        (Compression::Xz, &[0xfd, b'7', b'z', b'X', b'Z', 0x00]),
+    ];
+
+    /// Detects the compression format from the first bytes of the data.
+    pub fn detect(start: &[u8]) -> Self {
+        Self::MAGIC.iter().find(|(_, magic)| start.starts_with(magic)).map_or(Compression::None, |(c, _)| *c)
+    }
+
+    /// The file extension commonly used for the compression format, without leading dot.
+    pub const fn extension(self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some("gz"),
+            Compression::Bzip2 => Some("bz2"),
+            Compression::Zstd => Some("zst"),
+            Compression::Xz => Some("xz"),
+        }
+    }
+
+    /// The feature that HDT must be built with to decompress the format.
+    const fn feature(self) -> &'static str {
+        match self {
+            Compression::None => "",
+            Compression::Gzip => "gzip",
+            Compression::Bzip2 => "bzip2",
+            Compression::Zstd => "zstd",
+            Compression::Xz => "xz",
+        }
+    }
+}
+
+/// Returns a reader that decompresses the data of the given reader on the fly if it starts with the magic bytes
+/// of a supported compression format and otherwise returns the data unchanged.
+/// Concatenated streams, as produced by parallel compressors like pigz and pbzip2, are decompressed completely.
+/// Fails if the data is compressed in a format whose feature is not enabled.
+/// # Example
+/// ```
+/// let file = std::fs::File::open("tests/resources/snikmeta.hdt").expect("error opening file");
+/// let reader = hdt::compression::decompress(std::io::BufReader::new(file)).unwrap();
+/// let hdt = hdt::Hdt::read(reader).unwrap();
+/// ```
+pub fn decompress<'a, R: BufRead + 'a>(mut reader: R) -> io::Result<Box<dyn BufRead + 'a>> {
+    let compression = Compression::detect(reader.fill_buf()?);
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(reader))),
+        #[cfg(feature = "bzip2")]
+        Compression::Bzip2 => Box::new(BufReader::new(bzip2::bufread::MultiBzDecoder::new(reader))),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Box::new(BufReader::new(zstd::stream::read::Decoder::with_buffer(reader)?)),
+        #[cfg(feature = "xz")]
+        Compression::Xz => Box::new(BufReader::new(xz2::bufread::XzDecoder::new_multi_decoder(reader))),
+        #[allow(unreachable_patterns)]
+        c => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{c:?} compressed input requires HDT to be built with the \"{}\" feature", c.feature()),
+            ));
+        }
+    })
+}
+
+/// Opens the file at the given path and decompresses it on the fly if necessary, see [`decompress`].
+pub fn open(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    decompress(BufReader::new(File::open(path)?))
+}
+
+/// Detects the compression format of the file at the given path from its magic bytes.
+pub fn detect_file(path: &Path) -> io::Result<Compression> {
+    Ok(Compression::detect(BufReader::new(File::open(path)?).fill_buf()?))
+}
+
+/// Removes the extension of a compression format from the path, if there is one,
+/// so that the format of the compressed data can be determined, e.g. `data.nt.gz` becomes `data.nt`.
+pub fn strip_extension(path: &Path) -> PathBuf {
+    let compressed = path.extension().and_then(|e| e.to_str()).is_some_and(|e| {
+        Compression::MAGIC.iter().any(|(c, _)| c.extension().is_some_and(|c| c.eq_ignore_ascii_case(e)))
+    });
+    if compressed { path.with_extension("") } else { path.to_path_buf() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Hdt;
+    use crate::hdt::tests::snikmeta_check;
+    use crate::tests::init;
+    use color_eyre::Result;
+    use pretty_assertions::assert_eq;
+    #[cfg(any(feature = "gzip", feature = "bzip2", feature = "xz"))]
+    use std::io::Write;
+
+    #[test]
+    fn detect() {
+        assert_eq!(Compression::detect(b"$HDT\x01"), Compression::None);
+        assert_eq!(Compression::detect(b""), Compression::None);
+        assert_eq!(Compression::detect(&[0x1f, 0x8b, 0x08]), Compression::Gzip);
+        assert_eq!(Compression::detect(b"BZh91AY"), Compression::Bzip2);
+        assert_eq!(Compression::detect(&[0x28, 0xb5, 0x2f, 0xfd, 0]), Compression::Zstd);
+        assert_eq!(Compression::detect(&[0xfd, b'7', b'z', b'X', b'Z', 0, 0]), Compression::Xz);
+        assert_eq!(strip_extension(Path::new("data.nt.gz")), Path::new("data.nt"));
+        assert_eq!(strip_extension(Path::new("dir/data.hdt.BZ2")), Path::new("dir/data.hdt"));
+        assert_eq!(strip_extension(Path::new("data.hdt")), Path::new("data.hdt"));
+    }
+
+    #[test]
+    fn read_compressed() -> Result<()> {
+        init();
+        let data = std::fs::read("tests/resources/snikmeta.hdt")?;
+        snikmeta_check(&Hdt::read(decompress(data.as_slice())?)?)?;
+        #[cfg(feature = "gzip")]
+        {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&data)?;
+            snikmeta_check(&Hdt::read(decompress(encoder.finish()?.as_slice())?)?)?;
+        }
+        #[cfg(feature = "bzip2")]
+        {
+            let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+            encoder.write_all(&data)?;
+            snikmeta_check(&Hdt::read(decompress(encoder.finish()?.as_slice())?)?)?;
+        }
+        #[cfg(feature = "zstd")]
+        snikmeta_check(&Hdt::read(decompress(zstd::encode_all(data.as_slice(), 0)?.as_slice())?)?)?;
+        #[cfg(feature = "xz")]
+        {
+            let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+            encoder.write_all(&data)?;
+            snikmeta_check(&Hdt::read(decompress(encoder.finish()?.as_slice())?)?)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn index_of_compressed() -> Result<()> {
+        init();
+        let dir = std::env::temp_dir().join(format!("hdt-compressed-index-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("snikmeta.hdt.gz");
+        let mut encoder = flate2::write::GzEncoder::new(File::create(&path)?, flate2::Compression::default());
+        encoder.write_all(&std::fs::read("tests/resources/snikmeta.hdt")?)?;
+        encoder.finish()?;
+        snikmeta_check(&Hdt::read_from_path_with_index(&path)?)?;
+        assert!(dir.join("snikmeta.hdt.index.v1-1").exists());
+        // reads the index written by the first call
+        snikmeta_check(&Hdt::read_from_path_with_index(&path)?)?;
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
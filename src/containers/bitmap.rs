@@ -1,5 +1,6 @@
 //! Bitmap with rank and select support read from an HDT file.
 use crate::containers::vbyte::{encode_vbyte, read_vbyte};
+use crate::containers::{Bytes, BytesReader};
 use bytesize::ByteSize;
 use qwt::mem_dbg::{MemSize, SizeFlags};
 use qwt::{AccessBin, BitVector, BitVectorMut, RankBin, SelectBin, bitvector::rs_narrow::RSNarrow};
@@ -9,12 +10,174 @@ use std::fmt;
 use std::io::BufRead;
 use std::mem::size_of;
 
+/// Number of words per block of the rank directory of [`MappedBits`].
+const BLOCK_WORDS: usize = 8;
+
 /// Compact bitmap representation with rank and select support.
 #[derive(Clone)]
 #[cfg_attr(feature = "cache", derive(Serialize, Deserialize))]
 pub struct Bitmap {
     /// should be private but is needed by containers/bitmap.rs, use methods provided by Bitmap
-    pub dict: RSNarrow,
+    pub dict: BitmapData,
+}
+
+/// Storage of the bits of a [`Bitmap`].
+#[derive(Clone)]
+#[cfg_attr(feature = "cache", derive(Deserialize, Serialize), serde(from = "RSNarrow", into = "RSNarrow"))]
+pub enum BitmapData {
+    /// Bits with rank and select support on the heap.
+    Owned(RSNarrow),
+    /// Bits borrowed from a memory map, only the rank directory is on the heap.
+    Mapped(MappedBits),
+}
+
+/// Little endian bytes in HDT serialization, where the last word may be truncated, e.g. borrowed from a memory map,
+/// together with the number of one bits before each block of 512 bits for rank and select support.
+#[derive(Clone)]
+pub struct MappedBits {
+    bytes: Bytes,
+    /// Number of bits, the remaining bits of the last word are unused.
+    num_bits: usize,
+    /// The last word padded with zeroes if it is truncated, otherwise 0.
+    last: u64,
+    /// Number of one bits before each block, followed by the total number of one bits.
+    ranks: Vec<usize>,
+}
+
+impl MappedBits {
+    /// Builds the rank directory over the given bytes, which contain the given number of bits.
+    fn new(bytes: Bytes, num_bits: usize) -> Self {
+        // the truncated last word is padded once here, so that reading a word needs no padding
+        let tail = &bytes[bytes.len() - bytes.len() % size_of::<u64>()..];
+        let mut last = [0u8; size_of::<u64>()];
+        last[..tail.len()].copy_from_slice(tail);
+        let last = u64::from_le_bytes(last);
+        let mut mapped = MappedBits { bytes, num_bits, last, ranks: Vec::new() };
+        let num_words = mapped.num_words();
+        let mut ranks = Vec::with_capacity(num_words.div_ceil(BLOCK_WORDS) + 1);
+        let mut ones = 0;
+        for i in 0..num_words {
+            if i % BLOCK_WORDS == 0 {
+                ranks.push(ones);
+            }
+            ones += mapped.word(i).count_ones() as usize;
+        }
+        ranks.push(ones);
+        mapped.ranks = ranks;
+        mapped
+    }
+
+    fn num_words(&self) -> usize {
+        self.bytes.len().div_ceil(size_of::<u64>())
+    }
+
+    /// Get the word at the given index, counting from 0.
+    /// Panics if the index is out of bounds.
+    #[inline]
+    fn word(&self, index: usize) -> u64 {
+        let start = index * size_of::<u64>();
+        match self.bytes.get(start..start + size_of::<u64>()) {
+            Some(word) => u64::from_le_bytes(<[u8; 8]>::try_from(word).unwrap()),
+            None => {
+                assert!(index < self.num_words(), "word index {index} out of bounds");
+                self.last
+            }
+        }
+    }
+
+    fn num_ones(&self) -> usize {
+        self.ranks.last().copied().unwrap_or(0)
+    }
+
+    fn get(&self, pos: usize) -> Option<bool> {
+        (pos < self.num_bits).then(|| (self.word(pos / 64) >> (pos % 64)) & 1 == 1)
+    }
+
+    fn rank1(&self, pos: usize) -> Option<usize> {
+        if pos > self.num_words() * 64 {
+            return None;
+        }
+        let w = pos / 64;
+        let block = w / BLOCK_WORDS;
+        let mut rank = self.ranks[block];
+        rank += (block * BLOCK_WORDS..w).map(|i| self.word(i).count_ones() as usize).sum::<usize>();
+        if pos % 64 > 0 {
+            rank += (self.word(w) & ((1 << (pos % 64)) - 1)).count_ones() as usize;
+        }
+        Some(rank)
+    }
+
+    fn select1(&self, k: usize) -> Option<usize> {
+        if k >= self.num_ones() {
+            return None;
+        }
+        // last block with at most k one bits before it
+        let block = self.ranks.partition_point(|&r| r <= k) - 1;
+        let mut remaining = k - self.ranks[block];
+        for i in block * BLOCK_WORDS..self.num_words() {
+            let mut word = self.word(i);
+            let ones = word.count_ones() as usize;
+            if remaining < ones {
+                for _ in 0..remaining {
+                    word &= word - 1;
+                }
+                return Some(i * 64 + word.trailing_zeros() as usize);
+            }
+            remaining -= ones;
+        }
+        None
+    }
+}
+
+impl BitmapData {
+    /// Number of 64 bit words.
+    fn num_words(&self) -> usize {
+        match self {
+            BitmapData::Owned(rs) => rs.bit_vector().words().len(),
+            BitmapData::Mapped(m) => m.num_words(),
+        }
+    }
+
+    /// Get the word at the given index, counting from 0.
+    /// Panics if the index is out of bounds.
+    fn word(&self, index: usize) -> u64 {
+        match self {
+            BitmapData::Owned(rs) => rs.bit_vector().words()[index],
+            BitmapData::Mapped(m) => m.word(index),
+        }
+    }
+}
+
+impl From<RSNarrow> for BitmapData {
+    fn from(rs: RSNarrow) -> Self {
+        BitmapData::Owned(rs)
+    }
+}
+
+impl From<BitmapData> for RSNarrow {
+    fn from(data: BitmapData) -> Self {
+        match data {
+            BitmapData::Owned(rs) => rs,
+            BitmapData::Mapped(_) => {
+                let words: Vec<u64> = (0..data.num_words()).map(|i| data.word(i)).collect();
+                let bv: BitVector = BitVectorMut::from_packed_data(&words, words.len() * 64).into();
+                bv.into()
+            }
+        }
+    }
+}
+
+impl PartialEq for BitmapData {
+    fn eq(&self, other: &Self) -> bool {
+        self.num_words() == other.num_words() && (0..self.num_words()).all(|i| self.word(i) == other.word(i))
+    }
+}
+
+impl fmt::Debug for BitmapData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mapped = if matches!(self, BitmapData::Mapped(_)) { " mapped" } else { "" };
+        write!(f, "{} words{mapped}", self.num_words())
+    }
 }
 
 pub type Result<T> = core::result::Result<T, Error>;
@@ -36,19 +199,20 @@ pub enum Error {
 
 impl fmt::Debug for Bitmap {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}, {} bits", ByteSize(self.size_in_bytes() as u64), self.len())
+        let mapped = if self.is_mapped() { " mapped" } else { "" };
+        write!(f, "{}, {} bits{mapped}", ByteSize(self.size_in_bytes() as u64), self.len())
     }
 }
 
 impl From<BitVector> for Bitmap {
     fn from(bv: BitVector) -> Self {
-        Bitmap { dict: bv.into() }
+        Bitmap { dict: BitmapData::Owned(bv.into()) }
     }
 }
 
 impl From<BitVectorMut> for Bitmap {
     fn from(bv: BitVectorMut) -> Self {
-        Bitmap { dict: <BitVectorMut as Into<BitVector>>::into(bv).into() }
+        Bitmap { dict: BitmapData::Owned(<BitVectorMut as Into<BitVector>>::into(bv).into()) }
     }
 }
 
@@ -56,42 +220,66 @@ impl Bitmap {
     /// Construct a bitmap from an existing bitmap in form of a vector, which doesn't have rank and select support. Number of bits multiple of 64.
     pub fn new(data: &[u64]) -> Self {
         let v: BitVector = BitVectorMut::from_packed_data(data, data.len() * 64).into();
-        Bitmap { dict: v.into() }
+        Bitmap { dict: BitmapData::Owned(v.into()) }
+    }
+
+    /// Whether the bits are borrowed from a memory map instead of being stored on the heap.
+    pub const fn is_mapped(&self) -> bool {
+        matches!(self.dict, BitmapData::Mapped(_))
     }
 
     /// Size in bytes on the heap.
     pub fn size_in_bytes(&self) -> usize {
-        self.dict.mem_size(SizeFlags::default())
+        match &self.dict {
+            BitmapData::Owned(rs) => rs.mem_size(SizeFlags::default()),
+            BitmapData::Mapped(m) => m.bytes.size_in_bytes() + m.ranks.len() * size_of::<usize>(),
+        }
     }
 
     /// Number of bits in the bitmap, multiple of 64
     pub fn len(&self) -> usize {
-        self.dict.n_zeros() + self.dict.n_ones() // RSNarrow.len() is not public
-        // self.dict.bv_len() // only on RSWide
+        match &self.dict {
+            BitmapData::Owned(rs) => rs.n_zeros() + rs.n_ones(), // RSNarrow.len() is not public
+            BitmapData::Mapped(m) => m.num_words() * 64,
+        }
     }
 
     /// Number of bits set
     pub fn num_ones(&self) -> usize {
-        self.dict.n_ones()
+        match &self.dict {
+            BitmapData::Owned(rs) => rs.n_ones(),
+            BitmapData::Mapped(m) => m.num_ones(),
+        }
     }
 
     /// Returns the position of the k-1-th one bit or None if there aren't that many.
     pub fn select1(&self, k: usize) -> Option<usize> {
-        self.dict.select1(k)
+        match &self.dict {
+            BitmapData::Owned(rs) => rs.select1(k),
+            BitmapData::Mapped(m) => m.select1(k),
+        }
     }
 
     /// Returns the number of one bits from the 0-th bit to the k-1-th bit. Panics if self.len() < pos.
     pub fn rank(&self, k: usize) -> usize {
-        self.dict.rank1(k).unwrap_or_else(|| panic!("Out of bounds position: {} >= {}", k, self.len()))
+        let rank = match &self.dict {
+            BitmapData::Owned(rs) => rs.rank1(k),
+            BitmapData::Mapped(m) => m.rank1(k),
+        };
+        rank.unwrap_or_else(|| panic!("Out of bounds position: {} >= {}", k, self.len()))
     }
 
     /// Whether the node given position is the last child of its parent.
     pub fn at_last_sibling(&self, word_index: usize) -> bool {
-        self.dict.get(word_index).expect("word index out of bounds")
+        let bit = match &self.dict {
+            BitmapData::Owned(rs) => rs.get(word_index),
+            BitmapData::Mapped(m) => m.get(word_index),
+        };
+        bit.expect("word index out of bounds")
     }
 
-    /// Read bitmap from a suitable point within HDT file data and verify checksums.
-    pub fn read<R: BufRead>(reader: &mut R) -> Result<Self> {
+    /// Read the bitmap metadata and validate its CRC8, returns the number of bits.
+    fn read_meta<R: BufRead>(reader: &mut R) -> Result<usize> {
         use Error::*;
         let mut history: Vec<u8> = Vec::with_capacity(5);
 
@@ -120,6 +308,25 @@ impl Bitmap {
         if crc_calculated != crc_code {
             return Err(InvalidCrc8Checksum(crc_calculated, crc_code));
         }
+        Ok(num_bits)
+    }
+
+    /// Read the body CRC32 and validate it against the given body bytes.
+    fn verify_crc32<R: BufRead>(reader: &mut R, body: &[u8]) -> Result<()> {
+        let mut crc_code = [0_u8; 4];
+        reader.read_exact(&mut crc_code)?;
+        let crc_code = u32::from_le_bytes(crc_code);
+        let crc_calculated = crc::Crc::<u32>::new(&crc::CRC_32_ISCSI).checksum(body);
+        if crc_calculated != crc_code {
+            return Err(Error::InvalidCrc32Checksum(crc_calculated, crc_code));
+        }
+        Ok(())
+    }
+
+    /// Read bitmap from a suitable point within HDT file data and verify checksums.
+    pub fn read<R: BufRead>(reader: &mut R) -> Result<Self> {
+        use Error::*;
+        let num_bits = Self::read_meta(reader)?;
 
         // read all but the last word, last word is byte aligned
         let full_byte_amount = ((num_bits - 1) >> 6) * 8;
@@ -164,6 +371,15 @@ impl Bitmap {
         Ok(Self::new(&data))
     }
 
+    /// Read bitmap from HDT data that is already in memory, such as a memory-mapped file, and verify checksums.
+    /// The bits are not copied but borrowed from the underlying bytes, only a rank directory of 1/8 of their size is built.
+    pub fn read_mapped(reader: &mut BytesReader) -> Result<Self> {
+        let num_bits = Self::read_meta(reader)?;
+        let body = reader.take(num_bits.div_ceil(8))?;
+        Self::verify_crc32(reader, &body)?;
+        Ok(Bitmap { dict: BitmapData::Mapped(MappedBits::new(body, num_bits)) })
+    }
+
     pub fn write(&self, w: &mut impl std::io::Write) -> Result<()> {
         let crc = crc::Crc::<u8>::new(&crc::CRC_8_SMBUS);
         let mut hasher = crc.digest();
@@ -183,14 +399,25 @@ impl Bitmap {
         let crc32 = crc::Crc::<u32>::new(&crc::CRC_32_ISCSI);
         let mut hasher = crc32.digest();
 
-        let words: &[u64] = self.dict.bit_vector().words();
-        let num_bytes = self.dict.bit_vector().len().div_ceil(8); // HDT spec expects no superflous bytes to be written
-        let bytes = unsafe {
-            std::slice::from_raw_parts(words.as_ptr().cast::<u8>(), num_bytes) // assume little endian
-        };
-
-        w.write_all(bytes)?;
-        hasher.update(bytes);
+        match &self.dict {
+            BitmapData::Owned(rs) => {
+                let words: &[u64] = rs.bit_vector().words();
+                let num_bytes = rs.bit_vector().len().div_ceil(8); // HDT spec expects no superflous bytes to be written
+                let bytes = unsafe {
+                    std::slice::from_raw_parts(words.as_ptr().cast::<u8>(), num_bytes) // assume little endian
+                };
+                w.write_all(bytes)?;
+                hasher.update(bytes);
+            }
+            BitmapData::Mapped(m) => {
+                // pad the truncated last word like the owned bits, whose length is a multiple of 64
+                let padding = vec![0u8; m.num_words() * size_of::<u64>() - m.bytes.len()];
+                for bytes in [&m.bytes[..], &padding] {
+                    w.write_all(bytes)?;
+                    hasher.update(bytes);
+                }
+            }
+        }
         let crc_code = hasher.finalize();
         let crc_code = crc_code.to_le_bytes();
         w.write_all(&crc_code)?;
@@ -237,4 +464,51 @@ mod tests {
         assert_eq!(bitmap.dict, bitmap2.dict);
         Ok(())
     }
+
+    #[test]
+    fn mapped() -> color_eyre::Result<()> {
+        init();
+        // multiple rank directory blocks, some without one bits
+        let mut words = vec![0u64; 20];
+        words[0] = 0b1011;
+        words[9] = u64::MAX;
+        words[17] = 1 << 63;
+        words[19] = 0b101;
+        let owned = Bitmap::new(&words);
+        let mut buf = Vec::<u8>::new();
+        owned.write(&mut buf)?;
+        let mapped = Bitmap::read_mapped(&mut BytesReader::new(Bytes::from(buf.clone())))?;
+        assert!(mapped.is_mapped());
+        assert_eq!(mapped.len(), owned.len());
+        assert_eq!(mapped.num_ones(), owned.num_ones());
+        for pos in 0..owned.len() {
+            assert_eq!(mapped.rank(pos), owned.rank(pos), "rank({pos})");
+        }
+        for pos in 0..owned.len() {
+            assert_eq!(mapped.at_last_sibling(pos), owned.at_last_sibling(pos), "{pos}");
+        }
+        for k in 0..=owned.num_ones() {
+            assert_eq!(mapped.select1(k), owned.select1(k), "select1({k})");
+        }
+        assert_eq!(mapped.dict, owned.dict);
+        let mut buf2 = Vec::<u8>::new();
+        mapped.write(&mut buf2)?;
+        assert_eq!(buf, buf2);
+        // 70 bits, whose truncated last word is padded and whose bits after the length are out of bounds
+        let mut truncated = vec![1];
+        truncated.extend_from_slice(&encode_vbyte(70));
+        truncated.push(crc::Crc::<u8>::new(&crc::CRC_8_SMBUS).checksum(&truncated));
+        let body = [0xff, 0, 0, 0, 0, 0, 0, 0, 0b10_0001];
+        truncated.extend_from_slice(&body);
+        truncated.extend_from_slice(&crc::Crc::<u32>::new(&crc::CRC_32_ISCSI).checksum(&body).to_le_bytes());
+        let mapped = Bitmap::read_mapped(&mut BytesReader::new(Bytes::from(truncated.clone())))?;
+        assert_eq!(mapped.dict, Bitmap::read(&mut truncated.as_slice())?.dict);
+        assert_eq!(mapped.num_ones(), 10);
+        assert_eq!(mapped.rank(70), 10);
+        assert_eq!(mapped.select1(9), Some(69));
+        assert!(mapped.at_last_sibling(69));
+        let BitmapData::Mapped(bits) = &mapped.dict else { unreachable!() };
+        assert_eq!(bits.get(70), None);
+        Ok(())
+    }
 }
@@ -0,0 +1,166 @@
+//! Immutable byte buffers that are either owned or borrowed from a memory-mapped HDT file.
+use bytesize::ByteSize;
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+use std::fmt;
+use std::io::{self, BufRead, Read};
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// Reference counted, read-only bytes.
+/// Cloning and slicing never copy the underlying data, so sections of a memory-mapped file can be shared
+/// between the containers that were read from it.
+#[derive(Clone)]
+pub struct Bytes {
+    backing: Backing,
+    start: usize,
+    end: usize,
+}
+
+#[derive(Clone)]
+enum Backing {
+    Heap(Arc<[u8]>),
+    #[cfg(feature = "mmap")]
+    Mmap(Arc<Mmap>),
+}
+
+impl Bytes {
+    /// Returns a view of the given subrange without copying.
+    /// Panics if the range is out of bounds.
+    pub fn slice(&self, range: std::ops::Range<usize>) -> Self {
+        assert!(range.start <= range.end && range.end <= self.len(), "range {range:?} out of bounds");
+        Bytes { backing: self.backing.clone(), start: self.start + range.start, end: self.start + range.end }
+    }
+
+    /// Whether the bytes are borrowed from a memory map instead of being stored on the heap.
+    pub const fn is_mapped(&self) -> bool {
+        match self.backing {
+            Backing::Heap(_) => false,
+            #[cfg(feature = "mmap")]
+            Backing::Mmap(_) => true,
+        }
+    }
+
+    /// Size in bytes on the heap, which is zero for memory-mapped data.
+    pub fn size_in_bytes(&self) -> usize {
+        if self.is_mapped() { 0 } else { self.len() }
+    }
+}
+
+impl Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.backing {
+            Backing::Heap(data) => &data[self.start..self.end],
+            #[cfg(feature = "mmap")]
+            Backing::Mmap(mmap) => &mmap[self.start..self.end],
+        }
+    }
+}
+
+impl From<Arc<[u8]>> for Bytes {
+    fn from(data: Arc<[u8]>) -> Self {
+        let end = data.len();
+        Bytes { backing: Backing::Heap(data), start: 0, end }
+    }
+}
+
+impl From<Vec<u8>> for Bytes {
+    fn from(data: Vec<u8>) -> Self {
+        Arc::<[u8]>::from(data).into()
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl From<Mmap> for Bytes {
+    fn from(mmap: Mmap) -> Self {
+        let end = mmap.len();
+        Bytes { backing: Backing::Mmap(Arc::new(mmap)), start: 0, end }
+    }
+}
+
+impl PartialEq for Bytes {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl fmt::Debug for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", ByteSize(self.len() as u64), if self.is_mapped() { " mapped" } else { "" })
+    }
+}
+
+/// Buffered reader over [`Bytes`] that can split off parts of the data without copying them.
+pub struct BytesReader {
+    bytes: Bytes,
+    pos: usize,
+}
+
+impl BytesReader {
+    pub const fn new(bytes: Bytes) -> Self {
+        BytesReader { bytes, pos: 0 }
+    }
+
+    /// Number of bytes consumed so far.
+    pub const fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Returns the next `len` bytes as a view into the underlying data and advances past them.
+    pub fn take(&mut self, len: usize) -> io::Result<Bytes> {
+        if self.pos + len > self.bytes.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "cannot take {len} bytes at position {}, only {} bytes available",
+                    self.pos,
+                    self.bytes.len()
+                ),
+            ));
+        }
+        let taken = self.bytes.slice(self.pos..self.pos + len);
+        self.pos += len;
+        Ok(taken)
+    }
+}
+
+impl Read for BytesReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = (&self.bytes[self.pos..]).read(buf)?;
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl BufRead for BytesReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(&self.bytes[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.bytes.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn reader_take() -> color_eyre::Result<()> {
+        let mut reader = BytesReader::new(Bytes::from(vec![1u8, 2, 3, 4, 5, 6]));
+        let mut first = [0u8; 2];
+        reader.read_exact(&mut first)?;
+        assert_eq!(first, [1, 2]);
+        let taken = reader.take(3)?;
+        assert_eq!(&taken[..], &[3, 4, 5]);
+        assert_eq!(&taken.slice(1..3)[..], &[4, 5]);
+        assert_eq!(reader.position(), 5);
+        assert!(reader.take(2).is_err());
+        assert_eq!(reader.fill_buf()?, &[6]);
+        Ok(())
+    }
+}
@@ -72,8 +72,12 @@ pub enum ControlInfoReadErrorKind {
 }
 
 const HDT_CONTAINER: &str = "<http://purl.org/HDT/hdt#HDTv1>";
-const DICTIONARY_FOUR: &str = "<http://purl.org/HDT/hdt#dictionaryFour>";
+pub(crate) const DICTIONARY_FOUR: &str = "<http://purl.org/HDT/hdt#dictionaryFour>";
+pub(crate) const DICTIONARY_FOUR_PSFC: &str = "<http://purl.org/HDT/hdt#dictionaryFourPsfc>";
+pub(crate) const DICTIONARY_MULT: &str = "<http://purl.org/HDT/hdt#dictionaryMult>";
 const TRIPLES_BITMAP: &str = "<http://purl.org/HDT/hdt#triplesBitmap>";
+const TRIPLES_LIST: &str = "<http://purl.org/HDT/hdt#triplesList>";
+pub(crate) const INDEX_FOQ: &str = "<http://purl.org/HDT/hdt#indexFoQ>";
 
 impl ControlInfo {
     /// Create global control information for the start of the HDT file
@@ -99,12 +103,45 @@ impl ControlInfo {
         }
     }
 
+    /// Create control information for the four section dictionary with prefixed literals
+    pub fn four_psfc_sect_dict() -> ControlInfo {
+        ControlInfo {
+            control_type: ControlType::Dictionary,
+            format: DICTIONARY_FOUR_PSFC.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    /// Create control information for the multiple section dictionary
+    pub fn mult_sect_dict() -> ControlInfo {
+        ControlInfo {
+            control_type: ControlType::Dictionary,
+            format: DICTIONARY_MULT.to_owned(),
+            ..Default::default()
+        }
+    }
+
     /// Create control information for BitmapTriples
-    pub fn bitmap_triples(order: u32, num_triples: u32) -> ControlInfo {
+    pub fn bitmap_triples(order: u32, num_triples: usize) -> ControlInfo {
+        Self::triples_info(ControlType::Triples, TRIPLES_BITMAP, order, num_triples)
+    }
+
+    /// Create control information for TriplesList
+    pub fn list_triples(order: u32, num_triples: usize) -> ControlInfo {
+        Self::triples_info(ControlType::Triples, TRIPLES_LIST, order, num_triples)
+    }
+
+    /// Create control information for the object index file of hdt-cpp and hdt-java
+    pub fn object_index(order: u32, num_triples: usize) -> ControlInfo {
+        Self::triples_info(ControlType::Index, INDEX_FOQ, order, num_triples)
+    }
+
+    /// Control information with the order and the number of triples as properties.
+    fn triples_info(control_type: ControlType, format: &str, order: u32, num_triples: usize) -> ControlInfo {
         let mut properties = HashMap::<String, String>::new();
         properties.insert("order".to_owned(), order.to_string());
         properties.insert("numTriples".to_owned(), num_triples.to_string());
-        ControlInfo { control_type: ControlType::Triples, format: TRIPLES_BITMAP.to_owned(), properties }
+        ControlInfo { control_type, format: format.to_owned(), properties }
     }
 
     /// Read and verify control information.
@@ -7,6 +7,7 @@ pub mod vbyte;
 // byte containers
 mod adj_list;
 pub mod bitmap;
+pub mod bytes;
 pub mod sequence;
 
 // control info section reader
@@ -14,5 +15,6 @@ pub mod control_info;
 
 pub use adj_list::AdjList;
 pub use bitmap::Bitmap;
+pub use bytes::{Bytes, BytesReader};
 pub use control_info::{ControlInfo, ControlType};
 pub use sequence::Sequence;
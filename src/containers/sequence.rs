@@ -1,5 +1,6 @@
 use super::vbyte::encode_vbyte;
 use crate::containers::vbyte::read_vbyte;
+use crate::containers::{Bytes, BytesReader};
 use bytesize::ByteSize;
 #[cfg(feature = "cache")]
 use serde::{self, Deserialize, Serialize};
@@ -28,7 +29,80 @@ pub struct Sequence {
     /// Number of bits that each integer uses.
     pub bits_per_entry: usize,
     /// Data in blocks.
-    pub data: Vec<usize>,
+    pub data: SequenceData,
+}
+
+/// Storage of the bit packed blocks of a [`Sequence`].
+#[derive(Clone)]
+#[cfg_attr(feature = "cache", derive(Deserialize, Serialize), serde(from = "Vec<usize>", into = "Vec<usize>"))]
+pub enum SequenceData {
+    /// Blocks decoded into native words on the heap.
+    Owned(Vec<usize>),
+    /// Little endian bytes in HDT serialization, e.g. borrowed from a memory map.
+    Mapped {
+        /// Serialized blocks, where the last one may be truncated.
+        bytes: Bytes,
+        /// The last block padded with zeroes if it is truncated, otherwise 0.
+        last: usize,
+    },
+}
+
+impl SequenceData {
+    /// Number of blocks.
+    pub fn len(&self) -> usize {
+        match self {
+            SequenceData::Owned(words) => words.len(),
+            SequenceData::Mapped { bytes, .. } => bytes.len().div_ceil(size_of::<usize>()),
+        }
+    }
+
+    /// Get the block at the given index, counting from 0.
+    /// Panics if the index is out of bounds.
+    #[inline]
+    pub fn word(&self, index: usize) -> usize {
+        match self {
+            SequenceData::Owned(words) => words[index],
+            SequenceData::Mapped { bytes, last } => {
+                let start = index * size_of::<usize>();
+                match bytes.get(start..start + size_of::<usize>()) {
+                    Some(word) => usize::from_le_bytes(<[u8; size_of::<usize>()]>::try_from(word).unwrap()),
+                    None => {
+                        assert!(index < self.len(), "block index {index} out of bounds");
+                        *last
+                    }
+                }
+            }
+        }
+    }
+
+    /// Size in bytes on the heap.
+    pub fn size_in_bytes(&self) -> usize {
+        match self {
+            SequenceData::Owned(words) => words.len() * size_of::<usize>(),
+            SequenceData::Mapped { bytes, .. } => bytes.size_in_bytes(),
+        }
+    }
+}
+
+impl From<Vec<usize>> for SequenceData {
+    fn from(words: Vec<usize>) -> Self {
+        SequenceData::Owned(words)
+    }
+}
+
+impl From<SequenceData> for Vec<usize> {
+    fn from(data: SequenceData) -> Self {
+        match data {
+            SequenceData::Owned(words) => words,
+            SequenceData::Mapped { .. } => (0..data.len()).map(|i| data.word(i)).collect(),
+        }
+    }
+}
+
+impl PartialEq for SequenceData {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && (0..self.len()).all(|i| self.word(i) == other.word(i))
+    }
 }
 
 enum SequenceType {
@@ -119,23 +193,76 @@ impl Sequence {
         let result_shift = USIZE_BITS - self.bits_per_entry;
         if bit_index + self.bits_per_entry <= USIZE_BITS {
             let block_shift = USIZE_BITS - bit_index - self.bits_per_entry;
-            result = (self.data[block_index] << block_shift) >> result_shift;
+            result = (self.data.word(block_index) << block_shift) >> result_shift;
         } else {
             let block_shift = (USIZE_BITS << 1) - bit_index - self.bits_per_entry;
-            result = self.data[block_index] >> bit_index;
-            result |= (self.data[block_index + 1] << block_shift) >> result_shift;
+            result = self.data.word(block_index) >> bit_index;
+            result |= (self.data.word(block_index + 1) << block_shift) >> result_shift;
         }
         result
     }
 
     /// Size in bytes on the heap.
-    pub const fn size_in_bytes(&self) -> usize {
-        (self.data.len() * USIZE_BITS) >> 3
+    pub fn size_in_bytes(&self) -> usize {
+        self.data.size_in_bytes()
     }
 
     /// Read sequence including metadata from HDT data.
     pub fn read<R: BufRead>(reader: &mut R) -> Result<Self> {
-        // read entry metadata
+        let (bits_per_entry, entries) = Self::read_meta(reader)?;
+
+        // read body data
+        // read all but the last entry, since the last one is byte aligned
+        let total_bits = bits_per_entry * entries;
+        let full_byte_amount = (total_bits.div_ceil(USIZE_BITS).saturating_sub(1)) * size_of::<usize>();
+        let mut full_words = vec![0_u8; full_byte_amount];
+        reader.read_exact(&mut full_words)?;
+        let mut data: Vec<usize> = Vec::with_capacity(full_byte_amount / size_of::<usize>() + 2);
+        // read entry body
+
+        // turn the raw bytes into usize values
+        for word in full_words.chunks_exact(size_of::<usize>()) {
+            data.push(usize::from_le_bytes(<[u8; size_of::<usize>()]>::try_from(word)?));
+        }
+
+        // keep track of history for CRC32
+        let mut history = full_words;
+        // read the last few bits, byte aligned
+        let mut bits_read = 0;
+        let mut last_value: usize = 0;
+        let last_entry_bits = if total_bits == 0 { 0 } else { ((total_bits - 1) % USIZE_BITS) + 1 };
+
+        while bits_read < last_entry_bits {
+            let mut buffer = [0u8];
+            reader.read_exact(&mut buffer)?;
+            history.extend_from_slice(&buffer);
+            last_value |= (buffer[0] as usize) << bits_read;
+            bits_read += 8;
+        }
+        data.push(last_value);
+        //let start = std::time::Instant::now();
+        Self::verify_crc32(reader, &history)?;
+        //println!("Sequence of {} validated in {:?}", ByteSize(history.len() as u64), start.elapsed());
+
+        Ok(Sequence { entries, bits_per_entry, data: SequenceData::Owned(data) })
+    }
+
+    /// Read sequence including metadata from HDT data that is already in memory, such as a memory-mapped file.
+    /// The body is not copied but borrowed from the underlying bytes.
+    pub fn read_mapped(reader: &mut BytesReader) -> Result<Self> {
+        let (bits_per_entry, entries) = Self::read_meta(reader)?;
+        let body = reader.take((bits_per_entry * entries).div_ceil(8))?;
+        Self::verify_crc32(reader, &body)?;
+        // the truncated last block is padded once here, so that reading a block needs no padding
+        let tail = &body[body.len() - body.len() % size_of::<usize>()..];
+        let mut last = [0u8; size_of::<usize>()];
+        last[..tail.len()].copy_from_slice(tail);
+        let last = usize::from_le_bytes(last);
+        Ok(Sequence { entries, bits_per_entry, data: SequenceData::Mapped { bytes: body, last } })
+    }
+
+    /// Read the entry metadata and validate its CRC8, returns the number of bits per entry and the number of entries.
+    fn read_meta<R: BufRead>(reader: &mut R) -> Result<(usize, usize)> {
         // keep track of history for CRC8
         let mut history = Vec::<u8>::new();
 
@@ -172,53 +299,23 @@ impl Sequence {
         if crc_calculated != crc_code {
             return Err(Error::InvalidCrc8Checksum(crc_calculated, crc_code));
         }
+        Ok((bits_per_entry, entries))
+    }
 
-        // read body data
-        // read all but the last entry, since the last one is byte aligned
-        let total_bits = bits_per_entry * entries;
-        let full_byte_amount = (total_bits.div_ceil(USIZE_BITS).saturating_sub(1)) * size_of::<usize>();
-        let mut full_words = vec![0_u8; full_byte_amount];
-        reader.read_exact(&mut full_words)?;
-        let mut data: Vec<usize> = Vec::with_capacity(full_byte_amount / size_of::<usize>() + 2);
-        // read entry body
-
-        // turn the raw bytes into usize values
-        for word in full_words.chunks_exact(size_of::<usize>()) {
-            data.push(usize::from_le_bytes(<[u8; size_of::<usize>()]>::try_from(word)?));
-        }
-
-        // keep track of history for CRC32
-        let mut history = full_words;
-        // read the last few bits, byte aligned
-        let mut bits_read = 0;
-        let mut last_value: usize = 0;
-        let last_entry_bits = if total_bits == 0 { 0 } else { ((total_bits - 1) % USIZE_BITS) + 1 };
-
-        while bits_read < last_entry_bits {
-            let mut buffer = [0u8];
-            reader.read_exact(&mut buffer)?;
-            history.extend_from_slice(&buffer);
-            last_value |= (buffer[0] as usize) << bits_read;
-            bits_read += 8;
-        }
-        data.push(last_value);
-        // read entry body CRC32
+    /// Read the entry body CRC32 and validate it against the given body bytes.
+    fn verify_crc32<R: BufRead>(reader: &mut R, body: &[u8]) -> Result<()> {
         let mut crc_code = [0_u8; 4];
         reader.read_exact(&mut crc_code)?;
 
         let crc_code32 = u32::from_le_bytes(crc_code);
-        //let start = std::time::Instant::now();
-        // validate entry body CRC32
         let crc32 = crc::Crc::<u32>::new(&crc::CRC_32_ISCSI);
         let mut digest = crc32.digest();
-        digest.update(&history);
+        digest.update(body);
         let crc_calculated32 = digest.finalize();
-        //println!("Sequence of {} validated in {:?}", ByteSize(history.len() as u64), start.elapsed());
         if crc_calculated32 != crc_code32 {
             return Err(Error::InvalidCrc32Checksum(crc_calculated32, crc_code32));
         }
-
-        Ok(Sequence { entries, bits_per_entry, data })
+        Ok(())
     }
 
     /// save sequence per HDT spec using CRC
@@ -244,7 +341,7 @@ impl Sequence {
         // Write data
         let crc32 = crc::Crc::<u32>::new(&crc::CRC_32_ISCSI);
         let mut digest32 = crc32.digest();
-        let bytes: Vec<u8> = self.data.iter().flat_map(|&val| val.to_le_bytes()).collect();
+        let bytes: Vec<u8> = (0..self.data.len()).flat_map(|i| self.data.word(i).to_le_bytes()).collect();
         //  unused zero bytes in the last usize are not written
         let num_bytes = (self.bits_per_entry * self.entries).div_ceil(8);
         let bytes = &bytes[..num_bytes];
@@ -282,7 +379,7 @@ impl Sequence {
     pub fn new(numbers: &[usize]) -> Sequence {
         let entries = numbers.len();
         if entries == 0 {
-            return Sequence { entries, bits_per_entry: 0, data: vec![] };
+            return Sequence { entries, bits_per_entry: 0, data: SequenceData::Owned(vec![]) };
         }
         //let bits_per_entry = numbers.iter().max().unwrap().bit_width() as usize; // nightly only
         let bits_per_entry = (usize::BITS - numbers.iter().max().unwrap().leading_zeros()) as usize; // emulate bit_width using stable API
@@ -302,7 +399,7 @@ impl Sequence {
             last[..rest_byte_amount].copy_from_slice(&numbers8[full_byte_amount..]);
             data.push(usize::from_le_bytes(last));
         }
-        Sequence { entries, bits_per_entry, data }
+        Sequence { entries, bits_per_entry, data: SequenceData::Owned(data) }
     }
 
     // manual compact integer sequence, as sucds lib does not allow export of internal storage
@@ -359,7 +456,7 @@ mod tests {
         init();
         let data = vec![(5 << 16) + (4 << 12) + (3 << 8) + (2 << 4) + 1];
         // little endian
-        let s = Sequence { entries: 5, bits_per_entry: 4, data: data.clone() };
+        let s = Sequence { entries: 5, bits_per_entry: 4, data: data.clone().into() };
         let numbers: Vec<usize> = s.into_iter().collect();
         //let expected = vec![1];
         let expected = vec![1, 2, 3, 4, 5];
@@ -379,6 +476,12 @@ mod tests {
         let numbers2: Vec<usize> = s2.into_iter().collect();
         assert_eq!(numbers, numbers2);
         assert_eq!(cursor.position(), buf.len() as u64);
+        // borrowed instead of copied
+        let mut reader = BytesReader::new(buf.clone().into());
+        let s_mapped = Sequence::read_mapped(&mut reader)?;
+        assert_eq!(s, s_mapped);
+        assert_eq!(s_mapped.into_iter().collect::<Vec<_>>(), numbers);
+        assert_eq!(reader.position(), buf.len());
         // new and pack_bits
         let s3 = Sequence::new(&numbers);
         //let s3 = Sequence::new(&numbers, 4);
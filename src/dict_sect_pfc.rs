@@ -2,13 +2,13 @@
 /// Dictionary section with plain front coding.
 /// See <https://www.rdfhdt.org/hdt-binary-format/#DictionarySectionPlainFrontCoding>.
 use crate::containers::vbyte::{decode_vbyte_delta, encode_vbyte, read_vbyte};
-use crate::containers::{Sequence, sequence};
+use crate::containers::{Bytes, BytesReader, Sequence, sequence};
 use crate::triples::Id;
 use bytesize::ByteSize;
 use std::cmp::{Ordering, min};
 use std::collections::BTreeSet;
 use std::io::{BufRead, Write};
-use std::sync::Arc;
+use std::ops::Range;
 #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
 use std::thread::{JoinHandle, spawn};
 use std::{fmt, str};
@@ -17,7 +17,7 @@ use thiserror::Error;
 pub type Result<T> = core::result::Result<T, Error>;
 
 /// Type alias for the internal read result to reduce complexity
-type ReadInternalResult = (usize, usize, Sequence, Arc<[u8]>, [u8; 4]);
+type ReadInternalResult = (usize, usize, Sequence, Bytes, [u8; 4]);
 
 /// Dictionary section with plain front coding.
 //#[derive(Clone)]
@@ -30,7 +30,7 @@ pub struct DictSectPFC {
     /// stores the starting position of each block
     pub sequence: Sequence,
     /// the substrings
-    pub packed_data: Arc<[u8]>,
+    pub packed_data: Bytes,
 }
 
 /// The error type for the DictSectPFC read function.
@@ -48,6 +48,18 @@ pub enum Error {
     Sequence(#[from] sequence::Error),
 }
 
+/// Verifies the CRC32-C checksum of the packed data of a dictionary section.
+pub fn verify_crc32(packed_data: &[u8], crc_code: u32) -> Result<()> {
+    let crc32 = crc::Crc::<u32>::new(&crc::CRC_32_ISCSI);
+    let mut digest32 = crc32.digest();
+    digest32.update(packed_data);
+    let crc_calculated32 = digest32.finalize();
+    if crc_calculated32 != crc_code {
+        return Err(Error::InvalidCrc32Checksum(crc_calculated32, crc_code));
+    }
+    Ok(())
+}
+
 impl fmt::Debug for DictSectPFC {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -72,7 +84,12 @@ pub enum ExtractError {
 impl DictSectPFC {
     /// size in bytes of the dictionary section
     pub fn size_in_bytes(&self) -> usize {
-        self.sequence.size_in_bytes() + self.packed_data.len()
+        self.sequence.size_in_bytes() + self.packed_data.size_in_bytes()
+    }
+
+    /// CRC32-C checksum of the packed data, which is stored after it in the HDT file.
+    pub fn crc32(&self) -> u32 {
+        crc::Crc::<u32>::new(&crc::CRC_32_ISCSI).checksum(&self.packed_data)
     }
 
     fn index_str(&self, index: usize) -> &str {
@@ -213,6 +230,57 @@ impl DictSectPFC {
         }
     }
 
+    /// Number of strings that are smaller than the given bytes in byte order.
+    /// Finds the last block whose first string is smaller with a binary search and then decodes that block.
+    fn rank(&self, s: &[u8]) -> usize {
+        let mut low = 0;
+        let mut high = self.num_strings.div_ceil(self.block_size);
+        while low < high {
+            let mid = usize::midpoint(low, high);
+            let position = self.sequence.get(mid);
+            if &self.packed_data[position..position + self.strlen(position)] < s {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        let Some(block) = low.checked_sub(1) else { return 0 };
+        let start = block * self.block_size;
+        let iter = PfcIter { sect: self, index: start, position: 0, string: Vec::new() };
+        start + iter.take(self.block_size).take_while(|string| string.as_slice() < s).count()
+    }
+
+    /// Range of the IDs of all strings that start with the given prefix, which is empty if there are none.
+    /// As the strings are sorted, they are contiguous and found with two binary searches over the blocks.
+    /// # Example
+    /// ```
+    /// let file = std::fs::File::open("tests/resources/snikmeta.hdt").unwrap();
+    /// let hdt = hdt::Hdt::read(std::io::BufReader::new(file)).unwrap();
+    /// let hdt::Dict::Four(dict) = &hdt.dict else { panic!("snikmeta has a four section dictionary") };
+    /// let rdfs = "http://www.w3.org/2000/01/rdf-schema#";
+    /// let range = dict.predicates.prefix_range(rdfs);
+    /// assert!(!range.is_empty());
+    /// for id in range {
+    ///     assert!(dict.predicates.extract(id).unwrap().starts_with(rdfs));
+    /// }
+    /// ```
+    pub fn prefix_range(&self, prefix: &str) -> Range<Id> {
+        let start = self.rank(prefix.as_bytes()) + 1;
+        // the smallest byte string that is larger than all strings with the prefix
+        let mut upper = prefix.as_bytes().to_vec();
+        while upper.last() == Some(&u8::MAX) {
+            upper.pop();
+        }
+        let end = match upper.last_mut() {
+            Some(last) => {
+                *last += 1;
+                self.rank(&upper) + 1
+            }
+            None => self.num_strings + 1,
+        };
+        start..end
+    }
+
     fn strlen(&self, offset: usize) -> usize {
         let length = self.packed_data.len();
         let mut position = offset;
@@ -232,6 +300,37 @@ impl DictSectPFC {
     /// Common parsing logic for reading a dictionary section.
     /// Returns the parsed components and the CRC32 code to be verified.
     fn read_internal<R: BufRead>(reader: &mut R) -> Result<ReadInternalResult> {
+        let (num_strings, packed_length, block_size) = Self::read_meta(reader)?;
+        // read sequence log array
+        let sequence = Sequence::read(reader)?;
+        //println!("read sequence of length {} {:?}", sequence.data.len(), sequence.data);
+
+        // read packed data
+        let mut packed_data = vec![0u8; packed_length];
+        reader.read_exact(&mut packed_data)?;
+        let packed_data = Bytes::from(packed_data);
+        //println!("read packed data of length {} {:?}", packed_data.len(), packed_data);
+
+        // read packed data CRC32
+        let mut crc_code = [0u8; 4];
+        reader.read_exact(&mut crc_code)?;
+
+        Ok((num_strings, block_size, sequence, packed_data, crc_code))
+    }
+
+    /// Like `read_internal` but borrows the sequence and packed data from the underlying bytes instead of copying them.
+    #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
+    fn read_internal_mapped(reader: &mut BytesReader) -> Result<ReadInternalResult> {
+        let (num_strings, packed_length, block_size) = Self::read_meta(reader)?;
+        let sequence = Sequence::read_mapped(reader)?;
+        let packed_data = reader.take(packed_length)?;
+        let mut crc_code = [0u8; 4];
+        reader.read_exact(&mut crc_code)?;
+        Ok((num_strings, block_size, sequence, packed_data, crc_code))
+    }
+
+    /// Read the section metadata and validate its CRC8, returns the number of strings, the packed length and the block size.
+    fn read_meta<R: BufRead>(reader: &mut R) -> Result<(usize, usize, usize)> {
         // https://www.rdfhdt.org/hdt-binary-format/#DictionarySection specifies "unsigned 32bit value preamble" but 8bit is used in practice
         let mut preamble = [0_u8];
         reader.read_exact(&mut preamble)?;
@@ -265,38 +364,24 @@ impl DictSectPFC {
         if crc_calculated8 != crc_code8 {
             return Err(Error::InvalidCrc8Checksum(crc_calculated8, crc_code8));
         }
-        // read sequence log array
-        let sequence = Sequence::read(reader)?;
-        //println!("read sequence of length {} {:?}", sequence.data.len(), sequence.data);
-
-        // read packed data
-        let mut packed_data = vec![0u8; packed_length];
-        reader.read_exact(&mut packed_data)?;
-        let packed_data = Arc::<[u8]>::from(packed_data);
-        //println!("read packed data of length {} {:?}", packed_data.len(), packed_data);
-
-        // read packed data CRC32
-        let mut crc_code = [0u8; 4];
-        reader.read_exact(&mut crc_code)?;
-
-        Ok((num_strings, block_size, sequence, packed_data, crc_code))
+        Ok((num_strings, packed_length, block_size))
     }
 
     /// Verifies the CRC32 checksum and constructs the DictSectPFC.
     fn verify_and_construct(
-        num_strings: usize, block_size: usize, sequence: Sequence, packed_data: Arc<[u8]>, crc_code: [u8; 4],
+        num_strings: usize, block_size: usize, sequence: Sequence, packed_data: Bytes, crc_code: [u8; 4],
     ) -> Result<Self> {
-        let crc32 = crc::Crc::<u32>::new(&crc::CRC_32_ISCSI);
-        let mut digest32 = crc32.digest();
-        digest32.update(&packed_data[..]);
-        let crc_calculated32 = digest32.finalize();
-        let crc_code32 = u32::from_le_bytes(crc_code);
-        if crc_calculated32 != crc_code32 {
-            return Err(Error::InvalidCrc32Checksum(crc_calculated32, crc_code32));
-        }
+        verify_crc32(&packed_data, u32::from_le_bytes(crc_code))?;
         Ok(DictSectPFC { num_strings, block_size, sequence, packed_data })
     }
 
+    /// Returns a dictionary section without verifying the checksum of its packed data, together with the expected CRC32 code.
+    /// The checksum can be verified later with [`verify_crc32`], for example in parallel or in the background.
+    pub fn read_unverified<R: BufRead>(reader: &mut R) -> Result<(Self, u32)> {
+        let (num_strings, block_size, sequence, packed_data, crc_code) = Self::read_internal(reader)?;
+        Ok((DictSectPFC { num_strings, block_size, sequence, packed_data }, u32::from_le_bytes(crc_code)))
+    }
+
     /// Returns an unverified dictionary section together with a handle to verify the checksum.
     #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
     pub fn read<R: BufRead>(reader: &mut R) -> Result<JoinHandle<Result<Self>>> {
@@ -304,6 +389,21 @@ impl DictSectPFC {
         Ok(spawn(move || Self::verify_and_construct(num_strings, block_size, sequence, packed_data, crc_code)))
     }
 
+    /// Returns an unverified dictionary section that borrows its data from the given reader, e.g. a memory-mapped file,
+    /// together with a handle to verify the checksum.
+    #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
+    pub fn read_mapped(reader: &mut BytesReader) -> Result<JoinHandle<Result<Self>>> {
+        let (num_strings, block_size, sequence, packed_data, crc_code) = Self::read_internal_mapped(reader)?;
+        Ok(spawn(move || Self::verify_and_construct(num_strings, block_size, sequence, packed_data, crc_code)))
+    }
+
+    /// Like `read_unverified` but borrows the data from the given reader, e.g. a memory-mapped file.
+    #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
+    pub fn read_mapped_unverified(reader: &mut BytesReader) -> Result<(Self, u32)> {
+        let (num_strings, block_size, sequence, packed_data, crc_code) = Self::read_internal_mapped(reader)?;
+        Ok((DictSectPFC { num_strings, block_size, sequence, packed_data }, u32::from_le_bytes(crc_code)))
+    }
+
     /// WASM-specific version that returns result directly without threading
     #[cfg(any(target_arch = "wasm32", target_arch = "wasm64"))]
     pub fn read<R: BufRead>(reader: &mut R) -> Result<Self> {
@@ -349,38 +449,163 @@ impl DictSectPFC {
 
     /// sorted and unique terms
     pub fn compress(terms: &BTreeSet<&str>, block_size: usize) -> Self {
-        let mut compressed_terms = Vec::new();
-        let mut offsets = Vec::new();
-        let mut last_term: &[u8] = &[];
-
-        let num_terms = terms.len();
-        for (i, term) in terms.iter().enumerate() {
-            let term = term.as_bytes();
-            if i % block_size == 0 {
-                offsets.push(compressed_terms.len());
-                compressed_terms.extend_from_slice(term);
-            } else {
-                let common_prefix_len = last_term.iter().zip(term).take_while(|(a, b)| a == b).count();
-                compressed_terms.extend_from_slice(&encode_vbyte(common_prefix_len));
-                compressed_terms.extend_from_slice(&term[common_prefix_len..]);
+        let mut builder = PfcBuilder::new(block_size);
+        for term in terms {
+            builder.push(term.as_bytes());
+        }
+        builder.finish()
+    }
+
+    /// Decodes all strings sequentially in ascending order, which is much faster than extracting them one by one.
+    pub fn iter(&self) -> PfcIter<'_> {
+        PfcIter { sect: self, index: 0, position: 0, string: Vec::new() }
+    }
+
+    /// Like [`DictSectPFC::iter`] but starts at the string with the given ID,
+    /// so that only the strings before it in the same block are decoded.
+    pub(crate) fn iter_from(&self, id: Id) -> PfcIter<'_> {
+        let index = id.saturating_sub(1) / self.block_size * self.block_size;
+        let mut iter = PfcIter { sect: self, index, position: 0, string: Vec::new() };
+        while iter.index + 1 < id && iter.advance().is_some() {}
+        iter
+    }
+
+    /// IDs and strings that satisfy the predicate in ascending order.
+    /// Like [`DictSectPFC::iter`], the strings are decoded block by block from the previous string,
+    /// but only those that satisfy the predicate are copied.
+    /// # Example
+    /// ```
+    /// let file = std::fs::File::open("tests/resources/snikmeta.hdt").unwrap();
+    /// let hdt = hdt::Hdt::read(std::io::BufReader::new(file)).unwrap();
+    /// let hdt::Dict::Four(dict) = &hdt.dict else { panic!("snikmeta has a four section dictionary") };
+    /// for (id, s) in dict.objects.scan(|s| s.ends_with(b"@de")) {
+    ///     assert_eq!(dict.objects.extract(id).unwrap().as_bytes(), s);
+    /// }
+    /// ```
+    pub fn scan<'a>(
+        &'a self, mut predicate: impl FnMut(&[u8]) -> bool + 'a,
+    ) -> impl Iterator<Item = (Id, Vec<u8>)> + 'a {
+        let mut iter = self.iter();
+        std::iter::from_fn(move || {
+            while let Some((id, s)) = iter.advance() {
+                if predicate(s) {
+                    return Some((id, s.to_vec()));
+                }
             }
+            None
+        })
+    }
+}
+
+impl<'a> IntoIterator for &'a DictSectPFC {
+    type Item = Vec<u8>;
+    type IntoIter = PfcIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the raw bytes of the strings of a dictionary section, see [`DictSectPFC::iter`].
+pub struct PfcIter<'a> {
+    sect: &'a DictSectPFC,
+    /// index of the next string, starting at 0
+    index: usize,
+    /// position of the next string in the packed data, which is reset at each block start
+    position: usize,
+    string: Vec<u8>,
+}
+
+impl PfcIter<'_> {
+    /// Decodes the next string into the buffer and returns its ID and the buffer without copying it.
+    fn advance(&mut self) -> Option<(Id, &[u8])> {
+        if self.index >= self.sect.num_strings {
+            return None;
+        }
+        if self.index.is_multiple_of(self.sect.block_size) {
+            self.position = self.sect.sequence.get(self.index / self.sect.block_size);
+            self.string.clear();
+        } else {
+            let (delta, vbyte_bytes) = decode_vbyte_delta(&self.sect.packed_data, self.position);
+            self.position += vbyte_bytes;
+            self.string.truncate(delta);
+        }
+        let slen = self.sect.strlen(self.position);
+        self.string.extend_from_slice(&self.sect.packed_data[self.position..self.position + slen]);
+        self.position += slen + 1;
+        self.index += 1;
+        Some((self.index, &self.string))
+    }
+}
+
+impl Iterator for PfcIter<'_> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        self.advance().map(|(_, s)| s.to_vec())
+    }
 
-            compressed_terms.push(0); // Null separator
-            last_term = term;
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.sect.num_strings - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for PfcIter<'_> {}
+
+/// Front codes strings one at a time, so that a section can be built without holding all of its terms in memory.
+/// The strings must be pushed in ascending byte order without duplicates.
+pub(crate) struct PfcBuilder {
+    block_size: usize,
+    num_strings: usize,
+    compressed_terms: Vec<u8>,
+    offsets: Vec<usize>,
+    last_term: Vec<u8>,
+}
+
+impl PfcBuilder {
+    pub(crate) const fn new(block_size: usize) -> Self {
+        PfcBuilder {
+            block_size,
+            num_strings: 0,
+            compressed_terms: Vec::new(),
+            offsets: Vec::new(),
+            last_term: Vec::new(),
         }
-        if num_terms > 0 {
-            offsets.push(compressed_terms.len());
+    }
+
+    /// Appends the next string and returns its ID.
+    pub(crate) fn push(&mut self, term: &[u8]) -> Id {
+        if self.num_strings.is_multiple_of(self.block_size) {
+            self.offsets.push(self.compressed_terms.len());
+            self.compressed_terms.extend_from_slice(term);
+        } else {
+            let common_prefix_len = self.last_term.iter().zip(term).take_while(|(a, b)| a == b).count();
+            self.compressed_terms.extend_from_slice(&encode_vbyte(common_prefix_len));
+            self.compressed_terms.extend_from_slice(&term[common_prefix_len..]);
+        }
+
+        self.compressed_terms.push(0); // Null separator
+        self.last_term.clear();
+        self.last_term.extend_from_slice(term);
+        self.num_strings += 1;
+        self.num_strings
+    }
+
+    pub(crate) fn finish(mut self) -> DictSectPFC {
+        if self.num_strings > 0 {
+            self.offsets.push(self.compressed_terms.len());
         }
 
         // offsets are an increasing list of array indices, therefore the last one will be the largest
         // TODO: potential off by 1 in comparison with hdt-cpp implementation?
         //let bits_per_entry = if num_terms == 0 { 0 } else { (offsets.last().unwrap().ilog2() + 1) as usize };
         DictSectPFC {
-            num_strings: num_terms,
-            block_size,
+            num_strings: self.num_strings,
+            block_size: self.block_size,
             //sequence: Sequence::new(&offsets, bits_per_entry),
-            sequence: Sequence::new(&offsets),
-            packed_data: Arc::from(compressed_terms),
+            sequence: Sequence::new(&self.offsets),
+            packed_data: Bytes::from(self.compressed_terms),
         }
     }
 }
@@ -482,6 +707,46 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn prefix_range() -> color_eyre::Result<()> {
+        init();
+        let crate::Dict::Four(dict) = snikmeta()?.dict else {
+            panic!("snikmeta should have a four section dictionary")
+        };
+        for sect in [&dict.shared, &dict.subjects, &dict.predicates, &dict.objects] {
+            let strings: Vec<String> = sect.iter().map(|s| String::from_utf8(s).unwrap()).collect();
+            for prefix in [
+                "", "_:", "\"", "http://www.snik.eu/ontology/meta/", "http://www.snik.eu/ontology/meta/T",
+                "http://www.w3.org/", "http://www.w3.org/2000/01/rdf-schema#label", "~", "a",
+            ] {
+                let expected: Vec<Id> =
+                    (1..=strings.len()).filter(|&id| strings[id - 1].starts_with(prefix)).collect();
+                assert_eq!(sect.prefix_range(prefix).collect::<Vec<_>>(), expected, "prefix {prefix:?}");
+            }
+            // every string is found as its own prefix
+            for (i, string) in strings.iter().enumerate() {
+                assert!(sect.prefix_range(string).contains(&(i + 1)), "{string}");
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn scan() -> color_eyre::Result<()> {
+        init();
+        let crate::Dict::Four(dict) = snikmeta()?.dict else {
+            panic!("snikmeta should have a four section dictionary")
+        };
+        for sect in [&dict.shared, &dict.subjects, &dict.predicates, &dict.objects] {
+            let all: Vec<(Id, Vec<u8>)> = sect.iter().enumerate().map(|(i, s)| (i + 1, s)).collect();
+            assert_eq!(sect.scan(|_| true).collect::<Vec<_>>(), all);
+            let filter = |s: &[u8]| s.contains(&b'e') && !s.ends_with(b"s");
+            let expected: Vec<_> = all.into_iter().filter(|(_, s)| filter(s)).collect();
+            assert_eq!(sect.scan(filter).collect::<Vec<_>>(), expected);
+        }
+        Ok(())
+    }
+
     #[test]
     fn compress() -> color_eyre::Result<()> {
         const BLOCK_SIZE: usize = 16;
@@ -504,11 +769,13 @@ mod tests {
 
         // large test that relies on HDT reading and involved components working correctly
         let hdt = snikmeta()?;
-        let dict = hdt.dict;
+        let crate::Dict::Four(dict) = hdt.dict else { panic!("snikmeta should have a four section dictionary") };
         let names = ["shared", "subject", "predicate", "object"];
         let sects = [dict.shared, dict.subjects, dict.predicates, dict.objects];
         for (sect, name) in sects.iter().zip(names) {
             let items1 = sect_items(sect);
+            let iterated: Vec<String> = sect.iter().map(|b| String::from_utf8(b).unwrap()).collect();
+            assert_eq!(items1, iterated, "error iterating {name} section");
             let set1: BTreeSet<&str> = items1.iter().map(std::ops::Deref::deref).collect();
             let sect2 = DictSectPFC::compress(&set1, BLOCK_SIZE);
             let items2 = sect_items(&sect2);
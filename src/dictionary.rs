@@ -0,0 +1,340 @@
+//! Dictionary abstraction over the different dictionary formats that HDT files can contain.
+#[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
+use crate::containers::BytesReader;
+use crate::containers::control_info::{DICTIONARY_FOUR, DICTIONARY_FOUR_PSFC, DICTIONARY_MULT};
+use crate::containers::{Bytes, ControlInfo};
+use crate::dict_sect_pfc::{self, DictSectPFC};
+use crate::four_sect_dict::{DictSectError, Error, ExtractError, Result, SectKind, UnvalidatedFourSectDict};
+use crate::mult_sect_dict::{MultSectDict, UnvalidatedMultSectDict};
+use crate::triples::Id;
+use crate::{FourSectDict, IdKind};
+use std::borrow::Cow;
+use std::io::{BufRead, Write};
+
+/// Bidirectional mapping between RDF terms and the IDs used in the triples section.
+/// String representation of URIs, literals and blank nodes is defined in <https://www.w3.org/Submission/2011/SUBM-HDT-20110330/#dictionaryEncoding>.
+pub trait Dictionary {
+    /// Get the string value of a given ID of a given type.
+    fn id_to_string(&self, id: Id, id_kind: IdKind) -> core::result::Result<String, ExtractError>;
+    /// Get the ID for a given string or 0 if not found.
+    fn string_to_id(&self, s: &str, id_kind: IdKind) -> Id;
+    /// Number of terms that occur both as subject and object, which share the lowest IDs of both kinds.
+    fn num_shared(&self) -> usize;
+    /// Number of terms of the given kind, including the shared ones for subjects and objects.
+    fn num_terms(&self, id_kind: IdKind) -> usize;
+    /// Size in bytes of the in-memory dictionary.
+    fn size_in_bytes(&self) -> usize;
+    /// IDs of the terms of the given kind whose string representation starts with the given prefix in ascending order,
+    /// for example to autocomplete IRIs without scanning the triples.
+    /// The default implementation extracts the string of every term of the kind,
+    /// implementations should override it with a search in their sorted sections.
+    fn search(&self, prefix: &str, id_kind: IdKind) -> Box<dyn Iterator<Item = Id> + '_> {
+        let prefix = prefix.to_owned();
+        Box::new(
+            (1..=self.num_terms(id_kind))
+                .filter(move |&id| self.id_to_string(id, id_kind).is_ok_and(|s| s.starts_with(&prefix))),
+        )
+    }
+}
+
+/// Any of the supported dictionary formats.
+#[cfg_attr(test, derive(PartialEq))]
+#[derive(Debug)]
+pub enum Dict {
+    /// <http://purl.org/HDT/hdt#dictionaryFour>, written by this library, hdt-cpp and hdt-java.
+    Four(FourSectDict),
+    /// <http://purl.org/HDT/hdt#dictionaryFourPsfc>, the strings are stored prefixed, see [`lit_to_pref`].
+    FourPsfc(FourSectDict),
+    /// <http://purl.org/HDT/hdt#dictionaryMult>, written by hdt-java with one object section per literal datatype.
+    Mult(MultSectDict),
+}
+
+impl Dict {
+    /// Read the whole dictionary section including control information.
+    pub fn read<R: BufRead>(reader: &mut R) -> Result<UnvalidatedDict> {
+        let dict_ci = ControlInfo::read(reader)?;
+        Ok(match dict_ci.format.as_str() {
+            DICTIONARY_FOUR => UnvalidatedDict::Four(FourSectDict::read_sections(reader)?),
+            DICTIONARY_FOUR_PSFC => UnvalidatedDict::FourPsfc(FourSectDict::read_sections(reader)?),
+            DICTIONARY_MULT => UnvalidatedDict::Mult(MultSectDict::read_sections(reader)?),
+            f => return Err(Error::Other(format!("unsupported dictionary format {f}"))),
+        })
+    }
+
+    /// Like `read` but the sections borrow their data from the given reader instead of copying it.
+    #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
+    pub fn read_mapped(reader: &mut BytesReader) -> Result<UnvalidatedDict> {
+        let dict_ci = ControlInfo::read(reader)?;
+        Ok(match dict_ci.format.as_str() {
+            DICTIONARY_FOUR => UnvalidatedDict::Four(FourSectDict::read_mapped_sections(reader)?),
+            DICTIONARY_FOUR_PSFC => UnvalidatedDict::FourPsfc(FourSectDict::read_mapped_sections(reader)?),
+            DICTIONARY_MULT => UnvalidatedDict::Mult(MultSectDict::read_mapped_sections(reader)?),
+            f => return Err(Error::Other(format!("unsupported dictionary format {f}"))),
+        })
+    }
+
+    /// Like `read` but the checksums of the sections are not verified while reading, see [`PendingChecksums`].
+    pub fn read_unverified<R: BufRead>(reader: &mut R) -> Result<(Dict, PendingChecksums)> {
+        let dict_ci = ControlInfo::read(reader)?;
+        let mut pending = PendingChecksums::default();
+        let dict = match dict_ci.format.as_str() {
+            DICTIONARY_FOUR => Dict::Four(FourSectDict::read_sections_unverified(reader, &mut pending)?),
+            DICTIONARY_FOUR_PSFC => Dict::FourPsfc(FourSectDict::read_sections_unverified(reader, &mut pending)?),
+            DICTIONARY_MULT => Dict::Mult(MultSectDict::read_sections_unverified(reader, &mut pending)?),
+            f => return Err(Error::Other(format!("unsupported dictionary format {f}"))),
+        };
+        Ok((dict, pending))
+    }
+
+    /// Like `read_mapped` but the checksums of the sections are not verified while reading, see [`PendingChecksums`].
+    #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
+    pub fn read_mapped_unverified(reader: &mut BytesReader) -> Result<(Dict, PendingChecksums)> {
+        let dict_ci = ControlInfo::read(reader)?;
+        let mut pending = PendingChecksums::default();
+        let dict = match dict_ci.format.as_str() {
+            DICTIONARY_FOUR => Dict::Four(FourSectDict::read_mapped_sections_unverified(reader, &mut pending)?),
+            DICTIONARY_FOUR_PSFC => {
+                Dict::FourPsfc(FourSectDict::read_mapped_sections_unverified(reader, &mut pending)?)
+            }
+            DICTIONARY_MULT => Dict::Mult(MultSectDict::read_mapped_sections_unverified(reader, &mut pending)?),
+            f => return Err(Error::Other(format!("unsupported dictionary format {f}"))),
+        };
+        Ok((dict, pending))
+    }
+
+    /// Write the whole dictionary including control info in its original format.
+    pub fn write(&self, write: &mut impl Write) -> Result<()> {
+        match self {
+            Dict::Four(d) => d.write(write),
+            Dict::FourPsfc(d) => {
+                ControlInfo::four_psfc_sect_dict().write(write)?;
+                d.write_sections(write)
+            }
+            Dict::Mult(d) => {
+                ControlInfo::mult_sect_dict().write(write)?;
+                d.write_sections(write)
+            }
+        }
+    }
+}
+
+impl Dictionary for Dict {
+    fn id_to_string(&self, id: Id, id_kind: IdKind) -> core::result::Result<String, ExtractError> {
+        match self {
+            Dict::Four(d) => d.id_to_string(id, id_kind),
+            Dict::FourPsfc(d) => d.id_to_string(id, id_kind).map(|s| pref_to_lit(&s).into_owned()),
+            Dict::Mult(d) => d.id_to_string(id, id_kind),
+        }
+    }
+
+    fn string_to_id(&self, s: &str, id_kind: IdKind) -> Id {
+        match self {
+            Dict::Four(d) => d.string_to_id(s, id_kind),
+            Dict::FourPsfc(d) => d.string_to_id(&lit_to_pref(s), id_kind),
+            Dict::Mult(d) => d.string_to_id(s, id_kind),
+        }
+    }
+
+    fn num_shared(&self) -> usize {
+        match self {
+            Dict::Four(d) | Dict::FourPsfc(d) => d.num_shared(),
+            Dict::Mult(d) => d.num_shared(),
+        }
+    }
+
+    fn num_terms(&self, id_kind: IdKind) -> usize {
+        match self {
+            Dict::Four(d) | Dict::FourPsfc(d) => d.num_terms(id_kind),
+            Dict::Mult(d) => d.num_terms(id_kind),
+        }
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        match self {
+            Dict::Four(d) | Dict::FourPsfc(d) => Dictionary::size_in_bytes(d),
+            Dict::Mult(d) => d.size_in_bytes(),
+        }
+    }
+
+    /// The prefix is compared with the stored strings, so literals with a datatype or language tag
+    /// are only found by prefixes of their label in the multiple section dictionary
+    /// and by prefixes in the prefixed form of [`lit_to_pref`] in the PSFC dictionary.
+    fn search(&self, prefix: &str, id_kind: IdKind) -> Box<dyn Iterator<Item = Id> + '_> {
+        match self {
+            Dict::Four(d) | Dict::FourPsfc(d) => Dictionary::search(d, prefix, id_kind),
+            Dict::Mult(d) => d.search(prefix, id_kind),
+        }
+    }
+}
+
+impl From<FourSectDict> for Dict {
+    fn from(dict: FourSectDict) -> Self {
+        Dict::Four(dict)
+    }
+}
+
+/// A wrapper to prevent using a dictionary before the checksums of its sections have been validated.
+pub enum UnvalidatedDict {
+    Four(UnvalidatedFourSectDict),
+    FourPsfc(UnvalidatedFourSectDict),
+    Mult(UnvalidatedMultSectDict),
+}
+
+impl UnvalidatedDict {
+    /// Validates the checksums of all dictionary sections.
+    pub fn validate(self) -> Result<Dict> {
+        Ok(match self {
+            UnvalidatedDict::Four(d) => Dict::Four(d.validate()?),
+            UnvalidatedDict::FourPsfc(d) => Dict::FourPsfc(d.validate()?),
+            UnvalidatedDict::Mult(d) => Dict::Mult(d.validate()?),
+        })
+    }
+}
+
+/// Expected checksums of the dictionary sections read by [`Dict::read_unverified`], which can be verified separately from reading.
+#[derive(Debug, Default)]
+pub struct PendingChecksums(Vec<(SectKind, Bytes, u32)>);
+
+impl PendingChecksums {
+    /// Reads a dictionary section and remembers its packed data and expected checksum.
+    pub(crate) fn read_section<R: BufRead>(&mut self, reader: &mut R, sect_kind: SectKind) -> Result<DictSectPFC> {
+        let (sect, crc_code) = DictSectPFC::read_unverified(reader).map_err(|e| DictSectError { e, sect_kind })?;
+        self.0.push((sect_kind, sect.packed_data.clone(), crc_code));
+        Ok(sect)
+    }
+
+    /// Like `read_section` but the section borrows its data from the given reader instead of copying it.
+    #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
+    pub(crate) fn read_mapped_section(
+        &mut self, reader: &mut BytesReader, sect_kind: SectKind,
+    ) -> Result<DictSectPFC> {
+        let (sect, crc_code) =
+            DictSectPFC::read_mapped_unverified(reader).map_err(|e| DictSectError { e, sect_kind })?;
+        self.0.push((sect_kind, sect.packed_data.clone(), crc_code));
+        Ok(sect)
+    }
+
+    /// Verifies the checksums of all sections using up to the given number of threads.
+    pub fn verify(&self, threads: usize) -> Result<()> {
+        let check = |(sect_kind, packed_data, crc_code): &(SectKind, Bytes, u32)| {
+            dict_sect_pfc::verify_crc32(&packed_data[..], *crc_code)
+                .map_err(|e| DictSectError { e, sect_kind: *sect_kind })
+        };
+        #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
+        if threads > 1 && self.0.len() > 1 {
+            let chunk_size = self.0.len().div_ceil(threads);
+            return std::thread::scope(|s| {
+                let handles: Vec<_> = self
+                    .0
+                    .chunks(chunk_size)
+                    .map(|chunk| s.spawn(move || chunk.iter().try_for_each(check)))
+                    .collect();
+                handles.into_iter().try_for_each(|h| h.join().unwrap())
+            })
+            .map_err(Error::from);
+        }
+        #[cfg(any(target_arch = "wasm32", target_arch = "wasm64"))]
+        let _ = threads;
+        self.0.iter().try_for_each(check).map_err(Error::from)
+    }
+}
+
+/// Moves the datatype or language tag of a literal in front of its label, as done by the PSFC dictionary of hdt-java.
+/// For example `"1"^^<http://www.w3.org/2001/XMLSchema#integer>` becomes `^^<http://www.w3.org/2001/XMLSchema#integer>"1"`
+/// and `"chat"@fr` becomes `@fr"chat"`. Other terms are returned unchanged.
+pub fn lit_to_pref(s: &str) -> Cow<'_, str> {
+    if !s.starts_with('"') {
+        return Cow::Borrowed(s);
+    }
+    let Some(end) = s.rfind('"') else { return Cow::Borrowed(s) };
+    let (label, suffix) = s.split_at(end + 1);
+    if suffix.starts_with("^^") || suffix.starts_with('@') {
+        Cow::Owned(format!("{suffix}{label}"))
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+/// Inverse of [`lit_to_pref`].
+pub fn pref_to_lit(s: &str) -> Cow<'_, str> {
+    if !(s.starts_with("^^") || s.starts_with('@')) {
+        return Cow::Borrowed(s);
+    }
+    let Some(start) = s.find('"') else { return Cow::Borrowed(s) };
+    let (prefix, label) = s.split_at(start);
+    Cow::Owned(format!("{label}{prefix}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hdt::tests::snikmeta;
+    use crate::tests::init;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn prefixed_literals() {
+        for (lit, pref) in [
+            (
+                r#""1"^^<http://www.w3.org/2001/XMLSchema#integer>"#,
+                r#"^^<http://www.w3.org/2001/XMLSchema#integer>"1""#,
+            ),
+            (r#""chat"@fr"#, r#"@fr"chat""#),
+            (r#""say "hi"@en"@de"#, r#"@de"say "hi"@en""#),
+            (r#""plain""#, r#""plain""#),
+            ("http://www.snik.eu/ontology/meta", "http://www.snik.eu/ontology/meta"),
+            ("_:b1", "_:b1"),
+        ] {
+            assert_eq!(lit_to_pref(lit), pref);
+            assert_eq!(pref_to_lit(pref), lit);
+        }
+    }
+
+    #[test]
+    fn write_read() -> color_eyre::Result<()> {
+        init();
+        let Dict::Four(four) = snikmeta()?.dict else { panic!("snikmeta should have a four section dictionary") };
+        let mut buf = Vec::new();
+        four.write(&mut buf)?;
+        let dict = Dict::read(&mut std::io::Cursor::new(&buf))?.validate()?;
+        assert_eq!(dict, Dict::Four(four));
+        let mut buf2 = Vec::new();
+        dict.write(&mut buf2)?;
+        assert_eq!(buf, buf2);
+        Ok(())
+    }
+
+    /// Dictionary that only implements the required methods.
+    struct Minimal(Dict);
+
+    impl Dictionary for Minimal {
+        fn id_to_string(&self, id: Id, id_kind: IdKind) -> core::result::Result<String, ExtractError> {
+            self.0.id_to_string(id, id_kind)
+        }
+        fn string_to_id(&self, s: &str, id_kind: IdKind) -> Id {
+            self.0.string_to_id(s, id_kind)
+        }
+        fn num_shared(&self) -> usize {
+            self.0.num_shared()
+        }
+        fn num_terms(&self, id_kind: IdKind) -> usize {
+            self.0.num_terms(id_kind)
+        }
+        fn size_in_bytes(&self) -> usize {
+            self.0.size_in_bytes()
+        }
+    }
+
+    #[test]
+    fn default_search() -> color_eyre::Result<()> {
+        init();
+        let minimal = Minimal(snikmeta()?.dict);
+        let prefix = "http://www.snik.eu/ontology/meta/";
+        for kind in IdKind::KINDS {
+            let expected: Vec<Id> = minimal.0.search(prefix, kind).collect();
+            assert!(!expected.is_empty() || matches!(kind, IdKind::Predicate), "{kind:?}");
+            assert_eq!(minimal.search(prefix, kind).collect::<Vec<_>>(), expected, "{kind:?}");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+//! Set differences of the triples of two HDTs.
+//! The terms of both dictionaries are matched by merging their sorted sections,
+//! then each remaining triple is looked up by ID in the triples section of the other HDT.
+use super::Result;
+use super::cat::{Merge, Part, Terms, merge, sorted_terms};
+use crate::triples::{Id, TripleId};
+use crate::{Dictionary, Hdt, IdKind};
+
+/// Triples that were added and removed between two versions of a dataset, see [`Hdt::changes`].
+#[derive(Debug)]
+pub struct Changes {
+    /// triples of the new version that are not in the old one
+    pub added: Hdt,
+    /// triples of the old version that are not in the new one
+    pub removed: Hdt,
+}
+
+/// ID of the same term in the other HDT for each subject, predicate and object ID, 0 if the other HDT does not contain it.
+type IdMap = [Vec<Id>; 3];
+
+/// Maps the IDs of `a` to those of `b` and vice versa.
+fn id_maps(a: &Hdt, b: &Hdt) -> Result<(IdMap, IdMap)> {
+    let mut a_to_b = IdKind::KINDS.map(|k| vec![0; a.dict.num_terms(k) + 1]);
+    let mut b_to_a = IdKind::KINDS.map(|k| vec![0; b.dict.num_terms(k) + 1]);
+    for kind in IdKind::KINDS {
+        let streams: Vec<Terms> = vec![sorted_terms(&a.dict, kind), sorted_terms(&b.dict, kind)];
+        for (_, sources) in Merge::new(streams) {
+            // equal terms are yielded in stream order
+            if let [(0, a_id), (1, b_id)] = sources[..] {
+                a_to_b[kind as usize][a_id] = b_id;
+                b_to_a[kind as usize][b_id] = a_id;
+            }
+        }
+    }
+    Ok((a_to_b, b_to_a))
+}
+
+/// IDs of the triples of `a` that are not in `b`.
+fn minus(a: &Hdt, b: &Hdt, [s_map, p_map, o_map]: &IdMap) -> Vec<TripleId> {
+    a.triples
+        .into_iter()
+        .filter(|&[s, p, o]| {
+            let pattern = [s_map[s], p_map[p], o_map[o]];
+            pattern.contains(&0) || b.triples.triples_with_pattern(pattern).next().is_none()
+        })
+        .collect()
+}
+
+impl Hdt {
+    /// Creates a new HDT with SPO order that contains the triples of `a` which are not in `b`.
+    /// The triples are compared by ID, so that no triple is translated to strings.
+    /// The dictionary of the result only contains the terms used by its triples.
+    /// # Example
+    /// ```
+    /// use hdt::Hdt;
+    /// let file = std::fs::File::open("tests/resources/snikmeta.hdt").unwrap();
+    /// let hdt = Hdt::read(std::io::BufReader::new(file)).unwrap();
+    /// assert_eq!(Hdt::diff(&hdt, &hdt).unwrap().triples.len(), 0);
+    /// ```
+    pub fn diff(a: &Hdt, b: &Hdt) -> Result<Self> {
+        let (a_to_b, _) = id_maps(a, b)?;
+        merge(&[Part { hdt: a, triples: Some(minus(a, b, &a_to_b)) }])
+    }
+
+    /// Computes the change set between the `old` and the `new` version of a dataset like two calls of [`Hdt::diff`],
+    /// but matches the terms of both dictionaries only once.
+    pub fn changes(old: &Hdt, new: &Hdt) -> Result<Changes> {
+        let (old_to_new, new_to_old) = id_maps(old, new)?;
+        let added = merge(&[Part { hdt: new, triples: Some(minus(new, old, &new_to_old)) }])?;
+        let removed = merge(&[Part { hdt: old, triples: Some(minus(old, new, &old_to_new)) }])?;
+        Ok(Changes { added, removed })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hdt::tests::{snikmeta, snikmeta_check};
+    use crate::tests::init;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn diff() -> color_eyre::Result<()> {
+        init();
+        let snikmeta = snikmeta()?;
+        let empty = Hdt::cat(&[])?;
+        assert_eq!(Hdt::diff(&snikmeta, &snikmeta)?.triples.len(), 0);
+        let all = Hdt::diff(&snikmeta, &empty)?;
+        snikmeta_check(&all)?;
+        let changes = Hdt::changes(&empty, &snikmeta)?;
+        snikmeta_check(&changes.added)?;
+        assert_eq!(changes.removed.triples.len(), 0);
+        // overlapping versions
+        #[cfg(feature = "nt")]
+        {
+            let triples: Vec<_> = snikmeta.triples_all().collect();
+            let n = triples.len();
+            let old = Hdt::from_triples(triples[..n * 2 / 3].iter().cloned())?;
+            let new = Hdt::from_triples(triples[n / 3..].iter().cloned())?;
+            let changes = Hdt::changes(&old, &new)?;
+            let sorted = |hdt: &Hdt| {
+                let mut v: Vec<_> = hdt.triples_all().collect();
+                v.sort();
+                v
+            };
+            let mut expected = triples[n * 2 / 3..].to_vec();
+            expected.sort();
+            assert_eq!(sorted(&changes.added), expected);
+            assert_eq!(sorted(&Hdt::diff(&new, &old)?), expected);
+            let mut expected = triples[..n / 3].to_vec();
+            expected.sort();
+            assert_eq!(sorted(&changes.removed), expected);
+        }
+        Ok(())
+    }
+}
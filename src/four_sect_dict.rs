@@ -1,10 +1,15 @@
 #![allow(missing_docs)]
 // temporary while we figure out what should be public in the end
 /// Four section dictionary.
+#[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
+use crate::containers::BytesReader;
+use crate::containers::control_info::DICTIONARY_FOUR;
 use crate::dict_sect_pfc;
+use crate::dictionary::{Dictionary, PendingChecksums};
 use crate::triples::Id;
 use crate::{ControlInfo, DictSectPFC};
 use std::io::BufRead;
+use std::ops::Range;
 #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
 use std::thread::JoinHandle;
 use thiserror::Error;
@@ -44,7 +49,7 @@ pub struct FourSectDict {
 }
 
 /// Designates one of the four sections.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum SectKind {
     /// section for terms that appear as both subject and object
     Shared,
@@ -61,18 +66,18 @@ pub enum SectKind {
 #[error("four sect dict error id_to_string({id},IdKind::{id_kind:?}) in the {sect_kind:?} section, caused by {e}")]
 pub struct ExtractError {
     #[source]
-    e: dict_sect_pfc::ExtractError,
-    id: Id,
-    id_kind: IdKind,
-    sect_kind: SectKind,
+    pub(crate) e: dict_sect_pfc::ExtractError,
+    pub(crate) id: Id,
+    pub(crate) id_kind: IdKind,
+    pub(crate) sect_kind: SectKind,
 }
 
 #[derive(Error, Debug)]
 #[error("four sect dict section error in the {sect_kind:?} section")]
 pub struct DictSectError {
     #[source]
-    e: dict_sect_pfc::Error,
-    sect_kind: SectKind,
+    pub(crate) e: dict_sect_pfc::Error,
+    pub(crate) sect_kind: SectKind,
 }
 
 #[derive(Error, Debug)]
@@ -83,6 +88,8 @@ pub enum Error {
     DictSect(#[from] DictSectError),
     #[error("failed to read FourSectDict: {0}")]
     Other(String),
+    #[error("IO error")]
+    Io(#[from] std::io::Error),
 }
 
 #[derive(Debug, PartialEq, Eq, Hash)]
@@ -149,21 +156,92 @@ impl FourSectDict {
         }
     }
 
+    /// IDs of the terms of the given kind whose string representation starts with the given prefix in ascending order.
+    /// Subject and object IDs combine the matches of the shared section with those of the subject or object section.
+    pub fn search(&self, prefix: &str, id_kind: IdKind) -> impl Iterator<Item = Id> + use<> {
+        let shared_size = self.shared.num_strings();
+        let shift = |range: Range<Id>| range.start + shared_size..range.end + shared_size;
+        // subject and object IDs start with the shared section, predicates have none
+        let (shared, own) = match id_kind {
+            IdKind::Subject => (self.shared.prefix_range(prefix), shift(self.subjects.prefix_range(prefix))),
+            IdKind::Predicate => (0..0, self.predicates.prefix_range(prefix)),
+            IdKind::Object => (self.shared.prefix_range(prefix), shift(self.objects.prefix_range(prefix))),
+        };
+        shared.chain(own)
+    }
+
     /// read the whole dictionary section including control information
     pub fn read<R: BufRead>(reader: &mut R) -> Result<UnvalidatedFourSectDict> {
-        use SectKind::*;
         let dict_ci = ControlInfo::read(reader)?;
-        if dict_ci.format != "<http://purl.org/HDT/hdt#dictionaryFour>" {
+        if dict_ci.format != DICTIONARY_FOUR {
             return Err(Error::Other("Implementation only supports four section dictionaries".to_owned()));
         }
+        Self::read_sections(reader)
+    }
+
+    /// read the four sections following the control information
+    pub(crate) fn read_sections<R: BufRead>(reader: &mut R) -> Result<UnvalidatedFourSectDict> {
+        use SectKind::*;
         let mut f = |sect_kind| DictSectPFC::read(reader).map_err(|e| DictSectError { e, sect_kind });
         Ok(UnvalidatedFourSectDict([f(Shared)?, f(Subject)?, f(Predicate)?, f(Object)?]))
     }
 
+    /// Like `read_sections` but without verifying the checksums, which are added to `pending` instead.
+    pub(crate) fn read_sections_unverified<R: BufRead>(
+        reader: &mut R, pending: &mut PendingChecksums,
+    ) -> Result<Self> {
+        use SectKind::*;
+        let mut f = |sect_kind| pending.read_section(reader, sect_kind);
+        Ok(FourSectDict {
+            shared: f(Shared)?,
+            subjects: f(Subject)?,
+            predicates: f(Predicate)?,
+            objects: f(Object)?,
+        })
+    }
+
+    /// Like `read` but the sections borrow their data from the given reader instead of copying it.
+    #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
+    pub fn read_mapped(reader: &mut BytesReader) -> Result<UnvalidatedFourSectDict> {
+        let dict_ci = ControlInfo::read(reader)?;
+        if dict_ci.format != DICTIONARY_FOUR {
+            return Err(Error::Other("Implementation only supports four section dictionaries".to_owned()));
+        }
+        Self::read_mapped_sections(reader)
+    }
+
+    /// Like `read_sections` but the sections borrow their data from the given reader instead of copying it.
+    #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
+    pub(crate) fn read_mapped_sections(reader: &mut BytesReader) -> Result<UnvalidatedFourSectDict> {
+        use SectKind::*;
+        let mut f = |sect_kind| DictSectPFC::read_mapped(reader).map_err(|e| DictSectError { e, sect_kind });
+        Ok(UnvalidatedFourSectDict([f(Shared)?, f(Subject)?, f(Predicate)?, f(Object)?]))
+    }
+
+    /// Like `read_mapped_sections` but without verifying the checksums, which are added to `pending` instead.
+    #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
+    pub(crate) fn read_mapped_sections_unverified(
+        reader: &mut BytesReader, pending: &mut PendingChecksums,
+    ) -> Result<Self> {
+        use SectKind::*;
+        let mut f = |sect_kind| pending.read_mapped_section(reader, sect_kind);
+        Ok(FourSectDict {
+            shared: f(Shared)?,
+            subjects: f(Subject)?,
+            predicates: f(Predicate)?,
+            objects: f(Object)?,
+        })
+    }
+
     /// write the whole Dictionary including control info and all sections
     pub fn write(&self, write: &mut impl std::io::Write) -> Result<()> {
-        use SectKind::*;
         ControlInfo::four_sect_dict().write(write)?;
+        self.write_sections(write)
+    }
+
+    /// write the four sections without control information
+    pub(crate) fn write_sections(&self, write: &mut impl std::io::Write) -> Result<()> {
+        use SectKind::*;
         self.shared.write(write).map_err(|e| DictSectError { e, sect_kind: Shared })?;
         self.subjects.write(write).map_err(|e| DictSectError { e, sect_kind: Subject })?;
         self.predicates.write(write).map_err(|e| DictSectError { e, sect_kind: Predicate })?;
@@ -180,6 +258,36 @@ impl FourSectDict {
     }
 }
 
+impl Dictionary for FourSectDict {
+    fn id_to_string(&self, id: Id, id_kind: IdKind) -> core::result::Result<String, ExtractError> {
+        FourSectDict::id_to_string(self, id, id_kind)
+    }
+
+    fn string_to_id(&self, s: &str, id_kind: IdKind) -> Id {
+        FourSectDict::string_to_id(self, s, id_kind)
+    }
+
+    fn num_shared(&self) -> usize {
+        self.shared.num_strings()
+    }
+
+    fn num_terms(&self, id_kind: IdKind) -> usize {
+        match id_kind {
+            IdKind::Subject => self.shared.num_strings() + self.subjects.num_strings(),
+            IdKind::Predicate => self.predicates.num_strings(),
+            IdKind::Object => self.shared.num_strings() + self.objects.num_strings(),
+        }
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        FourSectDict::size_in_bytes(self)
+    }
+
+    fn search(&self, prefix: &str, id_kind: IdKind) -> Box<dyn Iterator<Item = Id> + '_> {
+        Box::new(FourSectDict::search(self, prefix, id_kind))
+    }
+}
+
 /// A wrapper to ensure prevent using FourSectDict before its checksums have been validated
 #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
 pub struct UnvalidatedFourSectDict([JoinHandle<dict_sect_pfc::Result<DictSectPFC>>; 4]);
@@ -257,6 +365,20 @@ mod tests {
                 assert_eq!(id, back, "{} id {} -> {} {} -> id {}", name, id, name, s, back);
             }
         }
+        for (kind, prefix) in [
+            (IdKind::Subject, "http://www.snik.eu/ontology/meta/"),
+            (IdKind::Subject, "_:"),
+            (IdKind::Predicate, "http://www.w3.org/2000/01/rdf-schema#"),
+            (IdKind::Object, "http://www.snik.eu/ontology/meta/C"),
+            (IdKind::Object, "\""),
+            (IdKind::Object, "nothing"),
+        ] {
+            let found: Vec<Id> = dict.search(prefix, kind).collect();
+            let expected: Vec<Id> = (1..=Dictionary::num_terms(&dict, kind))
+                .filter(|&id| dict.id_to_string(id, kind).unwrap().starts_with(prefix))
+                .collect();
+            assert_eq!(found, expected, "{kind:?} {prefix}");
+        }
         let mut buf = Vec::new();
         dict.write(&mut buf)?;
         let dict2 = FourSectDict::read(&mut std::io::Cursor::new(buf))?.validate()?;
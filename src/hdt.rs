@@ -1,25 +1,59 @@
 use crate::containers::{ControlInfo, control_info};
 use crate::four_sect_dict::{self, IdKind};
 use crate::header::Header;
-use crate::triples::{Id, ObjectIter, PredicateIter, PredicateObjectIter, SubjectIter, TripleId, TriplesBitmap};
-use crate::{FourSectDict, header};
+use crate::text_index::TextIndex;
+use crate::triples::{Cardinality, Id, ObjectIndex, TripleId, TriplesBitmap};
+use crate::{Dict, Dictionary, header};
 use bytesize::ByteSize;
-use log::{debug, error};
-#[cfg(feature = "cache")]
-use std::fs::File;
-#[cfg(feature = "cache")]
-use std::io::{Seek, SeekFrom, Write};
+use log::{debug, error, warn};
 use std::iter;
 use std::sync::Arc;
 
 pub type Result<T> = core::result::Result<T, Error>;
+/// number of strings per block in the dictionary sections
+const BLOCK_SIZE: usize = 16;
+/// Base IRI of the header when the HDT is not converted from a file.
+const DEFAULT_BASE_IRI: &str = "urn:hdt:dataset";
+/// Extension of the object index files of hdt-cpp and hdt-java, which is appended to the name of the HDT file.
+pub const INDEX_EXT: &str = "index.v1-1";
 
 #[cfg(feature = "cache")]
-const CACHE_EXT: &str = "index.v1-rust-cache";
+#[path = "cache.rs"]
+/// Versioned cache files for faster loading, available only if HDT is built with the experimental `"cache"` feature.
+mod cache;
+#[cfg(feature = "cache")]
+pub use cache::{CacheError, CacheOptions};
 #[cfg(feature = "nt")]
 #[path = "nt.rs"]
-/// Converting N-Triples to HDT, available only if HDT is built with the experimental `"nt"` feature.
+/// Converting RDF to HDT, available only if HDT is built with the experimental `"nt"` feature.
 mod nt;
+#[cfg(feature = "nt")]
+pub use nt::RdfFormat;
+#[cfg(feature = "nt")]
+#[path = "builder.rs"]
+/// Building HDT from triples in memory.
+mod builder;
+#[path = "cat.rs"]
+/// Merging multiple HDTs.
+mod cat;
+#[path = "diff.rs"]
+/// Differences between HDTs.
+mod diff;
+pub use diff::Changes;
+#[path = "load.rs"]
+/// Loading with checksum, index and thread options, progress reporting and cancellation.
+mod load;
+pub use load::{Checksums, LoadOptions, Phase, Progress, ProgressFn};
+#[path = "mutable_hdt.rs"]
+/// Changing the triples of an HDT in memory.
+mod mutable_hdt;
+pub use mutable_hdt::MutableHdt;
+#[cfg(feature = "nt")]
+#[path = "nt_disk.rs"]
+/// Converting large N-Triples files to HDT with bounded memory usage.
+mod nt_disk;
+#[cfg(feature = "nt")]
+pub use builder::HdtBuilder;
 
 /// In-memory representation of an RDF graph loaded from an HDT file.
 /// Allows queries by triple patterns.
@@ -29,9 +63,11 @@ pub struct Hdt {
     // header is not necessary for querying but shouldn't waste too much space and we need it for writing in the future, may also make it optional
     header: Header,
     /// in-memory representation of dictionary
-    pub dict: FourSectDict,
+    pub dict: Dict,
     /// in-memory representation of triples
     pub triples: TriplesBitmap,
+    /// optional full-text index over the literal objects
+    pub(crate) text_index: Option<TextIndex>,
 }
 
 type StringTriple = [Arc<str>; 3];
@@ -52,12 +88,16 @@ pub enum Error {
     ControlInfo(#[from] control_info::Error),
     #[error("failed to read HDT header")]
     Header(#[from] header::Error),
-    #[error("failed to read HDT four section dictionary")]
-    FourSectDict(#[from] four_sect_dict::Error),
+    #[error("failed to read HDT dictionary")]
+    Dictionary(#[from] four_sect_dict::Error),
     #[error("failed to read HDT triples section")]
     Triples(#[from] crate::triples::Error),
+    #[error("failed to extract term from HDT dictionary")]
+    Extract(#[from] four_sect_dict::ExtractError),
     #[error("IO Error")]
     Io(#[from] std::io::Error),
+    #[error("loading cancelled")]
+    Cancelled,
 }
 
 impl Hdt {
@@ -66,9 +106,15 @@ impl Hdt {
         Self::read(reader)
     }
 
+    /// Combines the header, dictionary and triples sections without a full-text index.
+    pub(crate) const fn from_sections(header: Header, dict: Dict, triples: TriplesBitmap) -> Self {
+        Hdt { header, dict, triples, text_index: None }
+    }
+
     /// Creates an immutable HDT instance containing the dictionary and triples from the given reader.
     /// The reader must point to the beginning of the data of an HDT file.
-    /// FourSectionDictionary with DictionarySectionPlainFrontCoding and SPO order is the only supported implementation.
+    /// Supported dictionary implementations are the FourSectionDictionary, including its PSFC variant, and the MultipleSectionDictionary of hdt-java,
+    /// each with DictionarySectionPlainFrontCoding sections.
     /// The format is specified at <https://www.rdfhdt.org/hdt-binary-format/>, however there are some deviations.
     /// The initial HDT specification at <http://www.w3.org/Submission/2011/03/> is outdated and not supported.
     /// # Example
@@ -76,119 +122,87 @@ impl Hdt {
     /// let file = std::fs::File::open("tests/resources/snikmeta.hdt").expect("error opening file");
     /// let hdt = hdt::Hdt::read(std::io::BufReader::new(file)).unwrap();
     /// ```
-    pub fn read<R: std::io::BufRead>(mut reader: R) -> Result<Self> {
-        ControlInfo::read(&mut reader)?;
-        let header = Header::read(&mut reader)?;
-        let unvalidated_dict = FourSectDict::read(&mut reader)?;
-        let triples = TriplesBitmap::read_sect(&mut reader)?;
-        let dict = unvalidated_dict.validate()?;
-        let hdt = Hdt { header, dict, triples };
-        debug!("HDT size in memory {}, details:", ByteSize(hdt.size_in_bytes() as u64));
-        debug!("{hdt:#?}");
-        Ok(hdt)
-    }
-
-    /// Write as N-Triples
-    #[cfg(feature = "sophia")]
-    pub fn write_nt(&self, write: &mut impl std::io::Write) -> std::io::Result<()> {
-        use sophia::api::prelude::TripleSerializer;
-        use sophia::turtle::serializer::nt::NtSerializer;
-        NtSerializer::new(write).serialize_graph(self).map_err(|e| std::io::Error::other(format!("{e}")))?;
-        Ok(())
+    pub fn read<R: std::io::BufRead>(reader: R) -> Result<Self> {
+        Self::read_with(reader, &LoadOptions::default())
     }
 
-    /// Creates an immutable HDT instance containing the dictionary and triples from the Path.
-    /// Will utilize a custom cached TriplesBitmap file if exists or create one if it does not exist.
-    /// The file path must point to the beginning of the data of an HDT file.
-    /// FourSectionDictionary with DictionarySectionPlainFrontCoding and SPO order is the only supported implementation.
-    /// The format is specified at <https://www.rdfhdt.org/hdt-binary-format/>, however there are some deviations.
-    /// The initial HDT specification at <http://www.w3.org/Submission/2011/03/> is outdated and not supported.
+    /// Like [`Hdt::read`] but reads the object index from an index file written by hdt-cpp, hdt-java or [`Hdt::write_index`]
+    /// instead of building it, which is usually stored next to the HDT file with the [`INDEX_EXT`] extension.
+    /// If the index file does not belong to the HDT file, a warning is logged and the object index is built as in [`Hdt::read`].
     /// # Example
     /// ```
-    /// let hdt = hdt::Hdt::read_from_path(std::path::Path::new("tests/resources/snikmeta.hdt")).unwrap();
+    /// use std::{fs::File, io::BufReader};
+    /// let hdt = hdt::Hdt::read(BufReader::new(File::open("tests/resources/snikmeta.hdt").unwrap())).unwrap();
+    /// let mut index = Vec::new();
+    /// hdt.write_index(&mut index).unwrap();
+    /// let file = File::open("tests/resources/snikmeta.hdt").unwrap();
+    /// let hdt = hdt::Hdt::read_with_index(BufReader::new(file), index.as_slice()).unwrap();
     /// ```
-    #[cfg(feature = "cache")]
-    pub fn read_from_path(f: &std::path::Path) -> Result<Self> {
-        use log::warn;
-
-        let source = File::open(f)?;
-        let mut reader = std::io::BufReader::new(source);
+    pub fn read_with_index<R: std::io::BufRead, I: std::io::BufRead>(
+        mut reader: R, mut index_reader: I,
+    ) -> Result<Self> {
         ControlInfo::read(&mut reader)?;
         let header = Header::read(&mut reader)?;
-        let unvalidated_dict = FourSectDict::read(&mut reader)?;
-        let mut abs_path = std::fs::canonicalize(f)?;
-        let _ = abs_path.pop();
-        let index_file_name = format!("{}.{CACHE_EXT}", f.file_name().unwrap().to_str().unwrap());
-        let index_file_path = abs_path.join(index_file_name);
-        let triples = if index_file_path.exists() {
-            let pos = reader.stream_position()?;
-            match Self::load_with_cache(&mut reader, &index_file_path, header.length) {
-                Ok(triples) => triples,
-                Err(e) => {
-                    warn!("error loading cache, overwriting: {e}");
-                    reader.seek(SeekFrom::Start(pos))?;
-                    Self::load_without_cache(&mut reader, &index_file_path, header.length)?
-                }
-            }
-        } else {
-            Self::load_without_cache(&mut reader, &index_file_path, header.length)?
-        };
-
+        let unvalidated_dict = Dict::read(&mut reader)?;
+        let triples = TriplesBitmap::read_sect_with_index(&mut reader, &mut index_reader)?;
         let dict = unvalidated_dict.validate()?;
-        let hdt = Hdt { header, dict, triples };
+        let hdt = Hdt::from_sections(header, dict, triples);
         debug!("HDT size in memory {}, details:", ByteSize(hdt.size_in_bytes() as u64));
         debug!("{hdt:#?}");
         Ok(hdt)
     }
 
-    #[cfg(feature = "cache")]
-    fn load_without_cache<R: std::io::BufRead>(
-        mut reader: R, index_file_path: &std::path::PathBuf, header_length: usize,
-    ) -> Result<TriplesBitmap> {
-        use log::warn;
-
-        debug!("no cache detected, generating index");
-        let triples = TriplesBitmap::read_sect(&mut reader)?;
-        debug!("index generated, saving cache to {}", index_file_path.display());
-        if let Err(e) = Self::write_cache(index_file_path, &triples, header_length) {
-            warn!("error trying to save cache to file: {e}");
+    /// Reads the HDT file at the given path using the index file next to it, e.g. `example.hdt.index.v1-1`, see [`Hdt::read_with_index`].
+    /// The index of a compressed file such as `example.hdt.gz` is the one of the uncompressed file.
+    /// If there is no index file yet, it is created, so that it can also be used by hdt-cpp and hdt-java.
+    /// Failing to write the index file is logged but not an error.
+    pub fn read_from_path_with_index(f: &std::path::Path) -> Result<Self> {
+        use std::fs::File;
+        use std::io::BufReader;
+
+        // the index belongs to the uncompressed HDT, e.g. `example.hdt.index.v1-1` for `example.hdt.gz`
+        let mut index_path = crate::compression::strip_extension(f).into_os_string();
+        index_path.push(format!(".{INDEX_EXT}"));
+        let index_path = std::path::PathBuf::from(index_path);
+        let reader = crate::compression::open(f)?;
+        match File::open(&index_path) {
+            Ok(index_file) => Self::read_with_index(reader, BufReader::new(index_file)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let hdt = Self::read(reader)?;
+                debug!("saving index to {}", index_path.display());
+                let written = File::create(&index_path)
+                    .map_err(Error::from)
+                    .and_then(|file| hdt.write_index(&mut std::io::BufWriter::new(file)));
+                if let Err(e) = written {
+                    warn!("error trying to save index to {}: {e}", index_path.display());
+                }
+                Ok(hdt)
+            }
+            Err(e) => Err(e.into()),
         }
-        Ok(triples)
     }
 
-    #[cfg(feature = "cache")]
-    fn load_with_cache<R: std::io::BufRead>(
-        mut reader: R, index_file_path: &std::path::PathBuf, header_length: usize,
-    ) -> core::result::Result<TriplesBitmap, Box<dyn std::error::Error>> {
-        use std::io::Read;
-        // load cached index
-        debug!("hdt file cache detected, loading from {}", index_file_path.display());
-        let index_source = File::open(index_file_path)?;
-        let mut index_reader = std::io::BufReader::new(index_source);
-        let triples_ci = ControlInfo::read(&mut reader)?;
-        // we cannot rely on the numTriples property being present, see https://github.com/rdfhdt/hdt-cpp/issues/289
-        // let num_triples = triples_ci.get("numTriples").expect("numTriples key missing in triples CI");
-        // thus we use the number of bytes of the header data
-        let mut buf = [0u8; size_of::<usize>()];
-        index_reader.read_exact(&mut buf)?;
-        if header_length != usize::from_le_bytes(buf) {
-            return Err("failed index validation".into());
-        }
-        let triples = TriplesBitmap::load_cache(&mut index_reader, &triples_ci)?;
-        Ok(triples)
+    /// Creates an immutable HDT instance by memory-mapping the HDT file at the given path.
+    /// In contrast to [`Hdt::read`], the dictionary sections and the triple bitmaps and sequences are not copied onto the heap but borrowed from the mapping,
+    /// so that the operating system can page them in and out as needed.
+    /// Only the derived indexes, i.e. the rank directories of the bitmaps, the wavelet matrix and the object index, are constructed in memory.
+    /// The file must not be modified while it is mapped.
+    /// See [`Hdt::map_with`] to build the object index lazily or skip the checksums.
+    /// # Example
+    /// ```
+    /// let hdt = hdt::Hdt::map(std::path::Path::new("tests/resources/snikmeta.hdt")).unwrap();
+    /// ```
+    #[cfg(feature = "mmap")]
+    pub fn map(f: &std::path::Path) -> Result<Self> {
+        Self::map_with(f, &LoadOptions::default())
     }
 
-    #[cfg(feature = "cache")]
-    /// Writes a custom cache file to improve load times. This cache file is usuable only by
-    /// this library and is not intended to be used with hdt-cpp or hdt-java versions of the HDT tooling
-    pub fn write_cache(
-        index_file_path: &std::path::PathBuf, triples: &TriplesBitmap, header_length: usize,
-    ) -> core::result::Result<(), Box<dyn std::error::Error>> {
-        let new_index_file = File::create(index_file_path)?;
-        let mut writer = std::io::BufWriter::new(new_index_file);
-        writer.write_all(&header_length.to_le_bytes())?;
-        bincode::serde::encode_into_std_write(triples, &mut writer, bincode::config::standard())?;
-        writer.flush()?;
+    /// Write as N-Triples
+    #[cfg(feature = "sophia")]
+    pub fn write_nt(&self, write: &mut impl std::io::Write) -> std::io::Result<()> {
+        use sophia::api::prelude::TripleSerializer;
+        use sophia::turtle::serializer::nt::NtSerializer;
+        NtSerializer::new(write).serialize_graph(self).map_err(|e| std::io::Error::other(format!("{e}")))?;
         Ok(())
     }
 
@@ -201,9 +215,93 @@ impl Hdt {
         Ok(())
     }
 
+    /// Changes when the object index is built, see [`TriplesBitmap::set_object_index`].
+    pub fn set_object_index(&mut self, object_index: ObjectIndex) {
+        self.triples.set_object_index(object_index);
+    }
+
+    /// Writes the object index in the index file format of hdt-cpp and hdt-java, see [`Hdt::read_with_index`].
+    pub fn write_index(&self, write: &mut impl std::io::Write) -> Result<()> {
+        self.triples.write_index(write)?;
+        write.flush()?;
+        Ok(())
+    }
+
     /// Recursive size in bytes on the heap.
     pub fn size_in_bytes(&self) -> usize {
-        self.dict.size_in_bytes() + self.triples.size_in_bytes()
+        self.dict.size_in_bytes()
+            + self.triples.size_in_bytes()
+            + self.text_index.as_ref().map_or(0, TextIndex::size_in_bytes)
+    }
+
+    /// Populate HDT header fields.
+    /// Some fields may be optional, populating same triples as those in C++ version for now.
+    /// The original size is only known when converting from a file.
+    fn fill_header(
+        &mut self, base_iri: &str, source: Option<&std::path::Path>, block_size: usize, num_triples: usize,
+    ) -> std::io::Result<()> {
+        use crate::containers::rdf::Term::Literal as Lit;
+        use crate::containers::rdf::{Id, Literal, Term, Triple};
+        use crate::vocab::*;
+        use std::io::Write;
+
+        macro_rules! literal {
+            ($s:expr, $p:expr, $o:expr) => {
+                self.header.body.insert(Triple::new($s.clone(), $p.to_owned(), Lit(Literal::new($o.to_string()))));
+            };
+        }
+        macro_rules! insert_id {
+            ($s:expr, $p:expr, $o:expr) => {
+                self.header.body.insert(Triple::new($s.clone(), $p.to_owned(), Term::Id($o.clone())));
+            };
+        }
+        let base = Id::Named(base_iri.to_owned());
+
+        literal!(base, RDF_TYPE, HDT_CONTAINER);
+        literal!(base, RDF_TYPE, VOID_DATASET);
+        literal!(base, VOID_TRIPLES, num_triples);
+        literal!(base, VOID_PROPERTIES, self.dict.num_terms(IdKind::Predicate));
+        let [d_s, d_o] = [IdKind::Subject, IdKind::Object].map(|k| self.dict.num_terms(k));
+        literal!(base, VOID_DISTINCT_SUBJECTS, d_s);
+        literal!(base, VOID_DISTINCT_OBJECTS, d_o);
+        // // TODO: Add more VOID Properties. E.g. void:classes
+
+        // // Structure
+        let stats_id = Id::Blank("statistics".to_owned());
+        let pub_id = Id::Blank("publicationInformation".to_owned());
+        let format_id = Id::Blank("format".to_owned());
+        let dict_id = Id::Blank("dictionary".to_owned());
+        let triples_id = Id::Blank("triples".to_owned());
+        insert_id!(base, HDT_STATISTICAL_INFORMATION, stats_id);
+        insert_id!(base, HDT_STATISTICAL_INFORMATION, pub_id);
+        insert_id!(base, HDT_FORMAT_INFORMATION, format_id);
+        insert_id!(format_id, HDT_DICTIONARY, dict_id);
+        insert_id!(format_id, HDT_TRIPLES, triples_id);
+        // DICTIONARY
+        literal!(dict_id, HDT_DICT_SHARED_SO, self.dict.num_shared());
+        literal!(dict_id, HDT_DICT_MAPPING, "1");
+        literal!(dict_id, HDT_DICT_SIZE_STRINGS, ByteSize(self.dict.size_in_bytes() as u64));
+        literal!(dict_id, HDT_DICT_BLOCK_SIZE, block_size);
+        // TRIPLES
+        literal!(triples_id, DC_TERMS_FORMAT, HDT_TYPE_BITMAP);
+        literal!(triples_id, HDT_NUM_TRIPLES, num_triples);
+        literal!(triples_id, HDT_TRIPLES_ORDER, format!("{:?}", self.triples.order()));
+        // // Sizes
+        if let Some(path) = source {
+            let meta = std::fs::File::open(path)?.metadata()?;
+            literal!(stats_id, HDT_ORIGINAL_SIZE, meta.len());
+        }
+        // a few bytes off because that literal itself is not counted
+        literal!(stats_id, HDT_SIZE, ByteSize(self.size_in_bytes() as u64));
+        // exclude for now to skip dependency on chrono
+        //let datetime_str = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%z").to_string();
+        //literal!(pub_id,DC_TERMS_ISSUED,datetime_str);
+        let mut buf = Vec::<u8>::new();
+        for triple in &self.header.body {
+            writeln!(buf, "{triple}")?;
+        }
+        self.header.length = buf.len();
+        Ok(())
     }
 
     /// An iterator visiting *all* triples as strings in order.
@@ -244,8 +342,9 @@ impl Hdt {
         let p_owned = p.to_owned();
         let o_owned = o.to_owned();
         Box::new(
-            PredicateObjectIter::new(&self.triples, pid, oid)
-                .map(move |sid| self.dict.id_to_string(sid, IdKind::Subject))
+            self.triples
+                .triples_with_pattern([0, pid, oid])
+                .map(move |t| self.dict.id_to_string(t[0], IdKind::Subject))
                 .filter_map(move |r| {
                     r.map_err(|e| error!("Error on triple with property {p_owned} and object {o_owned}: {e}")).ok()
                 }),
@@ -276,37 +375,34 @@ impl Hdt {
         let mut cache = TripleCache::new(self);
         match pattern {
             [Some(s), Some(p), Some(o)] => {
-                if SubjectIter::with_pattern(&self.triples, [s.1, p.1, o.1]).next().is_some() {
+                if self.triples.triples_with_pattern([s.1, p.1, o.1]).next().is_some() {
                     Box::new(iter::once([s.0, p.0, o.0]))
                 } else {
                     Box::new(iter::empty())
                 }
             }
-            [Some(s), Some(p), None] => {
-                Box::new(SubjectIter::with_pattern(&self.triples, [s.1, p.1, 0]).map(move |t| {
-                    [s.0.clone(), p.0.clone(), Arc::from(self.dict.id_to_string(t[2], IdKind::Object).unwrap())]
-                }))
-            }
-            [Some(s), None, Some(o)] => {
-                Box::new(SubjectIter::with_pattern(&self.triples, [s.1, 0, o.1]).map(move |t| {
-                    [s.0.clone(), Arc::from(self.dict.id_to_string(t[1], IdKind::Predicate).unwrap()), o.0.clone()]
-                }))
-            }
+            [Some(s), Some(p), None] => Box::new(self.triples.triples_with_pattern([s.1, p.1, 0]).map(move |t| {
+                [s.0.clone(), p.0.clone(), Arc::from(self.dict.id_to_string(t[2], IdKind::Object).unwrap())]
+            })),
+            [Some(s), None, Some(o)] => Box::new(self.triples.triples_with_pattern([s.1, 0, o.1]).map(move |t| {
+                [s.0.clone(), Arc::from(self.dict.id_to_string(t[1], IdKind::Predicate).unwrap()), o.0.clone()]
+            })),
             [Some(s), None, None] => Box::new(
-                SubjectIter::with_pattern(&self.triples, [s.1, 0, 0])
+                self.triples
+                    .triples_with_pattern([s.1, 0, 0])
                     .map(move |t| [s.0.clone(), cache.get(1, t[1]).unwrap(), cache.get(2, t[2]).unwrap()]),
             ),
-            [None, Some(p), Some(o)] => {
-                Box::new(PredicateObjectIter::new(&self.triples, p.1, o.1).map(move |sid| {
-                    [Arc::from(self.dict.id_to_string(sid, IdKind::Subject).unwrap()), p.0.clone(), o.0.clone()]
-                }))
-            }
+            [None, Some(p), Some(o)] => Box::new(self.triples.triples_with_pattern([0, p.1, o.1]).map(move |t| {
+                [Arc::from(self.dict.id_to_string(t[0], IdKind::Subject).unwrap()), p.0.clone(), o.0.clone()]
+            })),
             [None, Some(p), None] => Box::new(
-                PredicateIter::new(&self.triples, p.1)
+                self.triples
+                    .triples_with_pattern([0, p.1, 0])
                     .map(move |t| [cache.get(0, t[0]).unwrap(), p.0.clone(), cache.get(2, t[2]).unwrap()]),
             ),
             [None, None, Some(o)] => Box::new(
-                ObjectIter::new(&self.triples, o.1)
+                self.triples
+                    .triples_with_pattern([0, 0, o.1])
                     .map(move |t| [cache.get(0, t[0]).unwrap(), cache.get(1, t[1]).unwrap(), o.0.clone()]),
             ),
             [None, None, None] => Box::new(self.triples_all()),
@@ -332,16 +428,40 @@ impl Hdt {
     /// Get all internal triple IDs that fit the given triple patterns, where 0 stands for a variable.
     /// Used for specific optimizations, call triples_with_pattern instead to get actual string results.
     pub fn triple_ids_with_id_pattern<'a>(&'a self, pattern: TripleId) -> Box<dyn Iterator<Item = TripleId> + 'a> {
-        let ts = &self.triples;
-        let [s, p, o] = pattern;
-        // can't use slice: half_open_range_patterns_in_slices is still unstable, see https://github.com/rust-lang/rust/issues/67264
-        match (s, p, o) {
-            (1.., _, _) => Box::new(SubjectIter::with_pattern(ts, [s, p, o]).map(move |t| [s, t[1], t[2]])),
-            (0, 1.., 1..) => Box::new(PredicateObjectIter::new(ts, p, o).map(move |sid| [sid, p, o])),
-            (0, 1.., 0) => Box::new(PredicateIter::new(ts, p).map(move |t| [t[0], p, t[2]])),
-            (0, 0, 1..) => Box::new(ObjectIter::new(ts, o).map(move |t| [t[0], t[1], o])),
-            (0, 0, 0) => Box::new(self.triples.into_iter()),
-        }
+        self.triples.triples_with_pattern(pattern)
+    }
+
+    /// Get a page of the internal triple IDs that fit the given triple pattern, where 0 stands for a variable,
+    /// skipping the first `offset` ones without iterating over them and yielding at most `limit` ones.
+    /// Together with [`Hdt::estimate_count`], this allows paginating large results, as in Linked Data Fragments.
+    /// # Example
+    /// ```
+    /// use hdt::Hdt;
+    /// let file = std::fs::File::open("tests/resources/snikmeta.hdt").unwrap();
+    /// let hdt = Hdt::read(std::io::BufReader::new(file)).unwrap();
+    /// let page: Vec<_> = hdt.triple_ids_with_pattern_page([0, 0, 0], 100, 10).collect();
+    /// assert_eq!(page, hdt.triple_ids_with_id_pattern([0, 0, 0]).collect::<Vec<_>>()[100..110]);
+    /// ```
+    pub fn triple_ids_with_pattern_page(
+        &self, pattern: TripleId, offset: usize, limit: usize,
+    ) -> Box<dyn Iterator<Item = TripleId> + '_> {
+        self.triples.triples_with_pattern_page(pattern, offset, limit)
+    }
+
+    /// Number of triples that fit the given triple ID pattern, where 0 stands for a variable, without iterating over them.
+    /// See [`TriplesBitmap::estimate_count`] for which patterns are only estimated.
+    /// # Example
+    /// ```
+    /// use hdt::{Dictionary, Hdt, IdKind};
+    /// let file = std::fs::File::open("tests/resources/snikmeta.hdt").unwrap();
+    /// let hdt = Hdt::read(std::io::BufReader::new(file)).unwrap();
+    /// let s = hdt.dict.string_to_id("http://www.snik.eu/ontology/meta/Top", IdKind::Subject);
+    /// let count = hdt.estimate_count([s, 0, 0]);
+    /// assert!(count.is_exact());
+    /// assert_eq!(count.estimate, hdt.triple_ids_with_id_pattern([s, 0, 0]).count());
+    /// ```
+    pub fn estimate_count(&self, pattern: TripleId) -> Cardinality {
+        self.triples.estimate_count(pattern)
     }
 }
 
@@ -410,6 +530,49 @@ pub mod tests {
         Ok(())
     }
 
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn map() -> Result<()> {
+        init();
+        let hdt = Hdt::map(std::path::Path::new("tests/resources/snikmeta.hdt"))?;
+        snikmeta_check(&hdt)?;
+        let Dict::Four(dict) = &hdt.dict else { panic!("snikmeta should have a four section dictionary") };
+        assert!(dict.shared.packed_data.is_mapped());
+        assert!(hdt.triples.bitmap_y.is_mapped() && hdt.triples.adjlist_z.bitmap.is_mapped());
+        assert!(hdt.size_in_bytes() < snikmeta()?.size_in_bytes());
+        // mapped sections are written back unchanged
+        let mut buf = Vec::<u8>::new();
+        hdt.write(&mut buf)?;
+        snikmeta_check(&Hdt::read(std::io::Cursor::new(buf))?)?;
+        Ok(())
+    }
+
+    #[test]
+    fn index() -> Result<()> {
+        init();
+        let hdt = snikmeta()?;
+        let mut index = Vec::new();
+        hdt.write_index(&mut index)?;
+        let file = File::open("tests/resources/snikmeta.hdt")?;
+        let indexed = Hdt::read_with_index(std::io::BufReader::new(file), index.as_slice())?;
+        snikmeta_check(&indexed)?;
+        let op_index = |hdt: &Hdt| {
+            let op = hdt.triples.op_index().unwrap();
+            let firsts: Vec<bool> = (0..op.bitmap.len()).map(|i| op.bitmap.at_last_sibling(i)).collect();
+            (op.sequence.into_iter().collect::<Vec<_>>(), firsts)
+        };
+        assert_eq!(op_index(&hdt), op_index(&indexed));
+        let mut index2 = Vec::new();
+        indexed.write_index(&mut index2)?;
+        assert_eq!(index, index2);
+        // an index file that doesn't belong to the HDT file is ignored
+        let mut other_index = Vec::new();
+        TriplesBitmap::from_triples(&[[1, 1, 1]]).write_index(&mut other_index)?;
+        let file = File::open("tests/resources/snikmeta.hdt")?;
+        snikmeta_check(&Hdt::read_with_index(std::io::BufReader::new(file), other_index.as_slice())?)?;
+        Ok(())
+    }
+
     // make sure loading with cache works under different circumstances
     // e.g. clear cache, prexisting cache, stale cache
     #[cfg(feature = "cache")]
@@ -420,7 +583,7 @@ pub mod tests {
         init();
         // start with an empty cache
         let filename = "tests/resources/snikmeta.hdt";
-        let cachename = format!("{filename}.{CACHE_EXT}");
+        let cachename = format!("{filename}.{}", cache::CACHE_EXT);
         let path = Path::new(filename);
         let path_cache = Path::new(&cachename);
         // force fresh cache
@@ -444,7 +607,7 @@ pub mod tests {
                 hdt_empty.write(&mut writer)?;
             }
             // we don't care about the empty HDT, we just need it to create the cache
-            let filename_empty_cache = format!("{filename_empty_hdt}.{CACHE_EXT}");
+            let filename_empty_cache = format!("{filename_empty_hdt}.{}", cache::CACHE_EXT);
             let path_empty_cache = Path::new(&filename_empty_cache);
             let _ = remove_file(path_empty_cache);
             Hdt::read_from_path(path_empty_hdt)?;
@@ -463,10 +626,10 @@ pub mod tests {
         //assert_eq!();
         let v: Vec<StringTriple> = hdt.triples_all().collect();
         assert_eq!(v.len(), 328);
-        assert_eq!(hdt.dict.shared.num_strings, 43);
-        assert_eq!(hdt.dict.subjects.num_strings, 6);
-        assert_eq!(hdt.dict.predicates.num_strings, 23);
-        assert_eq!(hdt.dict.objects.num_strings, 133);
+        assert_eq!(hdt.dict.num_shared(), 43);
+        assert_eq!(hdt.dict.num_terms(IdKind::Subject), 43 + 6);
+        assert_eq!(hdt.dict.num_terms(IdKind::Predicate), 23);
+        assert_eq!(hdt.dict.num_terms(IdKind::Object), 43 + 133);
         assert_eq!(v, hdt.triples_with_pattern(None, None, None).collect::<Vec<_>>(), "all triples not equal ???");
         assert_ne!(0, hdt.dict.string_to_id("http://www.snik.eu/ontology/meta", IdKind::Subject));
         for uri in ["http://www.snik.eu/ontology/meta/Top", "http://www.snik.eu/ontology/meta", "doesnotexist"] {
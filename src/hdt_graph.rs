@@ -1,7 +1,8 @@
 // //! *This module is available only if HDT is built with the `"sophia"` feature, included by default.*
+use crate::Dictionary;
 use crate::four_sect_dict::IdKind;
 use crate::hdt::Hdt;
-use crate::triples::{Id, ObjectIter, PredicateIter, PredicateObjectIter, SubjectIter};
+use crate::triples::Id;
 use log::debug;
 use sophia::api::graph::Graph;
 use sophia::api::term::matcher::TermMatcher;
@@ -46,7 +47,7 @@ fn unpack_matcher<T: TermMatcher>(hdt: &Hdt, tm: &T, kind: IdKind) -> Option<Hdt
 
 /// Create the correct Sophia term for a given resource string.
 /// Slow, use the appropriate method if you know which type (Literal, URI, or blank node) the string has.
-fn auto_term(s: &str) -> io::Result<HdtTerm> {
+pub fn auto_term(s: &str) -> io::Result<HdtTerm> {
     match s.chars().next() {
         None => Err(Error::new(ErrorKind::InvalidData, "empty input")),
         Some('"') => match s.rfind('"') {
@@ -87,7 +88,7 @@ fn auto_term(s: &str) -> io::Result<HdtTerm> {
 // Convert a SimpleTerm into the HDT String format.
 // Sophia doesn't include the _: prefix for blank node strings but HDT expects it
 // not needed for property terms, as they can't be blank nodes
-fn term_string(t: &HdtTerm) -> String {
+pub(crate) fn term_string(t: &HdtTerm) -> String {
     match t {
         HdtTerm::BlankNode(b) => "_:".to_owned() + b.as_str(),
         HdtTerm::Iri(i) => i.as_str().to_owned(),
@@ -102,6 +103,14 @@ fn term_string(t: &HdtTerm) -> String {
     }
 }
 
+/// Convert any supported Sophia term into the HDT String format.
+/// Returns an error of kind `InvalidInput` for terms that cannot be stored in HDT, such as variables or quoted triples.
+pub(crate) fn sophia_term_string<T: Term>(t: T) -> io::Result<String> {
+    HdtTerm::try_from(t).map(|t| term_string(&t)).ok_or_else(|| {
+        Error::new(ErrorKind::InvalidInput, "only IRIs, blank nodes and literals can be stored in HDT")
+    })
+}
+
 impl Graph for Hdt {
     type Triple<'a> = [HdtTerm; 3];
     type Error = Infallible; // infallible for now, figure out what to put here later
@@ -151,45 +160,52 @@ impl Graph for Hdt {
         let Some(xoo) = unpack_matcher(self, &om, IdKind::Object) else { return Box::new(iter::empty()) };
         // TODO: improve error handling
         match (xso, xpo, xoo) {
-            //if SubjectIter::with_pattern(&self.triples, [s.1, p.1, o.1]).next().is_some() { // always true
+            //if self.triples.triples_with_pattern([s.1, p.1, o.1]).next().is_some() { // always true
             (Constant(s), Constant(p), Constant(o)) => Box::new(iter::once(Ok([s.0, p.0, o.0]))),
             (Constant(s), Constant(p), Other) => Box::new(
-                SubjectIter::with_pattern(&self.triples, [s.1, p.1, 0])
+                self.triples
+                    .triples_with_pattern([s.1, p.1, 0])
                     .map(|tid| auto_term(&self.dict.id_to_string(tid[2], IdKind::Object).unwrap()).unwrap())
                     .filter(move |term| om.matches(term))
                     .map(move |term| Ok([s.0.clone(), p.0.clone(), term])),
             ),
             (Constant(s), Other, Constant(o)) => Box::new(
-                SubjectIter::with_pattern(&self.triples, [s.1, 0, o.1])
+                self.triples
+                    .triples_with_pattern([s.1, 0, o.1])
                     .map(|t| id_term(self, t[1], IdKind::Predicate))
                     .filter(move |term| pm.matches(term))
                     .map(move |term| Ok([s.0.clone(), term, o.0.clone()])),
             ),
             (Constant(s), Other, Other) => Box::new(
-                SubjectIter::with_pattern(&self.triples, [s.1, 0, 0])
+                self.triples
+                    .triples_with_pattern([s.1, 0, 0])
                     .map(move |t| [id_term(self, t[1], IdKind::Predicate), id_term(self, t[2], IdKind::Object)])
                     .filter(move |[pt, ot]| pm.matches(pt) && om.matches(ot))
                     .map(move |[pt, ot]| Ok([s.0.clone(), pt, ot])),
             ),
             (Other, Constant(p), Constant(o)) => Box::new(
-                PredicateObjectIter::new(&self.triples, p.1, o.1)
-                    .map(|sid| id_term(self, sid, IdKind::Subject))
+                self.triples
+                    .triples_with_pattern([0, p.1, o.1])
+                    .map(|t| id_term(self, t[0], IdKind::Subject))
                     .filter(move |term| sm.matches(term))
                     .map(move |term| Ok([term, p.0.clone(), o.0.clone()])),
             ),
             (Other, Constant(p), Other) => Box::new(
-                PredicateIter::new(&self.triples, p.1)
+                self.triples
+                    .triples_with_pattern([0, p.1, 0])
                     .map(move |t| [id_term(self, t[0], IdKind::Subject), id_term(self, t[2], IdKind::Object)])
                     .filter(move |[st, ot]| sm.matches(st) && om.matches(ot))
                     .map(move |[st, ot]| Ok([st, p.0.clone(), ot])),
             ),
-            (Other, Other, Constant(o)) => Box::new(ObjectIter::new(&self.triples, o.1).map(move |t| {
-                Ok([
-                    auto_term(&Arc::from(self.dict.id_to_string(t[0], IdKind::Subject).unwrap())).unwrap(),
-                    id_term(self, t[1], IdKind::Predicate),
-                    o.0.clone(),
-                ])
-            })),
+            (Other, Other, Constant(o)) => {
+                Box::new(self.triples.triples_with_pattern([0, 0, o.1]).map(move |t| {
+                    Ok([
+                        auto_term(&Arc::from(self.dict.id_to_string(t[0], IdKind::Subject).unwrap())).unwrap(),
+                        id_term(self, t[1], IdKind::Predicate),
+                        o.0.clone(),
+                    ])
+                }))
+            }
             (Other, Other, Other) => Box::new(
                 self.triples_all()
                     .map(move |[s, p, o]| {
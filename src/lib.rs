@@ -36,6 +36,13 @@ Creating and/or loading a HDT file leveraging a custom cache:
 ```no_run
 let hdt = hdt::Hdt::read_from_path(std::path::Path::new("tests/resources/snikmeta.hdt")).unwrap();
 ``` 
+
+Storing the cache in a separate directory, including the dictionary:
+
+```no_run
+let options = hdt::CacheOptions { dir: Some("/var/cache/hdt".into()), dictionary: true };
+let hdt = hdt::Hdt::read_from_path_with_cache(std::path::Path::new("tests/resources/snikmeta.hdt"), &options).unwrap();
+```
 "#
 )]
 #![cfg_attr(
@@ -89,10 +96,18 @@ fn query(hdt: Hdt)
 #![allow(clippy::unsafe_derive_deserialize)]
 // multiple versions of syn crate in transitive dependencies
 #![allow(clippy::multiple_crate_versions)]
+/// Basic graph pattern evaluation on triple IDs.
+pub mod bgp;
+/// Decompression of gzip, bzip2, Zstandard and xz compressed input.
+pub mod compression;
 /// Types for storing and reading data.
 pub mod containers;
 /// Types for representing dictionaries.
 pub mod dict_sect_pfc;
+/// Dictionary trait and the supported dictionary formats.
+pub mod dictionary;
+#[cfg(any(feature = "sophia", feature = "sparql"))]
+mod escape;
 /// Types for representing a four section dictionary
 pub mod four_sect_dict;
 /// Types for representing triple sections.
@@ -104,9 +119,16 @@ pub use sophia;
 pub mod hdt_graph;
 /// Types for representing the header.
 pub mod header;
+/// Types for representing a multiple section dictionary
+pub mod mult_sect_dict;
 #[cfg(feature = "sparql")]
 /// SPARQL queries.
 pub mod sparql;
+/// Full-text index over literal objects.
+pub mod text_index;
+#[cfg(feature = "sophia")]
+/// Triple Pattern Fragments for Linked Data Fragments servers.
+pub mod tpf;
 /// Types for representing and querying triples.
 pub mod triples;
 /// Constants for triple terms
@@ -114,9 +136,17 @@ pub mod vocab;
 #[cfg(target_arch = "wasm32")]
 pub mod wasm;
 
-pub use crate::hdt::Hdt;
+#[cfg(all(feature = "mmap", any(target_arch = "wasm32", target_arch = "wasm64")))]
+compile_error!("the mmap feature is not available on WebAssembly targets");
+
+#[cfg(feature = "cache")]
+pub use crate::hdt::{CacheError, CacheOptions};
+pub use crate::hdt::{Checksums, Hdt, LoadOptions, MutableHdt, Phase, Progress};
+#[cfg(feature = "nt")]
+pub use crate::hdt::{HdtBuilder, RdfFormat};
 use containers::ControlInfo;
 use dict_sect_pfc::DictSectPFC;
+pub use dictionary::{Dict, Dictionary};
 use four_sect_dict::FourSectDict;
 pub use four_sect_dict::IdKind;
 
@@ -0,0 +1,428 @@
+//! Options for loading an HDT that control checksum validation, index construction and parallelism
+//! and report the progress of loading to a callback that can cancel it.
+use super::{Error, Hdt, Result};
+use crate::Dict;
+use crate::containers::ControlInfo;
+#[cfg(feature = "mmap")]
+use crate::containers::{Bytes, BytesReader};
+use crate::dictionary::PendingChecksums;
+use crate::header::Header;
+use crate::triples::{ObjectIndex, TriplesBitmap, TriplesLayers};
+use bytesize::ByteSize;
+use log::{debug, error};
+use std::fmt;
+use std::io::{self, BufRead, Read};
+use std::ops::ControlFlow;
+#[cfg(feature = "mmap")]
+use std::path::Path;
+use std::sync::Arc;
+
+/// Number of bytes read between two progress reports within the same phase.
+const REPORT_INTERVAL: u64 = 1 << 20;
+
+/// When the checksums of the dictionary sections, which make up most of an HDT file, are verified.
+/// The small checksums of the headers and of the triples section are always verified while reading.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Checksums {
+    /// Verify the checksums before returning, loading fails if one of them is invalid.
+    #[default]
+    Verify,
+    /// Verify the checksums in a background thread after returning, an invalid checksum is logged as an error
+    /// and reported to the progress callback as [`Phase::Verified`].
+    /// Behaves like [`Checksums::Verify`] on WASM.
+    Background,
+    /// Don't verify the checksums, for example for trusted files that are loaded often.
+    Skip,
+}
+
+/// Stage of loading an HDT that is reported to the progress callback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Reading the global control information and the header.
+    Header,
+    /// Reading the dictionary sections.
+    Dictionary,
+    /// Reading the triples section and building the wavelet matrix.
+    Triples,
+    /// Building the object index according to [`LoadOptions::object_index`].
+    Indexes,
+    /// Verifying the checksums of the dictionary sections, only with [`Checksums::Verify`],
+    /// which starts in a separate thread after the dictionary is read unless on WASM.
+    Checksums,
+    /// Loading is finished, cancelling has no effect anymore.
+    Done,
+    /// The checksums were verified in the background with [`Checksums::Background`], reported after [`Phase::Done`].
+    Verified {
+        /// Whether all checksums are valid.
+        valid: bool,
+    },
+}
+
+/// Progress of loading an HDT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// The current phase, which is reported at least once when it starts.
+    pub phase: Phase,
+    /// Number of bytes read from the reader so far.
+    pub bytes_read: u64,
+}
+
+/// Callback that receives the progress of loading and can cancel it by returning [`ControlFlow::Break`].
+pub type ProgressFn = dyn Fn(Progress) -> ControlFlow<()> + Send + Sync;
+
+/// Options for loading an HDT, see [`Hdt::read_with`].
+#[derive(Clone, Default)]
+pub struct LoadOptions {
+    /// When the checksums of the dictionary sections are verified.
+    pub checksums: Checksums,
+    /// When the object index for patterns with a bound object and unbound subject is built.
+    pub object_index: ObjectIndex,
+    /// Maximum number of threads used to verify the checksums, 0 uses the available parallelism.
+    pub threads: usize,
+    /// Called at the start of each phase, about every megabyte while reading
+    /// and about every million entries while building the wavelet matrix and the object index.
+    pub progress: Option<Arc<ProgressFn>>,
+}
+
+impl LoadOptions {
+    /// Sets the progress callback.
+    pub fn with_progress(
+        mut self, progress: impl Fn(Progress) -> ControlFlow<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// The number of threads to use, resolving 0 to the available parallelism.
+    fn num_threads(&self) -> usize {
+        if self.threads == 0 { std::thread::available_parallelism().map_or(1, usize::from) } else { self.threads }
+    }
+}
+
+impl fmt::Debug for LoadOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoadOptions")
+            .field("checksums", &self.checksums)
+            .field("object_index", &self.object_index)
+            .field("threads", &self.threads)
+            .field("progress", &self.progress.as_ref().map(|_| "Fn"))
+            .finish()
+    }
+}
+
+/// Reads the dictionary without verifying the checksums of its sections.
+type ReadDict<R> = fn(&mut ProgressReader<R>) -> crate::four_sect_dict::Result<(Dict, PendingChecksums)>;
+/// Reads the triples section without building the wavelet matrix and the object index.
+type ReadTriples<R> = fn(&mut ProgressReader<R>) -> crate::triples::Result<TriplesLayers>;
+
+/// Counts the bytes read from the inner reader and reports them to the progress callback.
+/// Once the callback cancels, all further reads fail.
+struct ProgressReader<R> {
+    inner: R,
+    progress: Option<Arc<ProgressFn>>,
+    phase: Phase,
+    bytes_read: u64,
+    reported: u64,
+    cancelled: bool,
+}
+
+impl<R: BufRead> ProgressReader<R> {
+    const fn new(inner: R, progress: Option<Arc<ProgressFn>>) -> Self {
+        ProgressReader { inner, progress, phase: Phase::Header, bytes_read: 0, reported: 0, cancelled: false }
+    }
+
+    /// Reports the current progress and remembers whether the callback cancelled.
+    fn report(&mut self) -> io::Result<()> {
+        self.reported = self.bytes_read;
+        if let Some(progress) = &self.progress {
+            if progress(Progress { phase: self.phase, bytes_read: self.bytes_read }).is_break() {
+                self.cancelled = true;
+            }
+        }
+        self.check()
+    }
+
+    fn check(&self) -> io::Result<()> {
+        if self.cancelled { Err(io::Error::other("loading cancelled")) } else { Ok(()) }
+    }
+
+    /// Reports the start of a new phase.
+    fn start(&mut self, phase: Phase) -> Result<()> {
+        self.phase = phase;
+        self.report().map_err(|_| Error::Cancelled)
+    }
+
+    /// Reports if enough bytes have been read since the last report.
+    fn poll(&mut self) -> io::Result<()> {
+        if self.bytes_read - self.reported >= REPORT_INTERVAL { self.report() } else { self.check() }
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl ProgressReader<BytesReader> {
+    /// Passes the inner reader to `f`, which borrows the data instead of reading it, and counts the bytes it consumed.
+    fn mapped<T>(&mut self, f: impl FnOnce(&mut BytesReader) -> T) -> T {
+        let start = self.inner.position();
+        let t = f(&mut self.inner);
+        self.bytes_read += (self.inner.position() - start) as u64;
+        t
+    }
+}
+
+impl<R: BufRead> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.poll()?;
+        let n = self.inner.read(buf)?;
+        self.bytes_read += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for ProgressReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.poll()?;
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt);
+        self.bytes_read += amt as u64;
+    }
+}
+
+impl Hdt {
+    /// Like [`Hdt::read`] but with the given options.
+    /// Returns [`Error::Cancelled`] if the progress callback returns [`ControlFlow::Break`] before loading is done.
+    /// # Example
+    /// ```
+    /// use hdt::{Checksums, Hdt, LoadOptions, triples::ObjectIndex};
+    /// use std::ops::ControlFlow;
+    /// let file = std::fs::File::open("tests/resources/snikmeta.hdt").expect("error opening file");
+    /// // subject-centric applications can save the loading time and memory of the object index
+    /// let options = LoadOptions {
+    ///     checksums: Checksums::Background,
+    ///     object_index: ObjectIndex::Lazy,
+    ///     ..Default::default()
+    /// }
+    /// .with_progress(|p| {
+    ///     println!("{:?} after {} bytes", p.phase, p.bytes_read);
+    ///     ControlFlow::Continue(())
+    /// });
+    /// let hdt = Hdt::read_with(std::io::BufReader::new(file), &options).unwrap();
+    /// ```
+    pub fn read_with<R: BufRead>(reader: R, options: &LoadOptions) -> Result<Self> {
+        Self::load(reader, options, Dict::read_unverified, TriplesBitmap::read_sect_layers)
+    }
+
+    /// Like [`Hdt::map`] but with the given options.
+    /// Progress is only reported at the start of each phase and while building the wavelet matrix and the object index,
+    /// because mapping the sections does not read them.
+    /// # Example
+    /// ```
+    /// use hdt::{Checksums, Hdt, LoadOptions, triples::ObjectIndex};
+    /// let options = LoadOptions { checksums: Checksums::Skip, object_index: ObjectIndex::Lazy, ..Default::default() };
+    /// let hdt = Hdt::map_with(std::path::Path::new("tests/resources/snikmeta.hdt"), &options).unwrap();
+    /// ```
+    #[cfg(feature = "mmap")]
+    pub fn map_with(f: &Path, options: &LoadOptions) -> Result<Self> {
+        let file = std::fs::File::open(f)?;
+        // SAFETY: the mapping is read-only and the documentation requires that the file is not modified while mapped
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+        Self::load(
+            BytesReader::new(Bytes::from(mmap)),
+            options,
+            |r| r.mapped(Dict::read_mapped_unverified),
+            |r| r.mapped(TriplesBitmap::read_mapped_sect_layers),
+        )
+    }
+
+    /// Loads an HDT with the given functions to read the dictionary and the triples section,
+    /// reports that loading is done and starts verifying the remaining checksums in the background.
+    fn load<R: BufRead>(
+        reader: R, options: &LoadOptions, read_dict: ReadDict<R>, read_triples: ReadTriples<R>,
+    ) -> Result<Self> {
+        let mut reader = ProgressReader::new(reader, options.progress.clone());
+        let result = Self::read_progress(&mut reader, options, read_dict, read_triples);
+        if reader.cancelled {
+            return Err(Error::Cancelled);
+        }
+        let (hdt, pending) = result?;
+        let bytes_read = reader.bytes_read;
+        if let Some(progress) = &options.progress {
+            // cancelling has no effect anymore
+            let _ = progress(Progress { phase: Phase::Done, bytes_read });
+        }
+        if let Some(pending) = pending {
+            let threads = options.num_threads();
+            let progress = options.progress.clone();
+            std::thread::spawn(move || {
+                let valid = match pending.verify(threads) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        error!("invalid HDT dictionary: {e}");
+                        false
+                    }
+                };
+                if let Some(progress) = progress {
+                    let _ = progress(Progress { phase: Phase::Verified { valid }, bytes_read });
+                }
+            });
+        }
+        Ok(hdt)
+    }
+
+    /// Reads all sections and returns the checksums that are left to verify in the background.
+    fn read_progress<R: BufRead>(
+        reader: &mut ProgressReader<R>, options: &LoadOptions, read_dict: ReadDict<R>,
+        read_triples: ReadTriples<R>,
+    ) -> Result<(Self, Option<PendingChecksums>)> {
+        reader.start(Phase::Header)?;
+        ControlInfo::read(reader)?;
+        let header = Header::read(reader)?;
+        reader.start(Phase::Dictionary)?;
+        let (dict, mut pending) = read_dict(reader)?;
+        let threads_supported = cfg!(not(any(target_arch = "wasm32", target_arch = "wasm64")));
+        // the checksums are verified while the triples are read and the indexes are built
+        let verifying = (options.checksums == Checksums::Verify && threads_supported).then(|| {
+            let pending = std::mem::take(&mut pending);
+            let threads = options.num_threads();
+            std::thread::spawn(move || pending.verify(threads))
+        });
+        reader.start(Phase::Triples)?;
+        // construction reports progress without reading, so that it can be cancelled
+        let Some(mut triples) = read_triples(reader)?.build(&mut || reader.report().is_err()) else {
+            return Err(Error::Cancelled);
+        };
+        reader.start(Phase::Indexes)?;
+        if !triples.set_object_index_cancellable(options.object_index, &mut || reader.report().is_err()) {
+            return Err(Error::Cancelled);
+        }
+        let pending = match options.checksums {
+            Checksums::Skip => None,
+            Checksums::Background if threads_supported => Some(pending),
+            Checksums::Verify | Checksums::Background => {
+                reader.start(Phase::Checksums)?;
+                match verifying {
+                    Some(verifying) => verifying.join().unwrap()?,
+                    None => pending.verify(options.num_threads())?,
+                }
+                None
+            }
+        };
+        let hdt = Hdt::from_sections(header, dict, triples);
+        debug!("HDT size in memory {}, details:", ByteSize(hdt.size_in_bytes() as u64));
+        debug!("{hdt:#?}");
+        Ok((hdt, pending))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hdt::tests::snikmeta_check;
+    use crate::tests::init;
+    use color_eyre::Result;
+    use pretty_assertions::assert_eq;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Mutex, mpsc};
+    use std::time::Duration;
+
+    const SNIKMETA: &str = "tests/resources/snikmeta.hdt";
+
+    #[test]
+    fn progress() -> Result<()> {
+        init();
+        let data = std::fs::read(SNIKMETA)?;
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let r = reports.clone();
+        let options = LoadOptions::default().with_progress(move |p| {
+            r.lock().unwrap().push(p);
+            ControlFlow::Continue(())
+        });
+        snikmeta_check(&Hdt::read_with(data.as_slice(), &options)?)?;
+        let reports = reports.lock().unwrap();
+        let mut phases: Vec<Phase> = reports.iter().map(|p| p.phase).collect();
+        // construction also reports progress
+        phases.dedup();
+        assert_eq!(
+            phases,
+            vec![Phase::Header, Phase::Dictionary, Phase::Triples, Phase::Indexes, Phase::Checksums, Phase::Done]
+        );
+        assert!(reports.windows(2).all(|w| w[0].bytes_read <= w[1].bytes_read));
+        assert_eq!(reports.last().unwrap().bytes_read, data.len() as u64);
+        // cancel while reading the dictionary
+        let options = LoadOptions::default().with_progress(|p| {
+            if p.phase == Phase::Dictionary { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+        });
+        assert!(matches!(Hdt::read_with(data.as_slice(), &options), Err(Error::Cancelled)));
+        // cancel while building the wavelet matrix and the object index, after the start of the phase was reported
+        for phase in [Phase::Triples, Phase::Indexes] {
+            let reports = AtomicUsize::new(0);
+            let options = LoadOptions::default().with_progress(move |p| {
+                if p.phase == phase && reports.fetch_add(1, Ordering::Relaxed) > 0 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            });
+            assert!(matches!(Hdt::read_with(data.as_slice(), &options), Err(Error::Cancelled)), "{phase:?}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "mmap")]
+    fn map_with() -> Result<()> {
+        init();
+        let path = std::path::Path::new(SNIKMETA);
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let r = reports.clone();
+        let options =
+            LoadOptions { checksums: Checksums::Skip, object_index: ObjectIndex::Lazy, ..Default::default() }
+                .with_progress(move |p| {
+                    r.lock().unwrap().push(p);
+                    ControlFlow::Continue(())
+                });
+        let hdt = Hdt::map_with(path, &options)?;
+        assert!(hdt.triples.op_index().is_none());
+        snikmeta_check(&hdt)?;
+        let reports = reports.lock().unwrap();
+        let mut phases: Vec<Phase> = reports.iter().map(|p| p.phase).collect();
+        phases.dedup();
+        assert_eq!(phases, vec![Phase::Header, Phase::Dictionary, Phase::Triples, Phase::Indexes, Phase::Done]);
+        assert_eq!(reports.last().unwrap().bytes_read, std::fs::metadata(path)?.len());
+        // a corrupted file is rejected unless the checksums are skipped
+        let mut data = std::fs::read(path)?;
+        let pos = data.windows(8).position(|w| w == b"Function").expect("term not found");
+        data[pos] = b'G';
+        let corrupted = std::env::temp_dir().join(format!("hdt-map-with-{}.hdt", std::process::id()));
+        std::fs::write(&corrupted, &data)?;
+        assert!(Hdt::map_with(&corrupted, &LoadOptions::default()).is_err());
+        Hdt::map_with(&corrupted, &LoadOptions { checksums: Checksums::Skip, ..Default::default() })?;
+        std::fs::remove_file(&corrupted)?;
+        Ok(())
+    }
+
+    #[test]
+    fn checksums() -> Result<()> {
+        init();
+        let mut data = std::fs::read(SNIKMETA)?;
+        let options = |checksums| LoadOptions { checksums, threads: 2, ..Default::default() };
+        snikmeta_check(&Hdt::read_with(data.as_slice(), &options(Checksums::Skip))?)?;
+        // corrupt a term in the packed data of a dictionary section
+        let pos = data.windows(8).position(|w| w == b"Function").expect("term not found");
+        data[pos] = b'G';
+        assert!(Hdt::read_with(data.as_slice(), &options(Checksums::Verify)).is_err());
+        Hdt::read_with(data.as_slice(), &options(Checksums::Skip))?;
+        let (tx, rx) = mpsc::channel();
+        let options = options(Checksums::Background).with_progress(move |p| {
+            if let Phase::Verified { valid } = p.phase {
+                tx.send(valid).unwrap();
+            }
+            ControlFlow::Continue(())
+        });
+        Hdt::read_with(data.as_slice(), &options)?;
+        assert!(!rx.recv_timeout(Duration::from_secs(10))?);
+        Ok(())
+    }
+}
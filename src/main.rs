@@ -3,12 +3,15 @@
 use bytesize::ByteSize;
 use clap::{Parser, Subcommand};
 use color_eyre::config::HookBuilder;
-use color_eyre::eyre::{Report, WrapErr};
+use color_eyre::eyre::{Report, WrapErr, bail, eyre};
 //use log::info;
 use fs_err::{File, metadata};
-use hdt::Hdt;
+use hdt::compression::{self, decompress};
 use hdt::containers::ControlInfo;
+use hdt::hdt_graph::auto_term;
 use hdt::header::Header;
+use hdt::triples::Order;
+use hdt::{Dictionary, Hdt, IdKind, RdfFormat, tpf};
 use sophia::api::graph::Graph;
 use sophia::api::prelude::{TripleSerializer, TripleSource};
 //use sophia::api::prelude::Stringifier;
@@ -17,8 +20,8 @@ use sophia::turtle::parser::{nt, turtle};
 use sophia::turtle::serializer::nt::NtSerializer;
 use sophia::turtle::serializer::turtle::{TurtleConfig, TurtleSerializer};
 use std::ffi::OsStr;
-use std::io::{BufReader, BufWriter};
-use std::path::PathBuf;
+use std::io::{BufReader, BufWriter, Write, stdout};
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 //use std::io::{BufReader, stdin};
 
@@ -61,7 +64,264 @@ enum Command {
         // /// the RDF file to create, if not given it is written to stdout
         // rdf_output_path: Option<String>,
         output_path: PathBuf,
+        /// convert N-Triples using temporary files to stay within the given memory, e.g. "4 GiB"
+        #[arg(long)]
+        memory_budget: Option<ByteSize>,
     },
+    /// merge HDT files into one
+    Cat {
+        /// the HDT files to merge
+        #[arg(required = true)]
+        input_paths: Vec<PathBuf>,
+        /// the HDT file to create
+        #[arg(short, long)]
+        output_path: PathBuf,
+    },
+    /// print the triples matching a triple pattern as N-Triples
+    Query {
+        /// the HDT file to query
+        input_path: PathBuf,
+        /// the subject IRI or blank node, e.g. "<http://example.org/s>", any subject if not given
+        #[arg(short, long)]
+        s: Option<String>,
+        /// the predicate IRI, any predicate if not given
+        #[arg(short, long)]
+        p: Option<String>,
+        /// the object term, e.g. "<http://example.org/o>" or '"label"@en', any object if not given
+        #[arg(short, long)]
+        o: Option<String>,
+        /// print the number of matching triples instead of the triples
+        #[arg(short, long)]
+        count: bool,
+    },
+    /// print the terms of the dictionary that start with a prefix, e.g. to autocomplete IRIs
+    Search {
+        /// the HDT file to search
+        input_path: PathBuf,
+        /// the prefix of the terms in the HDT string representation, i.e. IRIs without angle brackets
+        prefix: String,
+        /// only search terms in the given position, all terms if not given
+        #[arg(short, long, value_enum)]
+        kind: Option<TermKind>,
+        /// the maximum number of terms to print
+        #[arg(short, long)]
+        limit: Option<usize>,
+    },
+    /// evaluate a SPARQL query on an HDT file
+    #[cfg(feature = "sparql")]
+    Sparql {
+        /// the HDT file to query
+        input_path: PathBuf,
+        /// the SPARQL query
+        query: String,
+        /// the format of the results, by default TSV for solutions, JSON for booleans and N-Triples for graphs
+        #[arg(short, long, value_enum)]
+        format: Option<SparqlFormat>,
+        /// print the number of solutions or triples instead of the results
+        #[arg(short, long)]
+        count: bool,
+    },
+    /// serve an HDT file as Triple Pattern Fragments over HTTP
+    Serve {
+        /// the HDT file to serve
+        input_path: PathBuf,
+        /// the port to listen on
+        #[arg(short, long, default_value_t = 3000)]
+        port: u16,
+        /// the maximum number of triples per page
+        #[arg(long, default_value_t = tpf::PAGE_SIZE)]
+        page_size: usize,
+        /// the number of threads answering requests concurrently, 0 for one per CPU core
+        #[arg(long, default_value_t = 0)]
+        threads: usize,
+    },
+    /// serve an HDT file as a SPARQL 1.1 Protocol endpoint over HTTP at /sparql
+    #[cfg(feature = "sparql")]
+    SparqlServer {
+        /// the HDT file to serve
+        input_path: PathBuf,
+        /// the port to listen on
+        #[arg(short, long, default_value_t = 3030)]
+        port: u16,
+        /// the maximum duration of a query in seconds
+        #[arg(long, default_value_t = 30)]
+        timeout: u64,
+        /// the maximum number of solutions or triples of a query result, truncated results have an X-SPARQL-MaxRows header
+        #[arg(long, default_value_t = 10_000)]
+        limit: usize,
+        /// the number of threads answering requests concurrently, 0 for one per CPU core
+        #[arg(long, default_value_t = 0)]
+        threads: usize,
+    },
+}
+
+/// Position of the terms of the search subcommand.
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum TermKind {
+    Subject,
+    Predicate,
+    Object,
+}
+
+impl From<TermKind> for IdKind {
+    fn from(kind: TermKind) -> Self {
+        match kind {
+            TermKind::Subject => Self::Subject,
+            TermKind::Predicate => Self::Predicate,
+            TermKind::Object => Self::Object,
+        }
+    }
+}
+
+/// Output format of the sparql subcommand.
+#[cfg(feature = "sparql")]
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum SparqlFormat {
+    Json,
+    Xml,
+    Csv,
+    Tsv,
+    Turtle,
+    NTriples,
+}
+
+#[cfg(feature = "sparql")]
+impl From<SparqlFormat> for hdt::sparql::ResultsFormat {
+    fn from(format: SparqlFormat) -> Self {
+        match format {
+            SparqlFormat::Json => Self::Json,
+            SparqlFormat::Xml => Self::Xml,
+            SparqlFormat::Csv => Self::Csv,
+            SparqlFormat::Tsv => Self::Tsv,
+            SparqlFormat::Turtle => Self::Turtle,
+            SparqlFormat::NTriples => Self::NTriples,
+        }
+    }
+}
+
+/// Converts a term given in N-Triples syntax to the string representation of the dictionary.
+/// IRIs may also be given without angle brackets.
+fn dict_term(s: &str) -> &str {
+    s.strip_prefix('<').and_then(|s| s.strip_suffix('>')).unwrap_or(s)
+}
+
+/// Answers a Triple Pattern Fragments request, errors are reported to the client.
+fn serve_fragment(hdt: &Hdt, request: tiny_http::Request, port: u16, page_size: usize) -> std::io::Result<()> {
+    let header =
+        |name: &str| request.headers().iter().find(|h| h.field.equiv(name)).map(|h| h.value.as_str().to_owned());
+    let base_url = format!("http://{}/", header("Host").unwrap_or_else(|| format!("localhost:{port}")));
+    let format = tpf::Format::from_accept(&header("Accept").unwrap_or_default());
+    let (path, query) = request.url().split_once('?').unwrap_or((request.url(), ""));
+    if path != "/" {
+        return request.respond(tiny_http::Response::from_string("not found").with_status_code(404));
+    }
+    let mut body = Vec::new();
+    let written = tpf::Fragment::new(hdt, tpf::FragmentRequest::from_query(query), page_size)
+        .map_err(std::io::Error::other)
+        .and_then(|fragment| fragment.write(&base_url, format, &mut body));
+    if let Err(e) = written {
+        return request.respond(tiny_http::Response::from_string(e.to_string()).with_status_code(500));
+    }
+    let content_type = tiny_http::Header::from_bytes("Content-Type", format.media_type()).unwrap();
+    let cors = tiny_http::Header::from_bytes("Access-Control-Allow-Origin", "*").unwrap();
+    request.respond(tiny_http::Response::from_data(body).with_header(content_type).with_header(cors))
+}
+
+/// Maximum size in bytes of the body of a SPARQL POST request.
+#[cfg(feature = "sparql")]
+const MAX_QUERY_BYTES: u64 = 1 << 20;
+
+/// Answers a SPARQL 1.1 Protocol request, errors are reported to the client.
+#[cfg(feature = "sparql")]
+fn serve_sparql(
+    hdt: &Hdt, mut request: tiny_http::Request, timeout: std::time::Duration, limit: usize,
+) -> std::io::Result<()> {
+    use hdt::sparql::{self, ResultsFormat, ResultsPage};
+    use std::io::Read;
+    let header =
+        |name: &str| request.headers().iter().find(|h| h.field.equiv(name)).map(|h| h.value.as_str().to_owned());
+    let accept = header("Accept").unwrap_or_default();
+    let content_type = header("Content-Type").unwrap_or_default();
+    let post = *request.method() == tiny_http::Method::Post;
+    let url = request.url().to_owned();
+    let (path, url_query) = url.split_once('?').unwrap_or((&url, ""));
+    let error = |status: u16, message: String| tiny_http::Response::from_string(message).with_status_code(status);
+    if path != "/sparql" {
+        return request.respond(error(404, "not found, the SPARQL endpoint is at /sparql".to_owned()));
+    }
+    let mut body = String::new();
+    if post {
+        // one byte more than the limit to detect bodies that exceed it
+        request.as_reader().take(MAX_QUERY_BYTES + 1).read_to_string(&mut body)?;
+        if body.len() as u64 > MAX_QUERY_BYTES {
+            return request.respond(error(413, format!("query exceeds the limit of {MAX_QUERY_BYTES} bytes")));
+        }
+    }
+    let Some(query) = sparql::protocol_query(post, &content_type, url_query, &body) else {
+        return request.respond(error(400, "missing query".to_owned()));
+    };
+    let deadline = std::time::Instant::now() + timeout;
+    let results = match sparql::query_with_deadline(&query, hdt, deadline) {
+        Ok(results) => results,
+        Err(e) => return request.respond(error(400, format!("{e}"))),
+    };
+    let format = ResultsFormat::negotiate(&accept, &results);
+    // evaluate before responding, so that errors, timeouts and truncation can be reported in the status and headers
+    let page = match ResultsPage::new(results, limit, Some(deadline)) {
+        Ok(page) => page,
+        Err(e) => {
+            let status = if e.kind() == std::io::ErrorKind::TimedOut { 503 } else { 500 };
+            return request.respond(error(status, e.to_string()));
+        }
+    };
+    let mut headers = vec![
+        tiny_http::Header::from_bytes("Content-Type", format.media_type()).unwrap(),
+        tiny_http::Header::from_bytes("Access-Control-Allow-Origin", "*").unwrap(),
+    ];
+    if page.truncated() {
+        headers.push(tiny_http::Header::from_bytes("X-SPARQL-MaxRows", limit.to_string()).unwrap());
+    }
+    // stream the serialization to the client instead of buffering it, which uses chunked transfer encoding
+    let (reader, writer) = std::io::pipe()?;
+    std::thread::scope(|scope| {
+        let serializer = scope.spawn(move || {
+            let mut writer = BufWriter::new(writer);
+            page.write(format, &mut writer)?;
+            writer.flush()
+        });
+        let response = tiny_http::Response::new(tiny_http::StatusCode(200), headers, reader, None, None);
+        let responded = request.respond(response);
+        if let Err(e) = serializer.join().expect("serializer thread panicked") {
+            eprintln!("Error serializing SPARQL results: {e}");
+        }
+        responded
+    })
+}
+
+/// Answers the requests of the server with the given number of threads, 0 for one per CPU core.
+fn serve_requests(
+    server: &tiny_http::Server, threads: usize, respond: impl Fn(tiny_http::Request) -> std::io::Result<()> + Sync,
+) {
+    let threads = if threads == 0 { std::thread::available_parallelism().map_or(1, usize::from) } else { threads };
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                for request in server.incoming_requests() {
+                    if let Err(e) = respond(request) {
+                        eprintln!("Error responding to request: {e}");
+                    }
+                }
+            });
+        }
+    });
+}
+
+/// Reads the HDT file at the given path, which may be compressed.
+fn read_hdt(input_path: &Path) -> Result<Hdt, Report> {
+    let file = File::open(input_path).with_context(|| format!("Error opening input HDT file {input_path:?}"))?;
+    let reader = decompress(BufReader::new(file))
+        .with_context(|| format!("Error decompressing input HDT file {input_path:?}"))?;
+    Hdt::read(reader).with_context(|| format!("Error loading input HDT from {input_path:?}"))
 }
 
 fn main() -> Result<(), Report> {
@@ -87,8 +347,9 @@ fn main() -> Result<(), Report> {
         Command::Info { input_path } => {
             let file = File::open(input_path.clone())
                 .with_context(|| format!("Error opening input HDT file {input_path:?}"))?;
-            let mut reader = BufReader::new(file);
-            match input_path.extension().and_then(OsStr::to_str) {
+            let mut reader = decompress(BufReader::new(file))
+                .with_context(|| format!("Error decompressing input file {input_path:?}"))?;
+            match compression::strip_extension(&input_path).extension().and_then(OsStr::to_str) {
                 Some("nt") => {
                     let triples = nt::parse_bufread(reader).collect_triples();
                     let g: LightGraph = triples.unwrap();
@@ -113,20 +374,23 @@ fn main() -> Result<(), Report> {
                 }
             }
         }
-        Command::Convert { input_path, output_path /* turtle*/ } => {
+        Command::Convert { input_path, output_path, memory_budget /* turtle*/ } => {
             let t = Instant::now();
-            let file = File::open(input_path.clone())
-                .with_context(|| format!("Error opening input HDT file {input_path:?}"))?;
-            let reader = BufReader::new(file);
-
-            let hdt = match input_path.extension().and_then(OsStr::to_str) {
-                Some("hdt") => {
-                    Hdt::read(reader).with_context(|| format!("Error loading input HDT from {input_path:?}"))?
+            let format = RdfFormat::from_path(&input_path);
+            let uncompressed_path = compression::strip_extension(&input_path);
+            let hdt = match (uncompressed_path.extension().and_then(OsStr::to_str), format, memory_budget) {
+                (Some("hdt"), _, _) => read_hdt(&input_path)?,
+                (_, Some(RdfFormat::NTriples), Some(budget)) => {
+                    Hdt::read_nt_with_budget(&input_path, Order::SPO, budget.as_u64() as usize)
+                        .with_context(|| format!("Error loading input N-Triples file from {input_path:?}"))?
                 }
-                Some("nt") => Hdt::read_nt(&input_path)
-                    .with_context(|| format!("Error loading input N-Triples file from {input_path:?}"))?,
-                _ => {
-                    panic!(
+                (_, Some(_), Some(_)) => {
+                    bail!("A memory budget is only supported for N-Triples input, aborting.");
+                }
+                (_, Some(format), None) => Hdt::read_rdf(&input_path, format)
+                    .with_context(|| format!("Error loading input {format:?} file from {input_path:?}"))?,
+                (_, None, _) => {
+                    bail!(
                         "Input file has unsupported or no extension, RDF format cannot be determined, aborting."
                     );
                 }
@@ -160,7 +424,7 @@ fn main() -> Result<(), Report> {
                     hdt.write(&mut writer)?;
                 }
                 _ => {
-                    panic!(
+                    bail!(
                         "Output file has no extension or one signifying an unsupported export format, aborting."
                     );
                 }
@@ -173,6 +437,103 @@ fn main() -> Result<(), Report> {
             );
             // println!("{s}");
         }
+        Command::Cat { input_paths, output_path } => {
+            let t = Instant::now();
+            let mut hdts = Vec::with_capacity(input_paths.len());
+            for input_path in &input_paths {
+                hdts.push(read_hdt(input_path)?);
+            }
+            let hdt = Hdt::cat(&hdts.iter().collect::<Vec<_>>()).wrap_err("Error merging HDT files")?;
+            let mut writer = BufWriter::new(File::create(&output_path)?);
+            hdt.write(&mut writer)?;
+            let out_size = ByteSize(metadata(&output_path)?.len());
+            println!(
+                "Successfully merged {} HDT files with {} triples to {output_path:?} ({out_size}) in {:.2}s",
+                input_paths.len(),
+                hdt.triples.len(),
+                t.elapsed().as_secs_f32()
+            );
+        }
+        Command::Query { input_path, s, p, o, count } => {
+            let hdt = read_hdt(&input_path)?;
+            let triples = hdt.triples_with_pattern(
+                s.as_deref().map(dict_term),
+                p.as_deref().map(dict_term),
+                o.as_deref().map(dict_term),
+            );
+            if count {
+                println!("{}", triples.count());
+                return Ok(());
+            }
+            let triples =
+                triples.map(|[s, p, o]| Ok::<_, std::io::Error>([auto_term(&s)?, auto_term(&p)?, auto_term(&o)?]));
+            let mut writer = BufWriter::new(stdout().lock());
+            NtSerializer::new(&mut writer)
+                .serialize_triples(triples)
+                .wrap_err("error serializing triples as N-Triples")?;
+            writer.flush()?;
+        }
+        Command::Search { input_path, prefix, kind, limit } => {
+            let hdt = read_hdt(&input_path)?;
+            let kinds = kind.map_or_else(|| IdKind::KINDS.to_vec(), |kind| vec![kind.into()]);
+            // without a given kind, the shared terms are only printed as subjects
+            let skip_shared = kind.is_none();
+            let num_shared = hdt.dict.num_shared();
+            let terms = kinds.into_iter().flat_map(|kind| {
+                let skip = if skip_shared && matches!(kind, IdKind::Object) { num_shared } else { 0 };
+                hdt.dict.search(&prefix, kind).filter(move |&id| id > skip).map(move |id| (id, kind))
+            });
+            let mut writer = BufWriter::new(stdout().lock());
+            for (id, kind) in terms.take(limit.unwrap_or(usize::MAX)) {
+                writeln!(writer, "{}", hdt.dict.id_to_string(id, kind)?)?;
+            }
+            writer.flush()?;
+        }
+        #[cfg(feature = "sparql")]
+        Command::Sparql { input_path, query, format, count } => {
+            use hdt::sparql::{self, ResultsFormat};
+            use spareval::QueryResults;
+            let hdt = read_hdt(&input_path)?;
+            let results = sparql::query(&query, &hdt).wrap_err("Error evaluating SPARQL query")?;
+            if count {
+                match results {
+                    QueryResults::Boolean(b) => println!("{}", u8::from(b)),
+                    QueryResults::Solutions(mut solutions) => {
+                        println!("{}", solutions.try_fold(0, |n, s| s.map(|_| n + 1))?);
+                    }
+                    QueryResults::Graph(mut triples) => {
+                        println!("{}", triples.try_fold(0, |n, t| t.map(|_| n + 1))?);
+                    }
+                }
+                return Ok(());
+            }
+            let format = format.map_or_else(
+                || ResultsFormat::negotiate("text/tab-separated-values, application/n-triples", &results),
+                ResultsFormat::from,
+            );
+            let mut writer = BufWriter::new(stdout().lock());
+            sparql::write_results(results, format, usize::MAX, None, &mut writer)
+                .wrap_err_with(|| format!("Error writing SPARQL results as {format:?}"))?;
+            writer.flush()?;
+        }
+        Command::Serve { input_path, port, page_size, threads } => {
+            let hdt = read_hdt(&input_path)?;
+            let server = tiny_http::Server::http(("0.0.0.0", port))
+                .map_err(|e| eyre!(e))
+                .wrap_err_with(|| format!("Error listening on port {port}"))?;
+            println!("Serving {input_path:?} as Triple Pattern Fragments on http://localhost:{port}/");
+            serve_requests(&server, threads, |request| serve_fragment(&hdt, request, port, page_size));
+        }
+        #[cfg(feature = "sparql")]
+        Command::SparqlServer { input_path, port, timeout, limit, threads } => {
+            let hdt = read_hdt(&input_path)?;
+            let server = tiny_http::Server::http(("0.0.0.0", port))
+                .map_err(|e| eyre!(e))
+                .wrap_err_with(|| format!("Error listening on port {port}"))?;
+            println!("Serving {input_path:?} as a SPARQL endpoint on http://localhost:{port}/sparql");
+            let timeout = std::time::Duration::from_secs(timeout);
+            serve_requests(&server, threads, |request| serve_sparql(&hdt, request, timeout, limit));
+        }
     }
     Ok(())
 }
@@ -0,0 +1,352 @@
+//! Multiple section dictionary as written by hdt-java, which splits the objects into one section per literal datatype.
+use crate::DictSectPFC;
+#[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
+use crate::containers::BytesReader;
+use crate::containers::vbyte::{encode_vbyte, read_vbyte};
+use crate::dict_sect_pfc;
+use crate::dictionary::{Dictionary, PendingChecksums};
+use crate::four_sect_dict::{DictSectError, ExtractError, IdKind, Result, SectKind};
+use crate::triples::Id;
+use std::io::{BufRead, Read, Write};
+use std::ops::Range;
+#[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
+use std::thread::JoinHandle;
+
+/// Object section type of IRIs, blank nodes and literals without datatype.
+pub const NO_DATATYPE: &str = "NO_DATATYPE";
+/// Object section type of language tagged literals, whose strings keep the language tag.
+pub const LANG_STRING: &str = "<http://www.w3.org/1999/02/22-rdf-syntax-ns#langString>";
+
+/// Dictionary with shared, subject and predicate sections like [`crate::FourSectDict`] but with one object section per type.
+/// Literals in a typed section are stored without their datatype, e.g. `"1"` in the `<http://www.w3.org/2001/XMLSchema#integer>` section.
+/// Object IDs start after the shared section and continue through the object sections in the order they are stored.
+/// Type specified as <http://purl.org/HDT/hdt#dictionaryMult>.
+#[cfg_attr(test, derive(PartialEq))]
+#[derive(Debug)]
+pub struct MultSectDict {
+    /// URIs that occur both in subject and object position. Its IDs start at one.
+    pub shared: DictSectPFC,
+    /// URIs that only occur as subjects. Their IDs start at the last ID of the shared section + 1.
+    pub subjects: DictSectPFC,
+    /// The predicate section has its own separate numbering starting from 1.
+    pub predicates: DictSectPFC,
+    /// Object sections sorted by their type, which is either a datatype IRI, [`NO_DATATYPE`] or [`LANG_STRING`].
+    pub objects: Vec<(String, DictSectPFC)>,
+}
+
+/// Splits an object term into the type of the section it belongs to and the string stored there.
+pub(crate) fn split_type(s: &str) -> (&str, &str) {
+    if let Some(end) = s.rfind('"').filter(|_| s.starts_with('"')) {
+        let (label, suffix) = s.split_at(end + 1);
+        if let Some(datatype) = suffix.strip_prefix("^^") {
+            return (datatype, label);
+        }
+        if suffix.starts_with('@') {
+            return (LANG_STRING, s);
+        }
+    }
+    (NO_DATATYPE, s)
+}
+
+impl MultSectDict {
+    /// read the sections following the control information
+    pub(crate) fn read_sections<R: BufRead>(reader: &mut R) -> Result<UnvalidatedMultSectDict> {
+        use SectKind::*;
+        let mut f = |sect_kind| DictSectPFC::read(reader).map_err(|e| DictSectError { e, sect_kind });
+        let (shared, subjects, predicates) = (f(Shared)?, f(Subject)?, f(Predicate)?);
+        let (num_types, _) = read_vbyte(reader)?;
+        // the number of types is untrusted, so the vector only grows with the sections that are actually read
+        let mut objects = Vec::new();
+        for _ in 0..num_types {
+            let datatype = read_type(reader)?;
+            objects
+                .push((datatype, DictSectPFC::read(reader).map_err(|e| DictSectError { e, sect_kind: Object })?));
+        }
+        Ok(UnvalidatedMultSectDict { shared, subjects, predicates, objects })
+    }
+
+    /// Like `read_sections` but without verifying the checksums, which are added to `pending` instead.
+    pub(crate) fn read_sections_unverified<R: BufRead>(
+        reader: &mut R, pending: &mut PendingChecksums,
+    ) -> Result<MultSectDict> {
+        use SectKind::*;
+        let shared = pending.read_section(reader, Shared)?;
+        let subjects = pending.read_section(reader, Subject)?;
+        let predicates = pending.read_section(reader, Predicate)?;
+        let (num_types, _) = read_vbyte(reader)?;
+        let mut objects = Vec::new();
+        for _ in 0..num_types {
+            let datatype = read_type(reader)?;
+            objects.push((datatype, pending.read_section(reader, Object)?));
+        }
+        Ok(MultSectDict { shared, subjects, predicates, objects })
+    }
+
+    /// Like `read_sections` but the sections borrow their data from the given reader instead of copying it.
+    #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
+    pub(crate) fn read_mapped_sections(reader: &mut BytesReader) -> Result<UnvalidatedMultSectDict> {
+        use SectKind::*;
+        let mut f = |sect_kind| DictSectPFC::read_mapped(reader).map_err(|e| DictSectError { e, sect_kind });
+        let (shared, subjects, predicates) = (f(Shared)?, f(Subject)?, f(Predicate)?);
+        let (num_types, _) = read_vbyte(reader)?;
+        let mut objects = Vec::new();
+        for _ in 0..num_types {
+            let datatype = read_type(reader)?;
+            let sect = DictSectPFC::read_mapped(reader).map_err(|e| DictSectError { e, sect_kind: Object })?;
+            objects.push((datatype, sect));
+        }
+        Ok(UnvalidatedMultSectDict { shared, subjects, predicates, objects })
+    }
+
+    /// Like `read_mapped_sections` but without verifying the checksums, which are added to `pending` instead.
+    #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
+    pub(crate) fn read_mapped_sections_unverified(
+        reader: &mut BytesReader, pending: &mut PendingChecksums,
+    ) -> Result<MultSectDict> {
+        use SectKind::*;
+        let shared = pending.read_mapped_section(reader, Shared)?;
+        let subjects = pending.read_mapped_section(reader, Subject)?;
+        let predicates = pending.read_mapped_section(reader, Predicate)?;
+        let (num_types, _) = read_vbyte(reader)?;
+        let mut objects = Vec::new();
+        for _ in 0..num_types {
+            let datatype = read_type(reader)?;
+            objects.push((datatype, pending.read_mapped_section(reader, Object)?));
+        }
+        Ok(MultSectDict { shared, subjects, predicates, objects })
+    }
+
+    /// write the sections without control information
+    pub(crate) fn write_sections(&self, write: &mut impl Write) -> Result<()> {
+        use SectKind::*;
+        self.shared.write(write).map_err(|e| DictSectError { e, sect_kind: Shared })?;
+        self.subjects.write(write).map_err(|e| DictSectError { e, sect_kind: Subject })?;
+        self.predicates.write(write).map_err(|e| DictSectError { e, sect_kind: Predicate })?;
+        write.write_all(&encode_vbyte(self.objects.len()))?;
+        for (datatype, sect) in &self.objects {
+            write.write_all(&encode_vbyte(datatype.len()))?;
+            write.write_all(datatype.as_bytes())?;
+            sect.write(write).map_err(|e| DictSectError { e, sect_kind: Object })?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads a length prefixed section type.
+fn read_type<R: BufRead>(reader: &mut R) -> Result<String> {
+    let (len, _) = read_vbyte(reader)?;
+    // the length is untrusted, so only the bytes that are actually read are allocated
+    let mut buf = Vec::new();
+    reader.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() < len {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+    String::from_utf8(buf).map_err(|e| crate::four_sect_dict::Error::Other(format!("invalid section type: {e}")))
+}
+
+impl Dictionary for MultSectDict {
+    fn id_to_string(&self, id: Id, id_kind: IdKind) -> core::result::Result<String, ExtractError> {
+        use SectKind::*;
+        let shared_size = self.shared.num_strings();
+        match id_kind {
+            IdKind::Subject if id <= shared_size => {
+                self.shared.extract(id).map_err(|e| ExtractError { e, id, id_kind, sect_kind: Shared })
+            }
+            IdKind::Subject => self.subjects.extract(id - shared_size).map_err(|e| ExtractError {
+                e,
+                id,
+                id_kind,
+                sect_kind: Subject,
+            }),
+            IdKind::Predicate => {
+                self.predicates.extract(id).map_err(|e| ExtractError { e, id, id_kind, sect_kind: Predicate })
+            }
+            IdKind::Object if id <= shared_size => {
+                self.shared.extract(id).map_err(|e| ExtractError { e, id, id_kind, sect_kind: Shared })
+            }
+            IdKind::Object => {
+                let mut local = id - shared_size;
+                for (datatype, sect) in &self.objects {
+                    if local <= sect.num_strings() {
+                        let mut s =
+                            sect.extract(local).map_err(|e| ExtractError { e, id, id_kind, sect_kind: Object })?;
+                        if datatype != NO_DATATYPE && datatype != LANG_STRING {
+                            s.push_str("^^");
+                            s.push_str(datatype);
+                        }
+                        return Ok(s);
+                    }
+                    local -= sect.num_strings();
+                }
+                let len = self.num_terms(IdKind::Object);
+                let e = dict_sect_pfc::ExtractError::IdOutOfBounds { id, len };
+                Err(ExtractError { e, id, id_kind, sect_kind: Object })
+            }
+        }
+    }
+
+    fn string_to_id(&self, s: &str, id_kind: IdKind) -> Id {
+        let shared_size = self.shared.num_strings();
+        let local = |sect: &DictSectPFC, key: &str, offset: usize| match sect.string_to_id(key) {
+            0 => 0,
+            id => id + offset,
+        };
+        match id_kind {
+            IdKind::Predicate => self.predicates.string_to_id(s),
+            IdKind::Subject => match self.shared.string_to_id(s) {
+                0 => local(&self.subjects, s, shared_size),
+                id => id,
+            },
+            IdKind::Object => {
+                let id = self.shared.string_to_id(s);
+                if id > 0 {
+                    return id;
+                }
+                let (datatype, key) = split_type(s);
+                let mut offset = shared_size;
+                for (t, sect) in &self.objects {
+                    if t == datatype {
+                        return local(sect, key, offset);
+                    }
+                    offset += sect.num_strings();
+                }
+                0
+            }
+        }
+    }
+
+    fn num_shared(&self) -> usize {
+        self.shared.num_strings()
+    }
+
+    fn num_terms(&self, id_kind: IdKind) -> usize {
+        match id_kind {
+            IdKind::Subject => self.shared.num_strings() + self.subjects.num_strings(),
+            IdKind::Predicate => self.predicates.num_strings(),
+            IdKind::Object => {
+                self.shared.num_strings() + self.objects.iter().map(|(_, sect)| sect.num_strings()).sum::<usize>()
+            }
+        }
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        self.shared.size_in_bytes()
+            + self.subjects.size_in_bytes()
+            + self.predicates.size_in_bytes()
+            + self.objects.iter().map(|(datatype, sect)| datatype.len() + sect.size_in_bytes()).sum::<usize>()
+    }
+
+    fn search(&self, prefix: &str, id_kind: IdKind) -> Box<dyn Iterator<Item = Id> + '_> {
+        let shared_size = self.shared.num_strings();
+        let shift = |range: Range<Id>, offset: usize| range.start + offset..range.end + offset;
+        match id_kind {
+            IdKind::Predicate => Box::new(self.predicates.prefix_range(prefix)),
+            IdKind::Subject => Box::new(
+                self.shared.prefix_range(prefix).chain(shift(self.subjects.prefix_range(prefix), shared_size)),
+            ),
+            IdKind::Object => {
+                let mut ranges = vec![self.shared.prefix_range(prefix)];
+                let mut offset = shared_size;
+                for (_, sect) in &self.objects {
+                    ranges.push(shift(sect.prefix_range(prefix), offset));
+                    offset += sect.num_strings();
+                }
+                Box::new(ranges.into_iter().flatten())
+            }
+        }
+    }
+}
+
+#[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
+type Pending = JoinHandle<dict_sect_pfc::Result<DictSectPFC>>;
+
+#[cfg(any(target_arch = "wasm32", target_arch = "wasm64"))]
+type Pending = DictSectPFC;
+
+/// A wrapper to ensure prevent using MultSectDict before its checksums have been validated
+pub struct UnvalidatedMultSectDict {
+    shared: Pending,
+    subjects: Pending,
+    predicates: Pending,
+    objects: Vec<(String, Pending)>,
+}
+
+impl UnvalidatedMultSectDict {
+    /// Validates the checksums of all dictionary sections, which are verified in parallel on non-WASM targets.
+    pub fn validate(self) -> Result<MultSectDict> {
+        use SectKind::*;
+        #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
+        let f = |p: Pending, sect_kind| p.join().unwrap().map_err(|e| DictSectError { e, sect_kind });
+        #[cfg(any(target_arch = "wasm32", target_arch = "wasm64"))]
+        let f = |p: Pending, _| Ok::<_, DictSectError>(p);
+        let shared = f(self.shared, Shared)?;
+        let subjects = f(self.subjects, Subject)?;
+        let predicates = f(self.predicates, Predicate)?;
+        let objects = self
+            .objects
+            .into_iter()
+            .map(|(datatype, p)| Ok((datatype, f(p, Object)?)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(MultSectDict { shared, subjects, predicates, objects })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dictionary::Dict;
+    use crate::hdt::tests::snikmeta;
+    use crate::tests::init;
+    use pretty_assertions::assert_eq;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    #[test]
+    fn write_read() -> color_eyre::Result<()> {
+        init();
+        let hdt = snikmeta()?;
+        let Dict::Four(four) = &hdt.dict else { panic!("snikmeta should have a four section dictionary") };
+        // split the object section by type like hdt-java does
+        let objects: Vec<String> = (1..=four.objects.num_strings())
+            .map(|i| four.objects.extract(i))
+            .collect::<std::result::Result<_, _>>()?;
+        let mut by_type = BTreeMap::<&str, BTreeSet<&str>>::new();
+        for s in &objects {
+            let (datatype, key) = split_type(s);
+            by_type.entry(datatype).or_default().insert(key);
+        }
+        assert!(by_type.len() > 1, "snikmeta should contain typed literals");
+        // DictSectPFC is not Clone, so build copies of the other sections from their strings
+        let recompress = |sect: &DictSectPFC| {
+            let strings: Vec<String> = (1..=sect.num_strings()).map(|i| sect.extract(i).unwrap()).collect();
+            DictSectPFC::compress(&strings.iter().map(String::as_str).collect(), 16)
+        };
+        let mult = MultSectDict {
+            shared: recompress(&four.shared),
+            subjects: recompress(&four.subjects),
+            predicates: recompress(&four.predicates),
+            objects: by_type
+                .into_iter()
+                .map(|(datatype, keys)| (datatype.to_owned(), DictSectPFC::compress(&keys, 16)))
+                .collect(),
+        };
+        assert_eq!(mult.num_terms(IdKind::Object), four.num_terms(IdKind::Object));
+        for id in 1..=mult.num_terms(IdKind::Object) {
+            let s = mult.id_to_string(id, IdKind::Object)?;
+            assert_ne!(0, four.string_to_id(&s, IdKind::Object), "unknown object {s}");
+            assert_eq!(id, mult.string_to_id(&s, IdKind::Object), "object id {id} -> {s}");
+        }
+        assert!(mult.id_to_string(mult.num_terms(IdKind::Object) + 1, IdKind::Object).is_err());
+        for kind in [IdKind::Subject, IdKind::Predicate] {
+            for id in 1..=mult.num_terms(kind) {
+                assert_eq!(four.id_to_string(id, kind)?, mult.id_to_string(id, kind)?);
+            }
+        }
+
+        let dict = Dict::Mult(mult);
+        let mut buf = Vec::new();
+        dict.write(&mut buf)?;
+        let dict2 = Dict::read(&mut std::io::Cursor::new(&buf))?.validate()?;
+        assert_eq!(dict, dict2);
+        Ok(())
+    }
+}
@@ -0,0 +1,399 @@
+//! Additions and deletions on top of an immutable HDT.
+//! Terms that are not in the base dictionary in the kind they are used get the IDs after the last base ID of that kind,
+//! so that the added triples can be stored and queried by ID together with the base triples.
+use super::cat::{Part, merge};
+use super::{BLOCK_SIZE, Result, StringTriple};
+use crate::four_sect_dict::ExtractError;
+use crate::header::Header;
+use crate::triples::{Id, TripleId, TriplesBitmap};
+use crate::{DictSectPFC, Dictionary, FourSectDict, Hdt, IdKind};
+use std::collections::{BTreeSet, HashMap};
+use std::iter;
+use std::sync::Arc;
+
+/// An HDT that supports adding and removing triples while the loaded HDT stays unchanged.
+/// Added triples are kept in memory with their own dictionary for new terms
+/// and removed triples of the base HDT are marked in a bitmap over their positions in the triples section.
+/// Queries transparently combine both, use [`MutableHdt::compact`] to create a new HDT that incorporates the changes.
+/// Terms use the string representation of the dictionary, see [`crate::Dictionary`].
+/// # Example
+/// ```
+/// use hdt::{Hdt, MutableHdt};
+/// let file = std::fs::File::open("tests/resources/snikmeta.hdt").unwrap();
+/// let mut graph = MutableHdt::new(Hdt::read(std::io::BufReader::new(file)).unwrap());
+/// let s = "http://www.snik.eu/ontology/meta/Top";
+/// let label = "http://www.w3.org/2000/01/rdf-schema#label";
+/// assert!(graph.insert(s, label, "\"Oberklasse\"@de"));
+/// assert!(graph.triples_with_pattern(Some(s), Some(label), Some("\"Oberklasse\"@de")).next().is_some());
+/// let compacted: Hdt = graph.compact().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct MutableHdt {
+    base: Hdt,
+    /// terms that are missing from the base dictionary in at least one kind
+    new_terms: Vec<String>,
+    new_term_indices: HashMap<String, usize>,
+    /// added triples in subject, predicate, object order
+    added: BTreeSet<TripleId>,
+    /// one bit for each triple of the base triples section, allocated on the first removal
+    deleted: Vec<u64>,
+    num_deleted: usize,
+}
+
+impl MutableHdt {
+    /// Wraps the given HDT without any changes.
+    pub fn new(base: Hdt) -> Self {
+        MutableHdt {
+            base,
+            new_terms: Vec::new(),
+            new_term_indices: HashMap::new(),
+            added: BTreeSet::new(),
+            deleted: Vec::new(),
+            num_deleted: 0,
+        }
+    }
+
+    /// The unchanged HDT that the changes are applied to.
+    pub const fn base(&self) -> &Hdt {
+        &self.base
+    }
+
+    /// Number of triples including the changes.
+    pub fn len(&self) -> usize {
+        self.base.triples.len() - self.num_deleted + self.added.len()
+    }
+
+    /// Whether there are no triples left.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether triples have been added to or removed from the base HDT.
+    pub fn is_modified(&self) -> bool {
+        self.num_deleted > 0 || !self.added.is_empty()
+    }
+
+    /// Get the ID for a given string of a given kind in the combined ID space of base and new terms, or 0 if not found.
+    pub fn string_to_id(&self, s: &str, kind: IdKind) -> Id {
+        match self.base.dict.string_to_id(s, kind) {
+            0 => self.new_term_indices.get(s).map_or(0, |i| self.base.dict.num_terms(kind) + 1 + i),
+            id => id,
+        }
+    }
+
+    /// Get the string value of an ID of a given kind in the combined ID space of base and new terms.
+    pub fn id_to_string(&self, id: Id, kind: IdKind) -> core::result::Result<String, ExtractError> {
+        match id.checked_sub(self.base.dict.num_terms(kind) + 1).and_then(|i| self.new_terms.get(i)) {
+            Some(s) => Ok(s.clone()),
+            None => self.base.dict.id_to_string(id, kind),
+        }
+    }
+
+    /// Get the ID for the given string of the given kind, creating a new term if necessary.
+    fn intern(&mut self, s: &str, kind: IdKind) -> Id {
+        match self.string_to_id(s, kind) {
+            0 => {
+                self.new_term_indices.insert(s.to_owned(), self.new_terms.len());
+                self.new_terms.push(s.to_owned());
+                self.base.dict.num_terms(kind) + self.new_terms.len()
+            }
+            id => id,
+        }
+    }
+
+    fn base_position(&self, triple: TripleId) -> Option<usize> {
+        self.base.triples.position(triple)
+    }
+
+    fn is_deleted(&self, position: usize) -> bool {
+        self.deleted.get(position / 64).is_some_and(|word| (word >> (position % 64)) & 1 == 1)
+    }
+
+    /// Adds a triple, returns false if it was already contained.
+    pub fn insert(&mut self, s: &str, p: &str, o: &str) -> bool {
+        let triple =
+            [(s, IdKind::Subject), (p, IdKind::Predicate), (o, IdKind::Object)].map(|(x, k)| self.intern(x, k));
+        if let Some(position) = self.base_position(triple) {
+            if !self.is_deleted(position) {
+                return false;
+            }
+            self.deleted[position / 64] &= !(1 << (position % 64));
+            self.num_deleted -= 1;
+            return true;
+        }
+        self.added.insert(triple)
+    }
+
+    /// Removes a triple, returns false if it was not contained.
+    pub fn remove(&mut self, s: &str, p: &str, o: &str) -> bool {
+        let triple = [(s, IdKind::Subject), (p, IdKind::Predicate), (o, IdKind::Object)]
+            .map(|(x, k)| self.string_to_id(x, k));
+        if triple.contains(&0) {
+            return false;
+        }
+        if self.added.remove(&triple) {
+            return true;
+        }
+        let Some(position) = self.base_position(triple) else { return false };
+        if self.is_deleted(position) {
+            return false;
+        }
+        if self.deleted.is_empty() {
+            self.deleted = vec![0; self.base.triples.len().div_ceil(64)];
+        }
+        self.deleted[position / 64] |= 1 << (position % 64);
+        self.num_deleted += 1;
+        true
+    }
+
+    /// Iterate over all triple IDs of the combined ID space that fit the given pattern in subject, predicate, object order, where 0 stands for a variable.
+    /// Remaining base triples come first, followed by the added triples.
+    pub fn triple_ids_with_pattern(&self, pattern: TripleId) -> Box<dyn Iterator<Item = TripleId> + '_> {
+        let in_base = IdKind::KINDS.iter().zip(pattern).all(|(k, id)| id <= self.base.dict.num_terms(*k));
+        let base: Box<dyn Iterator<Item = TripleId> + '_> = match (in_base, self.num_deleted) {
+            (false, _) => Box::new(iter::empty()),
+            (true, 0) => self.base.triples.triples_with_pattern(pattern),
+            (true, _) => Box::new(
+                self.base
+                    .triples
+                    .triples_with_pattern(pattern)
+                    .filter(|t| self.base_position(*t).is_none_or(|pos| !self.is_deleted(pos))),
+            ),
+        };
+        let added: Box<dyn Iterator<Item = &TripleId> + '_> = if pattern[0] == 0 {
+            Box::new(self.added.iter())
+        } else {
+            Box::new(self.added.range([pattern[0], 0, 0]..=[pattern[0], Id::MAX, Id::MAX]))
+        };
+        let added = added.filter(move |t| (0..3).all(|i| pattern[i] == 0 || pattern[i] == t[i])).copied();
+        Box::new(base.chain(added))
+    }
+
+    /// Get all triples that fit the given triple patterns, where `None` stands for a variable.
+    pub fn triples_with_pattern(
+        &self, sp: Option<&str>, pp: Option<&str>, op: Option<&str>,
+    ) -> Box<dyn Iterator<Item = StringTriple> + '_> {
+        let mut pattern = [0; 3];
+        for ((x, kind), id) in [sp, pp, op].into_iter().zip(IdKind::KINDS).zip(&mut pattern) {
+            if let Some(x) = x {
+                *id = self.string_to_id(x, kind);
+                // term does not exist in the graph
+                if *id == 0 {
+                    return Box::new(iter::empty());
+                }
+            }
+        }
+        // TODO: improve error handling
+        Box::new(
+            self.triple_ids_with_pattern(pattern)
+                .map(move |t| [0, 1, 2].map(|i| Arc::from(self.id_to_string(t[i], IdKind::KINDS[i]).unwrap()))),
+        )
+    }
+
+    /// The added triples as a separate HDT.
+    fn added_hdt(&self) -> Result<Hdt> {
+        let strings = self
+            .added
+            .iter()
+            .map(|t| [0, 1, 2].map(|i| self.id_to_string(t[i], IdKind::KINDS[i])))
+            .map(|[s, p, o]| Ok([s?, p?, o?]))
+            .collect::<Result<Vec<_>>>()?;
+        let mut sets: [BTreeSet<&str>; 3] = Default::default();
+        for triple in &strings {
+            for (set, s) in sets.iter_mut().zip(triple) {
+                set.insert(s.as_str());
+            }
+        }
+        let [subjects, predicates, objects] = sets;
+        let shared: BTreeSet<&str> = subjects.intersection(&objects).copied().collect();
+        let subjects: BTreeSet<&str> = subjects.difference(&shared).copied().collect();
+        let objects: BTreeSet<&str> = objects.difference(&shared).copied().collect();
+        let dict = FourSectDict {
+            shared: DictSectPFC::compress(&shared, BLOCK_SIZE),
+            subjects: DictSectPFC::compress(&subjects, BLOCK_SIZE),
+            predicates: DictSectPFC::compress(&predicates, BLOCK_SIZE),
+            objects: DictSectPFC::compress(&objects, BLOCK_SIZE),
+        };
+        let mut triples: Vec<TripleId> =
+            strings.iter().map(|t| [0, 1, 2].map(|i| dict.string_to_id(&t[i], IdKind::KINDS[i]))).collect();
+        triples.sort_unstable();
+        let header = Header { format: "ntriples".to_owned(), length: 0, body: BTreeSet::new() };
+        Ok(Hdt::from_sections(header, dict.into(), TriplesBitmap::from_triples(&triples)))
+    }
+
+    /// Creates a new HDT with SPO order that incorporates the changes, which can then be saved with [`Hdt::write`].
+    /// Terms that are no longer used by any triple are left out.
+    pub fn compact(&self) -> Result<Hdt> {
+        let added = self.added_hdt()?;
+        let base_triples = (self.num_deleted > 0).then(|| {
+            self.base
+                .triples
+                .into_iter()
+                .enumerate()
+                .filter(|(pos, _)| !self.is_deleted(*pos))
+                .map(|(_, t)| t)
+                .collect()
+        });
+        merge(&[Part { hdt: &self.base, triples: base_triples }, Part { hdt: &added, triples: None }])
+    }
+}
+
+impl From<Hdt> for MutableHdt {
+    fn from(base: Hdt) -> Self {
+        Self::new(base)
+    }
+}
+
+#[cfg(feature = "sophia")]
+mod graph {
+    use super::MutableHdt;
+    use crate::hdt_graph::{HdtTerm, auto_term, sophia_term_string};
+    use sophia::api::graph::{Graph, MgResult, MutableGraph};
+    use sophia::api::term::matcher::{Any, TermMatcher};
+    use sophia::api::term::{IriRef, Term};
+    use std::convert::Infallible;
+    use std::io;
+    use std::iter;
+
+    /// The string of a constant matcher, `Some(None)` if it is a constant that cannot be stored in HDT and thus never matches.
+    fn constant<T: TermMatcher>(tm: &T) -> Option<Option<String>> {
+        tm.constant().map(|t| sophia_term_string(t.borrow_term()).ok())
+    }
+
+    impl Graph for MutableHdt {
+        type Triple<'a> = [HdtTerm; 3];
+        type Error = Infallible;
+
+        fn triples(&self) -> impl Iterator<Item = Result<Self::Triple<'_>, Self::Error>> {
+            self.triples_matching(Any, Any, Any)
+        }
+
+        /// Constant matchers are answered by ID, other matchers are applied to the results.
+        fn triples_matching<'s, S, P, O>(
+            &'s self, sm: S, pm: P, om: O,
+        ) -> impl Iterator<Item = Result<Self::Triple<'s>, Self::Error>> + 's
+        where
+            S: TermMatcher + 's,
+            P: TermMatcher + 's,
+            O: TermMatcher + 's,
+        {
+            let (s, p, o) = (constant(&sm), constant(&pm), constant(&om));
+            if [&s, &p, &o].iter().any(|c| matches!(c, Some(None))) {
+                return Box::new(iter::empty()) as Box<dyn Iterator<Item = _>>;
+            }
+            let [s, p, o] = [s, p, o].map(Option::flatten);
+            Box::new(
+                self.triples_with_pattern(s.as_deref(), p.as_deref(), o.as_deref())
+                    .map(|[s, p, o]| {
+                        [auto_term(&s).unwrap(), HdtTerm::Iri(IriRef::new_unchecked(p)), auto_term(&o).unwrap()]
+                    })
+                    .filter(move |[st, pt, ot]| sm.matches(st) && pm.matches(pt) && om.matches(ot))
+                    .map(Ok),
+            )
+        }
+    }
+
+    impl MutableGraph for MutableHdt {
+        type MutationError = io::Error;
+
+        fn insert<TS, TP, TO>(&mut self, s: TS, p: TP, o: TO) -> MgResult<Self, bool>
+        where
+            TS: Term,
+            TP: Term,
+            TO: Term,
+        {
+            let (s, p, o) = (sophia_term_string(s)?, sophia_term_string(p)?, sophia_term_string(o)?);
+            Ok(MutableHdt::insert(self, &s, &p, &o))
+        }
+
+        fn remove<TS, TP, TO>(&mut self, s: TS, p: TP, o: TO) -> MgResult<Self, bool>
+        where
+            TS: Term,
+            TP: Term,
+            TO: Term,
+        {
+            // terms that cannot be stored in HDT are never contained
+            let (Ok(s), Ok(p), Ok(o)) = (sophia_term_string(s), sophia_term_string(p), sophia_term_string(o))
+            else {
+                return Ok(false);
+            };
+            Ok(MutableHdt::remove(self, &s, &p, &o))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hdt::tests::{snikmeta, snikmeta_check};
+    use crate::tests::init;
+    use pretty_assertions::assert_eq;
+
+    fn sorted(triples: impl Iterator<Item = StringTriple>) -> Vec<StringTriple> {
+        let mut v: Vec<_> = triples.collect();
+        v.sort();
+        v
+    }
+
+    #[test]
+    fn mutable_hdt() -> color_eyre::Result<()> {
+        init();
+        let mut graph = MutableHdt::new(snikmeta()?);
+        let mut expected = sorted(graph.base().triples_all());
+        assert!(!graph.is_modified());
+        // unchanged
+        snikmeta_check(&graph.compact()?)?;
+
+        // a triple whose object is an IRI that can also be used as a subject
+        let i = expected.iter().position(|t| t[2].starts_with("http")).unwrap();
+        let [s, p, o] = expected[i].clone();
+        assert!(!graph.insert(&s, &p, &o), "existing triple should not be inserted again");
+        assert!(graph.remove(&s, &p, &o));
+        assert!(!graph.remove(&s, &p, &o), "removed triple should not be removed again");
+        assert_eq!(graph.triples_with_pattern(Some(&s), Some(&p), Some(&o)).count(), 0);
+        assert_eq!(graph.len(), expected.len() - 1);
+        assert!(graph.insert(&s, &p, &o), "removed triple should be insertable again");
+        assert_eq!(graph.triples_with_pattern(Some(&s), Some(&p), Some(&o)).count(), 1);
+        assert!(graph.remove(&s, &p, &o));
+        expected.remove(i);
+
+        // new subject, an object that becomes a subject and a new literal
+        let new_s = "http://example.org/new";
+        let label = "http://www.w3.org/2000/01/rdf-schema#label";
+        let new = [[new_s, label, "\"neu\"@de"], [&*o, label, "\"new\"@en"], [new_s, "http://example.org/p", &*s]];
+        for [s, p, o] in new {
+            assert!(graph.insert(s, p, o));
+            assert!(!graph.insert(s, p, o));
+            expected.push([s, p, o].map(Arc::from));
+        }
+        expected.sort();
+        assert_eq!(graph.len(), expected.len());
+        assert_eq!(sorted(graph.triples_with_pattern(None, None, None)), expected);
+        assert_eq!(graph.triples_with_pattern(Some(new_s), None, None).count(), 2);
+        assert_eq!(graph.triples_with_pattern(None, Some(label), Some("\"neu\"@de")).count(), 1);
+        assert_eq!(
+            graph.triples_with_pattern(None, None, Some(&s)).count(),
+            1 + graph.base().triples_with_pattern(None, None, Some(&s)).count()
+        );
+
+        let compacted = graph.compact()?;
+        assert_eq!(sorted(compacted.triples_all()), expected);
+        #[cfg(feature = "sophia")]
+        {
+            use crate::hdt_graph::HdtTerm;
+            use sophia::api::graph::{Graph, MutableGraph};
+            use sophia::api::term::{IriRef, LanguageTag, matcher::Any};
+            let new_s = HdtTerm::Iri(IriRef::new_unchecked(Arc::from(new_s)));
+            let label = HdtTerm::Iri(IriRef::new_unchecked(Arc::from(label)));
+            let neu = HdtTerm::LiteralLanguage(Arc::from("neu"), LanguageTag::new_unchecked(Arc::from("de")));
+            assert_eq!(graph.triples_matching([&new_s], Any, Any).count(), 2);
+            assert_eq!(graph.triples_matching(Any, Any, [&neu]).count(), 1);
+            assert_eq!(graph.triples().count(), expected.len());
+            assert!(MutableGraph::remove(&mut graph, &new_s, &label, &neu)?);
+            assert!(!MutableGraph::remove(&mut graph, &new_s, &label, &neu)?);
+            assert_eq!(graph.triples_matching([&new_s], Any, Any).count(), 1);
+            assert!(MutableGraph::insert(&mut graph, &new_s, &label, &neu)?);
+        }
+        Ok(())
+    }
+}
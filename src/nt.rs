@@ -1,14 +1,18 @@
 // //! *This module is available only if HDT is built with the experimental `"nt"` feature.*
+use super::BLOCK_SIZE;
+use crate::compression::{self, Compression};
 use crate::header::Header;
-use crate::triples::{TripleId, TriplesBitmap};
+use crate::triples::{Order, TripleId, TriplesBitmap};
 use crate::{DictSectPFC, FourSectDict, Hdt, IdKind};
 use bitset_core::BitSet;
 use bytesize::ByteSize;
 use lasso::{Key, Spur, ThreadedRodeo};
 use log::{debug, error};
-use oxttl::NTriplesParser;
+use oxrdfxml::RdfXmlParser;
+use oxttl::{NQuadsParser, NTriplesParser, TriGParser, TurtleParser};
 use rayon::prelude::*;
 use std::collections::BTreeSet;
+use std::io;
 use std::path::Path;
 use std::sync::Arc;
 use std::thread;
@@ -17,8 +21,41 @@ pub type Result<T> = std::io::Result<T>;
 type Simd = [u64; 4];
 type Indices = Vec<Simd>;
 
+/// RDF serialization formats that can be converted to HDT.
+/// *This type is available only if HDT is built with the experimental `"nt"` feature.*
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RdfFormat {
+    /// <https://www.w3.org/TR/n-triples/>
+    NTriples,
+    /// <https://www.w3.org/TR/n-quads/>, graph names are dropped so that all graphs are merged
+    NQuads,
+    /// <https://www.w3.org/TR/turtle/>
+    Turtle,
+    /// <https://www.w3.org/TR/trig/>, graph names are dropped so that all graphs are merged
+    TriG,
+    /// <https://www.w3.org/TR/rdf-syntax-grammar/>
+    RdfXml,
+}
+
+impl RdfFormat {
+    /// Determines the format from the file extension, e.g. "ttl" for Turtle.
+    /// The extension of a compression format is skipped, e.g. "data.ttl.gz" is Turtle as well.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        use RdfFormat::*;
+        Some(match compression::strip_extension(path).extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "nt" => NTriples,
+            "nq" => NQuads,
+            "ttl" => Turtle,
+            "trig" => TriG,
+            "rdf" | "owl" | "xml" => RdfXml,
+            _ => return None,
+        })
+    }
+}
+
 impl Hdt {
     /// Converts RDF N-Triples to HDT with a FourSectionDictionary with DictionarySectionPlainFrontCoding and SPO order.
+    /// Compressed files are decompressed on the fly, see [`crate::compression`], but are parsed sequentially.
     /// *This function is available only if HDT is built with the experimental `"nt"` feature.*
     /// # Example
     /// ```no_run
@@ -26,94 +63,71 @@ impl Hdt {
     /// let hdt = hdt::Hdt::read_nt(path).unwrap();
     /// ```
     pub fn read_nt(f: &Path) -> Result<Self> {
-        const BLOCK_SIZE: usize = 16;
+        Self::read_nt_with_order(f, Order::SPO)
+    }
+
+    /// Converts RDF N-Triples to HDT like [`Hdt::read_nt`] but with the given order of the triples section.
+    /// *This function is available only if HDT is built with the experimental `"nt"` feature.*
+    /// # Example
+    /// ```no_run
+    /// let path = std::path::Path::new("example.nt");
+    /// let hdt = hdt::Hdt::read_nt_with_order(path, hdt::triples::Order::POS).unwrap();
+    /// ```
+    pub fn read_nt_with_order(f: &Path, order: Order) -> Result<Self> {
+        Self::read_rdf_with_order(f, RdfFormat::NTriples, order)
+    }
 
-        let (dict, mut encoded_triples) = read_dict_triples(f, BLOCK_SIZE)?;
+    /// Converts RDF in the given format to HDT with a FourSectionDictionary with DictionarySectionPlainFrontCoding and SPO order.
+    /// Only uncompressed N-Triples are parsed in parallel, the other formats and compressed files, see [`crate::compression`],
+    /// are parsed sequentially but share the parallel dictionary building.
+    /// *This function is available only if HDT is built with the experimental `"nt"` feature.*
+    /// # Example
+    /// ```no_run
+    /// use hdt::{Hdt, RdfFormat};
+    /// let hdt = Hdt::read_rdf(std::path::Path::new("example.ttl"), RdfFormat::Turtle).unwrap();
+    /// ```
+    pub fn read_rdf(f: &Path, format: RdfFormat) -> Result<Self> {
+        Self::read_rdf_with_order(f, format, Order::SPO)
+    }
+
+    /// Converts RDF in the given format to HDT like [`Hdt::read_rdf`] but with the given order of the triples section.
+    /// *This function is available only if HDT is built with the experimental `"nt"` feature.*
+    pub fn read_rdf_with_order(f: &Path, format: RdfFormat, order: Order) -> Result<Self> {
+        let pool = parse_terms(f, format)?;
+        Self::from_pool(pool, order, &file_iri(f)?, Some(f))
+    }
+
+    /// Builds the dictionary and the triples from the interned terms and populates the header.
+    pub(super) fn from_pool(pool: IndexPool, order: Order, base_iri: &str, source: Option<&Path>) -> Result<Self> {
+        if order == Order::Unknown {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "unknown triples order"));
+        }
+        let (dict, mut encoded_triples) = encode_pool(pool, BLOCK_SIZE)?;
         let num_triples = encoded_triples.len();
-        encoded_triples.sort_unstable();
-        let triples = TriplesBitmap::from_triples(&encoded_triples);
+        let triples = if order == Order::SPO {
+            encoded_triples.sort_unstable();
+            TriplesBitmap::from_triples(&encoded_triples)
+        } else {
+            TriplesBitmap::from_triples_with_order(&encoded_triples, order)
+        };
 
         let header = Header { format: "ntriples".to_owned(), length: 0, body: BTreeSet::new() };
-        let mut hdt = Hdt { header, dict, triples };
-        hdt.fill_header(f, BLOCK_SIZE, num_triples)?;
+        let mut hdt = Hdt::from_sections(header, dict.into(), triples);
+        hdt.fill_header(base_iri, source, BLOCK_SIZE, num_triples)?;
 
         debug!("HDT size in memory {}, details:", ByteSize(hdt.size_in_bytes() as u64));
         debug!("{hdt:#?}");
         Ok(hdt)
     }
+}
 
-    /// Populate HDT header fields.
-    /// Some fields may be optional, populating same triples as those in C++ version for now.
-    fn fill_header(&mut self, path: &Path, block_size: usize, num_triples: usize) -> Result<()> {
-        use crate::containers::rdf::Term::Literal as Lit;
-        use crate::containers::rdf::{Id, Literal, Term, Triple};
-        use crate::vocab::*;
-        use std::io::Write;
-
-        const ORDER: &str = "SPO";
-
-        macro_rules! literal {
-            ($s:expr, $p:expr, $o:expr) => {
-                self.header.body.insert(Triple::new($s.clone(), $p.to_owned(), Lit(Literal::new($o.to_string()))));
-            };
-        }
-        macro_rules! insert_id {
-            ($s:expr, $p:expr, $o:expr) => {
-                self.header.body.insert(Triple::new($s.clone(), $p.to_owned(), Term::Id($o.clone())));
-            };
-        }
-        // as this is "just" metadata, we could also add a fallback if there ever is a valid use case, e.g. loading from stream instead of file
-        let file_iri = format!("file://{}", path.canonicalize()?.display());
-        let base = Id::Named(file_iri);
-
-        literal!(base, RDF_TYPE, HDT_CONTAINER);
-        literal!(base, RDF_TYPE, VOID_DATASET);
-        literal!(base, VOID_TRIPLES, num_triples);
-        literal!(base, VOID_PROPERTIES, self.dict.predicates.num_strings);
-        let [d_s, d_o] =
-            [&self.dict.subjects, &self.dict.objects].map(|s| s.num_strings + self.dict.shared.num_strings);
-        literal!(base, VOID_DISTINCT_SUBJECTS, d_s);
-        literal!(base, VOID_DISTINCT_OBJECTS, d_o);
-        // // TODO: Add more VOID Properties. E.g. void:classes
-
-        // // Structure
-        let stats_id = Id::Blank("statistics".to_owned());
-        let pub_id = Id::Blank("publicationInformation".to_owned());
-        let format_id = Id::Blank("format".to_owned());
-        let dict_id = Id::Blank("dictionary".to_owned());
-        let triples_id = Id::Blank("triples".to_owned());
-        insert_id!(base, HDT_STATISTICAL_INFORMATION, stats_id);
-        insert_id!(base, HDT_STATISTICAL_INFORMATION, pub_id);
-        insert_id!(base, HDT_FORMAT_INFORMATION, format_id);
-        insert_id!(format_id, HDT_DICTIONARY, dict_id);
-        insert_id!(format_id, HDT_TRIPLES, triples_id);
-        // DICTIONARY
-        literal!(dict_id, HDT_DICT_SHARED_SO, self.dict.shared.num_strings);
-        literal!(dict_id, HDT_DICT_MAPPING, "1");
-        literal!(dict_id, HDT_DICT_SIZE_STRINGS, ByteSize(self.dict.size_in_bytes() as u64));
-        literal!(dict_id, HDT_DICT_BLOCK_SIZE, block_size);
-        // TRIPLES
-        literal!(triples_id, DC_TERMS_FORMAT, HDT_TYPE_BITMAP);
-        literal!(triples_id, HDT_NUM_TRIPLES, num_triples);
-        literal!(triples_id, HDT_TRIPLES_ORDER, ORDER);
-        // // Sizes
-        let meta = std::fs::File::open(path)?.metadata()?;
-        literal!(stats_id, HDT_ORIGINAL_SIZE, meta.len());
-        // a few bytes off because that literal itself is not counted
-        literal!(stats_id, HDT_SIZE, ByteSize(self.size_in_bytes() as u64));
-        // exclude for now to skip dependency on chrono
-        //let datetime_str = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%z").to_string();
-        //literal!(pub_id,DC_TERMS_ISSUED,datetime_str);
-        let mut buf = Vec::<u8>::new();
-        for triple in &self.header.body {
-            writeln!(buf, "{triple}")?;
-        }
-        self.header.length = buf.len();
-        Ok(())
-    }
+/// Base IRI of the header, which identifies the dataset by the file it was converted from.
+pub(super) fn file_iri(path: &Path) -> Result<String> {
+    Ok(format!("file://{}", path.canonicalize()?.display()))
 }
 
-struct IndexPool {
+/// Interned terms and the triples referencing them by index.
+pub(super) struct IndexPool {
     triples: Vec<[usize; 3]>,
     subjects: Indices,
     objects: Indices,
@@ -121,12 +135,9 @@ struct IndexPool {
     strings: Vec<String>,
 }
 
-/// read N-Triples and convert them to a dictionary and triple IDs
-fn read_dict_triples(path: &Path, block_size: usize) -> Result<(FourSectDict, Vec<TripleId>)> {
-    // 1. Parse N-Triples and collect terms using string interning
-    let mut pool = parse_nt_terms(path)?;
-
-    // Sort and deduplicate triples in parallel with dictionary building
+/// convert the interned terms to a dictionary and triple IDs
+fn encode_pool(mut pool: IndexPool, block_size: usize) -> Result<(FourSectDict, Vec<TripleId>)> {
+    // 1. Sort and deduplicate triples in parallel with dictionary building
     let mut triples = std::mem::take(&mut pool.triples); // not needed anymore
     let sorter = thread::Builder::new().name("sorter".to_owned()).spawn(move || {
         triples.sort_unstable();
@@ -161,61 +172,107 @@ fn read_dict_triples(path: &Path, block_size: usize) -> Result<(FourSectDict, Ve
     Ok((dict, encoded_triples))
 }
 
-/// Parse N-Triples in parallel and collect terms into sets
-fn parse_nt_terms(path: &Path) -> Result<IndexPool> {
+/// Removes the angle brackets around IRIs, as the dictionary stores IRIs without them.
+pub(super) fn clean_term(s: &mut String) {
+    let mut chars = s.chars();
+    if chars.next() == Some('<') && chars.nth_back(0) == Some('>') {
+        s.remove(0);
+        s.pop();
+    }
+}
+
+/// Subject, predicate and object of a parsed triple or quad as dictionary strings.
+fn clean_strings(s: &impl ToString, p: &impl ToString, o: &impl ToString) -> [String; 3] {
+    [s.to_string(), p.to_string(), o.to_string()].map(|mut s| {
+        clean_term(&mut s);
+        s
+    })
+}
+
+fn invalid_data(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// Parses the file sequentially into the dictionary strings of each triple, graph names of quads are dropped.
+pub(super) fn term_strings(
+    path: &Path, format: RdfFormat,
+) -> Result<Box<dyn Iterator<Item = Result<[String; 3]>>>> {
+    let reader = compression::open(path)?;
+    Ok(match format {
+        RdfFormat::NTriples => Box::new(
+            NTriplesParser::new()
+                .for_reader(reader)
+                .map(|r| r.map(|t| clean_strings(&t.subject, &t.predicate, &t.object)).map_err(invalid_data)),
+        ),
+        RdfFormat::NQuads => Box::new(
+            NQuadsParser::new()
+                .for_reader(reader)
+                .map(|r| r.map(|q| clean_strings(&q.subject, &q.predicate, &q.object)).map_err(invalid_data)),
+        ),
+        RdfFormat::Turtle => Box::new(
+            TurtleParser::new()
+                .for_reader(reader)
+                .map(|r| r.map(|t| clean_strings(&t.subject, &t.predicate, &t.object)).map_err(invalid_data)),
+        ),
+        RdfFormat::TriG => Box::new(
+            TriGParser::new()
+                .for_reader(reader)
+                .map(|r| r.map(|q| clean_strings(&q.subject, &q.predicate, &q.object)).map_err(invalid_data)),
+        ),
+        RdfFormat::RdfXml => Box::new(
+            RdfXmlParser::new()
+                .for_reader(reader)
+                .map(|r| r.map(|t| clean_strings(&t.subject, &t.predicate, &t.object)).map_err(invalid_data)),
+        ),
+    })
+}
+
+/// Parse RDF and collect terms into sets, uncompressed N-Triples are parsed in parallel
+fn parse_terms(path: &Path, format: RdfFormat) -> Result<IndexPool> {
     let lasso: Arc<ThreadedRodeo<Spur>> = Arc::new(ThreadedRodeo::new());
-    // workaround for bug with lasso v0.7.3 when concurrency is too high, see https://github.com/Kixiron/lasso/issues/48
-    // experiments have always failed with 24, often with 23 and sometimes with 22 threads, choose 16 to be on the safe side
-    // use two threads when available parallelism cannot be determined as going to a single thread is around 38% slower
-    let num_parsers = std::cmp::min(16, thread::available_parallelism().map_or(2, std::num::NonZero::get));
     // Store triple indices instead of strings
-    let readers = NTriplesParser::new().split_file_for_parallel_parsing(path, num_parsers)?;
-    let triples: Vec<[usize; 3]> = readers
-        .into_par_iter()
-        .flat_map_iter(|reader| {
-            //for q in reader {
-            reader.map(|q| {
-                let clean = |s: &mut String| {
-                    let mut chars = s.chars();
-                    if chars.next() == Some('<') && chars.nth_back(0) == Some('>') {
-                        s.remove(0);
-                        s.pop();
-                    }
-                };
-                let q = q.unwrap(); // TODO: error handling
-                let mut subj_str = q.subject.to_string();
-                clean(&mut subj_str);
-                let mut pred_str = q.predicate.to_string();
-                clean(&mut pred_str);
-                let mut obj_str = q.object.to_string();
-                clean(&mut obj_str);
-
-                let s_idx = lasso.get_or_intern(subj_str).into_usize();
-                let p_idx = lasso.get_or_intern(pred_str).into_usize();
-                let o_idx = lasso.get_or_intern(obj_str).into_usize();
-
-                [s_idx, p_idx, o_idx]
+    let intern = |strings: [String; 3]| strings.map(|s| lasso.get_or_intern(s).into_usize());
+    let parallel = format == RdfFormat::NTriples && compression::detect_file(path)? == Compression::None;
+    let triples: Vec<[usize; 3]> = if parallel {
+        // workaround for bug with lasso v0.7.3 when concurrency is too high, see https://github.com/Kixiron/lasso/issues/48
+        // experiments have always failed with 24, often with 23 and sometimes with 22 threads, choose 16 to be on the safe side
+        // use two threads when available parallelism cannot be determined as going to a single thread is around 38% slower
+        let num_parsers = std::cmp::min(16, thread::available_parallelism().map_or(2, std::num::NonZero::get));
+        let readers = NTriplesParser::new().split_file_for_parallel_parsing(path, num_parsers)?;
+        readers
+            .into_par_iter()
+            .flat_map_iter(|reader| {
+                reader.map(|t| {
+                    let t = t.map_err(invalid_data)?;
+                    Ok(intern(clean_strings(&t.subject, &t.predicate, &t.object)))
+                })
             })
-        })
-        .collect();
-    // TODO: error handling
-    //.map_err(|e| Error::Other(format!("Error reading N-Triples: {e:?}")))?;
+            .collect::<Result<_>>()?
+    } else {
+        term_strings(path, format)?.map(|r| r.map(intern)).collect::<Result<_>>()?
+    };
     let lasso = Arc::try_unwrap(lasso).unwrap(); // no parallel usage anymore
-    // Track which indices are subjects/objects/predicates
-    let block = [0u64; 4];
-    let blocks = lasso.len().div_ceil(256);
-    let mut subjects = vec![block; blocks];
-    let mut objects = vec![block; blocks];
-    let mut predicates = vec![block; blocks];
-
-    for [s, p, o] in &triples {
-        subjects.bit_set(*s);
-        predicates.bit_set(*p);
-        objects.bit_set(*o);
-    }
+    Ok(IndexPool::new(lasso, triples))
+}
+
+impl IndexPool {
+    pub(super) fn new(lasso: ThreadedRodeo<Spur>, triples: Vec<[usize; 3]>) -> Self {
+        // Track which indices are subjects/objects/predicates
+        let block = [0u64; 4];
+        let blocks = lasso.len().div_ceil(256);
+        let mut subjects = vec![block; blocks];
+        let mut objects = vec![block; blocks];
+        let mut predicates = vec![block; blocks];
+
+        for [s, p, o] in &triples {
+            subjects.bit_set(*s);
+            predicates.bit_set(*p);
+            objects.bit_set(*o);
+        }
 
-    let strings: Vec<String> = lasso.into_resolver().strings().map(String::from).collect();
-    Ok(IndexPool { triples, subjects, objects, predicates, strings })
+        let strings: Vec<String> = lasso.into_resolver().strings().map(String::from).collect();
+        IndexPool { triples, subjects, objects, predicates, strings }
+    }
 }
 
 /// Build dictionary from collected terms using string pool indices
@@ -270,9 +327,10 @@ fn build_dict_from_terms(pool: &IndexPool, block_size: usize) -> FourSectDict {
 pub mod tests {
     use super::super::StringTriple;
     use super::super::tests::snikmeta_check;
-    use super::Hdt;
+    use super::{Hdt, RdfFormat};
     use crate::hdt::tests::snikmeta;
     use crate::tests::init;
+    use crate::triples::Order;
     use color_eyre::Result;
     use fs_err::File;
     use std::path::Path;
@@ -295,6 +353,15 @@ pub mod tests {
         assert_eq!(nt_triples, hdt_triples);
         assert_eq!(snikmeta.triples.bitmap_y.dict, snikmeta_nt.triples.bitmap_y.dict);
         snikmeta_check(&snikmeta_nt)?;
+        for order in Order::ORDERS {
+            let ordered = Hdt::read_nt_with_order(path, order)?;
+            assert_eq!(ordered.triples.order(), order);
+            let mut ordered_triples: Vec<StringTriple> = ordered.triples_all().collect();
+            ordered_triples.sort_unstable();
+            let mut sorted_triples = hdt_triples.clone();
+            sorted_triples.sort_unstable();
+            assert_eq!(ordered_triples, sorted_triples, "{order:?}");
+        }
         let path = Path::new("tests/resources/empty.nt");
         let hdt_empty = Hdt::read_nt(path)?;
         let mut buf = Vec::<u8>::new();
@@ -302,4 +369,76 @@ pub mod tests {
         Hdt::read(std::io::Cursor::new(buf))?;
         Ok(())
     }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn read_compressed_nt() -> Result<()> {
+        use std::io::Write;
+        init();
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("snikmeta.nt.gz");
+        let mut encoder = flate2::write::GzEncoder::new(File::create(&path)?, flate2::Compression::default());
+        snikmeta()?.write_nt(&mut encoder)?;
+        encoder.finish()?.flush()?;
+        assert_eq!(RdfFormat::from_path(&path), Some(RdfFormat::NTriples));
+        snikmeta_check(&Hdt::read_nt(&path)?)?;
+        Ok(())
+    }
+
+    #[test]
+    fn read_rdf() -> Result<()> {
+        use sophia::api::prelude::TripleSerializer;
+        use sophia::turtle::serializer::turtle::TurtleSerializer;
+        init();
+        let snikmeta = snikmeta()?;
+        let mut expected: Vec<StringTriple> = snikmeta.triples_all().collect();
+        expected.sort_unstable();
+        let mut nt = Vec::<u8>::new();
+        snikmeta.write_nt(&mut nt)?;
+        let nt = String::from_utf8(nt)?;
+        let nq: String =
+            nt.lines().map(|l| l.trim_end_matches('.').to_owned() + "<http://example.org/g> .\n").collect();
+        let trig = format!("<http://example.org/g> {{\n{nt}}}\n");
+        let mut ttl = Vec::<u8>::new();
+        TurtleSerializer::new(&mut ttl).serialize_graph(&snikmeta)?;
+
+        let dir = tempfile::tempdir()?;
+        for (name, format, content) in [
+            ("snikmeta.nq", RdfFormat::NQuads, nq.as_bytes()),
+            ("snikmeta.trig", RdfFormat::TriG, trig.as_bytes()),
+            ("snikmeta.ttl", RdfFormat::Turtle, ttl.as_slice()),
+        ] {
+            let path = dir.path().join(name);
+            fs_err::write(&path, content)?;
+            assert_eq!(RdfFormat::from_path(&path), Some(format));
+            let mut actual: Vec<StringTriple> = Hdt::read_rdf(&path, format)?.triples_all().collect();
+            actual.sort_unstable();
+            assert_eq!(actual, expected, "{format:?}");
+        }
+
+        let path = dir.path().join("example.rdf");
+        fs_err::write(
+            &path,
+            r#"<?xml version="1.0"?>
+<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#" xmlns:rdfs="http://www.w3.org/2000/01/rdf-schema#">
+  <rdf:Description rdf:about="http://example.org/s">
+    <rdfs:label xml:lang="en">label</rdfs:label>
+    <rdfs:seeAlso rdf:resource="http://example.org/o"/>
+  </rdf:Description>
+</rdf:RDF>"#,
+        )?;
+        let hdt = Hdt::read_rdf(&path, RdfFormat::from_path(&path).unwrap())?;
+        let s = "http://example.org/s";
+        let mut actual: Vec<StringTriple> = hdt.triples_all().collect();
+        actual.sort_unstable();
+        assert_eq!(
+            actual,
+            vec![
+                [s.into(), "http://www.w3.org/2000/01/rdf-schema#label".into(), "\"label\"@en".into()],
+                [s.into(), "http://www.w3.org/2000/01/rdf-schema#seeAlso".into(), "http://example.org/o".into()],
+            ]
+        );
+        assert!(Hdt::read_rdf(&path, RdfFormat::Turtle).is_err(), "RDF/XML is not valid Turtle");
+        Ok(())
+    }
 }
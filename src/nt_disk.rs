@@ -0,0 +1,464 @@
+// //! *This module is available only if HDT is built with the experimental `"nt"` feature.*
+//! External sorting variant of the N-Triples conversion.
+//! Each term occurrence is identified by `3 * triple index + position`, where the position is 0 for subjects, 1 for predicates and 2 for objects.
+//! 1. The terms of the parsed triples are collected together with their occurrences and written to sorted runs.
+//! 2. Merging the term runs yields the terms in byte order, which is the order of the dictionary sections,
+//!    so each term is appended to its sections and the resulting IDs are written to runs sorted by occurrence.
+//! 3. Merging the ID runs yields the three IDs of each triple in turn, which are written to runs sorted in the requested order.
+//! 4. Merging the triple runs yields the deduplicated triples in order, from which the triples section is built.
+use super::BLOCK_SIZE;
+use super::nt::{RdfFormat, file_iri, term_strings};
+use crate::containers::vbyte::{encode_vbyte, read_vbyte};
+use crate::dict_sect_pfc::PfcBuilder;
+use crate::header::Header;
+use crate::triples::{Id, Order, TriplesBitmap};
+use crate::{FourSectDict, Hdt};
+use bytesize::ByteSize;
+use log::{debug, warn};
+use rayon::prelude::*;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap, HashMap};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, Write};
+use std::path::Path;
+
+pub type Result<T> = io::Result<T>;
+
+/// Estimated heap usage of a distinct term in a term run besides its bytes, i.e. the map entry and the occurrence vector.
+const TERM_OVERHEAD: usize = 64;
+/// Maximum number of runs that are merged at once, more runs are first merged in several passes.
+/// Limits the number of open files and the memory used by their buffers. Small in tests to exercise the passes.
+const MAX_FAN_IN: usize = if cfg!(test) { 4 } else { 64 };
+
+impl Hdt {
+    /// Converts N-Triples to HDT like [`Hdt::read_nt_with_order`] but keeps the intermediate data within the given memory budget in bytes.
+    /// Terms and triple IDs are written to sorted runs in temporary files, which are then merged,
+    /// with intermediate passes if there are more than [`MAX_FAN_IN`] runs.
+    /// Only the resulting HDT has to fit into memory, whose dictionary and triples are compressed.
+    /// The result is identical to the one of [`Hdt::read_nt_with_order`], the conversion is slower due to the additional disk I/O.
+    /// Temporary files are created in [`std::env::temp_dir`] and deleted automatically.
+    /// # Example
+    /// ```no_run
+    /// let path = std::path::Path::new("example.nt");
+    /// let hdt = hdt::Hdt::read_nt_with_budget(path, hdt::triples::Order::SPO, 1 << 30).unwrap();
+    /// ```
+    pub fn read_nt_with_budget(f: &Path, order: Order, memory_budget: usize) -> Result<Self> {
+        if order == Order::Unknown {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "unknown triples order"));
+        }
+        let term_runs = write_term_runs(f, memory_budget)?;
+        debug!("wrote {} term runs", term_runs.len());
+        let (dict, id_runs) = build_dict(term_runs, memory_budget)?;
+        if dict.predicates.num_strings() == 0 {
+            warn!("no triples found in provided RDF");
+        }
+        let triple_runs = write_triple_runs(id_runs, dict.shared.num_strings(), order, memory_budget)?;
+
+        let mut error = None;
+        let mut last = None;
+        let mut num_triples = 0;
+        let coords = Merge::<3>::new(triple_runs)?
+            .map_while(|r| r.map_err(|e| error = Some(e)).ok())
+            .map(|c| c.map(|x| x as Id))
+            .filter(|c| last.replace(*c) != Some(*c))
+            .inspect(|_| num_triples += 1);
+        let triples = TriplesBitmap::from_coords(order, coords);
+        if let Some(e) = error {
+            return Err(e);
+        }
+
+        let header = Header { format: "ntriples".to_owned(), length: 0, body: BTreeSet::new() };
+        let mut hdt = Hdt::from_sections(header, dict.into(), triples);
+        hdt.fill_header(&file_iri(f)?, Some(f), BLOCK_SIZE, num_triples)?;
+
+        debug!("HDT size in memory {}, details:", ByteSize(hdt.size_in_bytes() as u64));
+        debug!("{hdt:#?}");
+        Ok(hdt)
+    }
+}
+
+/// Rewinds the written temporary file so that it can be read.
+fn into_file(writer: BufWriter<File>) -> Result<File> {
+    let mut file = writer.into_inner().map_err(io::IntoInnerError::into_error)?;
+    file.rewind()?;
+    Ok(file)
+}
+
+/// Parses the N-Triples file and writes each term with its occurrences to sorted runs.
+fn write_term_runs(path: &Path, memory_budget: usize) -> Result<Vec<File>> {
+    let mut runs = Vec::new();
+    let mut terms = HashMap::<String, Vec<u64>>::new();
+    let mut size = 0;
+    for (i, strings) in term_strings(path, RdfFormat::NTriples)?.enumerate() {
+        for (pos, s) in strings?.into_iter().enumerate() {
+            terms
+                .entry(s)
+                .or_insert_with_key(|s| {
+                    size += s.len() + TERM_OVERHEAD;
+                    Vec::new()
+                })
+                .push(3 * i as u64 + pos as u64);
+            size += size_of::<u64>();
+        }
+        if size >= memory_budget {
+            runs.push(write_term_run(&mut terms)?);
+            size = 0;
+        }
+    }
+    if !terms.is_empty() {
+        runs.push(write_term_run(&mut terms)?);
+    }
+    Ok(runs)
+}
+
+/// Writes the terms in byte order, each followed by the positions it occurs in as bit flags and its delta encoded occurrences.
+fn write_term_run(terms: &mut HashMap<String, Vec<u64>>) -> Result<File> {
+    let mut sorted: Vec<(String, Vec<u64>)> = terms.drain().collect();
+    sorted.par_sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    let mut writer = BufWriter::new(tempfile::tempfile()?);
+    for (term, occurrences) in sorted {
+        let flags = occurrences.iter().fold(0u8, |flags, occ| flags | (1 << (occ % 3)));
+        write_term(&mut writer, term.as_bytes(), flags, occurrences.len())?;
+        let mut last = 0;
+        for occ in occurrences {
+            writer.write_all(&encode_vbyte((occ - last) as usize))?;
+            last = occ;
+        }
+    }
+    into_file(writer)
+}
+
+/// Writes a term with its position flags and number of occurrences, which have to be written afterwards.
+fn write_term(writer: &mut impl Write, term: &[u8], flags: u8, num_occurrences: usize) -> Result<()> {
+    writer.write_all(&encode_vbyte(term.len()))?;
+    writer.write_all(term)?;
+    writer.write_all(&[flags])?;
+    writer.write_all(&encode_vbyte(num_occurrences))
+}
+
+/// Merges the runs in groups of at most [`MAX_FAN_IN`] with the given function until no more than [`MAX_FAN_IN`] are left.
+fn cascade(mut runs: Vec<File>, merge: impl Fn(Vec<File>) -> Result<File>) -> Result<Vec<File>> {
+    while runs.len() > MAX_FAN_IN {
+        debug!("merging {} runs in groups of {MAX_FAN_IN}", runs.len());
+        let mut merged = Vec::with_capacity(runs.len().div_ceil(MAX_FAN_IN));
+        let mut iter = runs.into_iter();
+        loop {
+            let group: Vec<File> = iter.by_ref().take(MAX_FAN_IN).collect();
+            if group.is_empty() {
+                break;
+            }
+            merged.push(merge(group)?);
+        }
+        runs = merged;
+    }
+    Ok(runs)
+}
+
+/// Reads a run written by [`write_term_run`].
+struct TermRun {
+    reader: BufReader<File>,
+    /// number of occurrences of the current term that have not been read yet
+    remaining: usize,
+    last: u64,
+}
+
+impl TermRun {
+    /// Reads the next term and its position flags, the occurrences of the previous term must have been read before.
+    fn next_term(&mut self) -> Result<Option<(Vec<u8>, u8)>> {
+        if self.reader.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        let (len, _) = read_vbyte(&mut self.reader)?;
+        let mut term = vec![0u8; len];
+        self.reader.read_exact(&mut term)?;
+        let mut flags = [0u8];
+        self.reader.read_exact(&mut flags)?;
+        (self.remaining, _) = read_vbyte(&mut self.reader)?;
+        self.last = 0;
+        Ok(Some((term, flags[0])))
+    }
+
+    fn next_occurrence(&mut self) -> Result<Option<u64>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        self.last += read_vbyte(&mut self.reader)?.0 as u64;
+        Ok(Some(self.last))
+    }
+}
+
+/// Iterates over the terms of several term runs in byte order.
+/// Terms that occur in several runs are combined, their occurrences are read from the runs in turn.
+/// Each run contains the occurrences of a contiguous part of the input and the runs are in input order,
+/// so the combined occurrences stay sorted.
+struct TermMerge {
+    runs: Vec<TermRun>,
+    heap: BinaryHeap<Reverse<(Vec<u8>, usize, u8)>>,
+    /// runs that contain the current term in ascending order
+    current: Vec<usize>,
+    /// index into `current` of the run whose occurrences are read next
+    pos: usize,
+}
+
+impl TermMerge {
+    /// Merges the runs, with intermediate passes if there are more than [`MAX_FAN_IN`].
+    fn new(term_runs: Vec<File>) -> Result<Self> {
+        Self::open(cascade(term_runs, |group| Self::open(group)?.write_run())?)
+    }
+
+    fn open(term_runs: Vec<File>) -> Result<Self> {
+        let mut merge = TermMerge {
+            runs: term_runs
+                .into_iter()
+                .map(|f| TermRun { reader: BufReader::new(f), remaining: 0, last: 0 })
+                .collect(),
+            heap: BinaryHeap::new(),
+            current: Vec::new(),
+            pos: 0,
+        };
+        for i in 0..merge.runs.len() {
+            merge.advance(i)?;
+        }
+        Ok(merge)
+    }
+
+    /// Skips the unread occurrences of the current term in the given run and reads its next term, if any.
+    fn advance(&mut self, i: usize) -> Result<()> {
+        let run = &mut self.runs[i];
+        while run.next_occurrence()?.is_some() {}
+        if let Some((term, flags)) = run.next_term()? {
+            self.heap.push(Reverse((term, i, flags)));
+        }
+        Ok(())
+    }
+
+    /// Returns the next term with its position flags combined over all runs.
+    fn next_term(&mut self) -> Result<Option<(Vec<u8>, u8)>> {
+        for i in std::mem::take(&mut self.current) {
+            self.advance(i)?;
+        }
+        self.pos = 0;
+        let Some(Reverse((term, i, mut flags))) = self.heap.pop() else {
+            return Ok(None);
+        };
+        // the same term may occur in several runs
+        self.current.push(i);
+        while self.heap.peek().is_some_and(|Reverse((t, _, _))| *t == term) {
+            let Reverse((_, j, f)) = self.heap.pop().unwrap();
+            self.current.push(j);
+            flags |= f;
+        }
+        Ok(Some((term, flags)))
+    }
+
+    /// Number of occurrences of the current term that have not been read yet.
+    fn remaining(&self) -> usize {
+        self.current[self.pos..].iter().map(|&j| self.runs[j].remaining).sum()
+    }
+
+    /// Returns the next occurrence of the current term in ascending order.
+    fn next_occurrence(&mut self) -> Result<Option<u64>> {
+        while let Some(&j) = self.current.get(self.pos) {
+            if let Some(occ) = self.runs[j].next_occurrence()? {
+                return Ok(Some(occ));
+            }
+            self.pos += 1;
+        }
+        Ok(None)
+    }
+
+    /// Writes the merged terms to a new run in the format of [`write_term_run`].
+    fn write_run(mut self) -> Result<File> {
+        let mut writer = BufWriter::new(tempfile::tempfile()?);
+        while let Some((term, flags)) = self.next_term()? {
+            write_term(&mut writer, &term, flags, self.remaining())?;
+            let mut last = 0;
+            while let Some(occ) = self.next_occurrence()? {
+                writer.write_all(&encode_vbyte((occ - last) as usize))?;
+                last = occ;
+            }
+        }
+        into_file(writer)
+    }
+}
+
+/// Merges the term runs into the dictionary and writes runs of `[occurrence, ID]` records.
+/// Subject and object IDs after the shared section are stored relative to it with the lowest bit set,
+/// because the size of the shared section is only known after the merge.
+fn build_dict(term_runs: Vec<File>, memory_budget: usize) -> Result<(FourSectDict, Vec<File>)> {
+    let mut terms = TermMerge::new(term_runs)?;
+    let [mut shared, mut subjects, mut predicates, mut objects] = [(); 4].map(|()| PfcBuilder::new(BLOCK_SIZE));
+    let mut ids = RunWriter::<2>::new(memory_budget);
+    while let Some((term, flags)) = terms.next_term()? {
+        let (s, p, o) = (flags & 1 != 0, flags & 2 != 0, flags & 4 != 0);
+        let so_id = match (s, o) {
+            (true, true) => (shared.push(&term) as u64) << 1,
+            (true, false) => ((subjects.push(&term) as u64) << 1) | 1,
+            (false, true) => ((objects.push(&term) as u64) << 1) | 1,
+            (false, false) => 0,
+        };
+        let p_id = if p { (predicates.push(&term) as u64) << 1 } else { 0 };
+        while let Some(occ) = terms.next_occurrence()? {
+            ids.push([occ, if occ % 3 == 1 { p_id } else { so_id }])?;
+        }
+    }
+    let dict = FourSectDict {
+        shared: shared.finish(),
+        subjects: subjects.finish(),
+        predicates: predicates.finish(),
+        objects: objects.finish(),
+    };
+    Ok((dict, ids.finish()?))
+}
+
+/// Joins the three IDs of each triple and writes runs of triples sorted by their coordinates in the given order.
+fn write_triple_runs(
+    id_runs: Vec<File>, num_shared: usize, order: Order, memory_budget: usize,
+) -> Result<Vec<File>> {
+    let resolve = |id: u64| (id >> 1) as Id + if id & 1 == 1 { num_shared } else { 0 };
+    let mut triples = RunWriter::<3>::new(memory_budget);
+    let mut merge = Merge::<2>::new(id_runs)?;
+    while let Some(s) = merge.next() {
+        let (s, p, o) = (s?, merge.next().transpose()?, merge.next().transpose()?);
+        let (Some(p), Some(o)) = (p, o) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "incomplete triple in ID runs"));
+        };
+        let triple = [s[1], p[1], o[1]].map(resolve);
+        triples.push(order.to_coord(triple).map(|id| id as u64))?;
+    }
+    triples.finish()
+}
+
+/// Collects fixed size records and writes them to a new sorted and deduplicated run whenever the memory budget is used up.
+struct RunWriter<const N: usize> {
+    records: Vec<[u64; N]>,
+    capacity: usize,
+    runs: Vec<File>,
+}
+
+impl<const N: usize> RunWriter<N> {
+    fn new(memory_budget: usize) -> Self {
+        RunWriter {
+            records: Vec::new(),
+            capacity: (memory_budget / size_of::<[u64; N]>()).max(1),
+            runs: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, record: [u64; N]) -> Result<()> {
+        self.records.push(record);
+        if self.records.len() >= self.capacity {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.records.par_sort_unstable();
+        self.records.dedup();
+        self.runs.push(write_records(self.records.drain(..).map(Ok))?);
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Vec<File>> {
+        if !self.records.is_empty() {
+            self.flush()?;
+        }
+        Ok(self.runs)
+    }
+}
+
+/// Writes the records to a new run in the format of [`RunWriter`].
+fn write_records<const N: usize>(records: impl IntoIterator<Item = Result<[u64; N]>>) -> Result<File> {
+    let mut writer = BufWriter::new(tempfile::tempfile()?);
+    for record in records {
+        for x in record? {
+            writer.write_all(&x.to_le_bytes())?;
+        }
+    }
+    into_file(writer)
+}
+
+/// Iterates over the records of several runs written by [`RunWriter`] in sorted order.
+struct Merge<const N: usize> {
+    readers: Vec<BufReader<File>>,
+    heap: BinaryHeap<Reverse<([u64; N], usize)>>,
+}
+
+impl<const N: usize> Merge<N> {
+    /// Merges the runs, with intermediate passes if there are more than [`MAX_FAN_IN`].
+    fn new(runs: Vec<File>) -> Result<Self> {
+        Self::open(cascade(runs, |group| write_records(Self::open(group)?))?)
+    }
+
+    fn open(runs: Vec<File>) -> Result<Self> {
+        let mut merge = Merge { readers: runs.into_iter().map(BufReader::new).collect(), heap: BinaryHeap::new() };
+        for i in 0..merge.readers.len() {
+            merge.advance(i)?;
+        }
+        Ok(merge)
+    }
+
+    /// Reads the next record of the given run, if any.
+    fn advance(&mut self, i: usize) -> Result<()> {
+        let reader = &mut self.readers[i];
+        if reader.fill_buf()?.is_empty() {
+            return Ok(());
+        }
+        let mut record = [0u64; N];
+        let mut buf = [0u8; size_of::<u64>()];
+        for x in &mut record {
+            reader.read_exact(&mut buf)?;
+            *x = u64::from_le_bytes(buf);
+        }
+        self.heap.push(Reverse((record, i)));
+        Ok(())
+    }
+}
+
+impl<const N: usize> Iterator for Merge<N> {
+    type Item = Result<[u64; N]>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((record, i)) = self.heap.pop()?;
+        Some(self.advance(i).map(|()| record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hdt::tests::snikmeta;
+    use crate::tests::init;
+    use color_eyre::Result;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn read_nt_with_budget() -> Result<()> {
+        init();
+        // separate file because the read_nt test may create snikmeta.nt concurrently
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("snikmeta.nt");
+        snikmeta()?.write_nt(&mut BufWriter::new(File::create(&path)?))?;
+        for order in [Order::SPO, Order::POS] {
+            let mut expected = Vec::new();
+            Hdt::read_nt_with_order(&path, order)?.write(&mut expected)?;
+            // tiny budgets create many runs in each phase
+            for budget in [1000, 1 << 30] {
+                let mut actual = Vec::new();
+                Hdt::read_nt_with_budget(&path, order, budget)?.write(&mut actual)?;
+                assert!(
+                    expected == actual,
+                    "{order:?} output with budget {budget} differs from in-memory conversion"
+                );
+            }
+        }
+        let empty = Path::new("tests/resources/empty.nt");
+        let (mut expected, mut actual) = (Vec::new(), Vec::new());
+        Hdt::read_nt(empty)?.write(&mut expected)?;
+        Hdt::read_nt_with_budget(empty, Order::SPO, 1000)?.write(&mut actual)?;
+        assert_eq!(expected, actual);
+        Ok(())
+    }
+}
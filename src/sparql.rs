@@ -1,9 +1,23 @@
-use crate::Hdt;
-use spareval::{InternalQuad, QueryEvaluationError, QueryEvaluator, QueryableDataset};
-use spargebra::SparqlParser;
-use spargebra::term::{BlankNode, NamedNode, Term};
-use std::io::{Error, ErrorKind};
+use crate::dictionary::pref_to_lit;
+use crate::escape::percent_decode;
+use crate::four_sect_dict::ExtractError;
+use crate::mult_sect_dict::{LANG_STRING, NO_DATATYPE};
+use crate::text_index::label;
+use crate::triples::{Id, TripleId};
+use crate::{Dict, DictSectPFC, Dictionary, Hdt, IdKind};
+use sparesults::{QueryResultsFormat, QueryResultsSerializer, QuerySolution};
+use spareval::{InternalQuad, QueryEvaluationError, QueryEvaluator, QueryResults, QueryableDataset};
+use spargebra::algebra::{Expression, Function, GraphPattern};
+use spargebra::term::{
+    BlankNode, GroundTerm, NamedNode, NamedNodePattern, Term, TermPattern, Triple, TriplePattern, Variable,
+};
+use spargebra::{Query, SparqlParser};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::io::{Error, ErrorKind, Write};
+use std::iter;
 use std::str::FromStr;
+use std::time::Instant;
 
 /// Create the correct term for a given resource string.
 /// Slow, use the appropriate method if you know which type (Literal, URI, or blank node) the string has.
@@ -42,44 +56,620 @@ fn term_to_hdt_bgp_str(term: Term) -> String {
     }
 }
 
+/// Internal term of the SPARQL evaluation, which refers to a dictionary term by ID, so that no string is extracted before projection.
+/// As IDs depend on the position, a term is identified by the ID of the first section that contains it,
+/// so that equal terms are equal regardless of the position they are bound at.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DictTerm {
+    /// subject ID, which is also the object ID for shared terms
+    Subject(Id),
+    /// object ID of a term that is not a subject
+    Object(Id),
+    /// predicate ID of a term that is neither a subject nor an object
+    Predicate(Id),
+    /// term that is not in the dictionary, such as a computed value
+    Other(Term),
+}
+
+fn extract_error(e: ExtractError) -> Error {
+    Error::new(ErrorKind::InvalidData, e)
+}
+
+/// Dictionary term with the given string representation, if any.
+fn lookup(hdt: &Hdt, s: &str) -> Option<DictTerm> {
+    let id = hdt.dict.string_to_id(s, IdKind::Subject);
+    if id != 0 {
+        return Some(DictTerm::Subject(id));
+    }
+    let id = hdt.dict.string_to_id(s, IdKind::Object);
+    if id != 0 {
+        return Some(DictTerm::Object(id));
+    }
+    let id = hdt.dict.string_to_id(s, IdKind::Predicate);
+    (id != 0).then_some(DictTerm::Predicate(id))
+}
+
+/// Internal term of an object ID.
+fn object_term(hdt: &Hdt, id: Id) -> DictTerm {
+    if id <= hdt.dict.num_shared() { DictTerm::Subject(id) } else { DictTerm::Object(id) }
+}
+
+/// Internal term of a predicate ID, which needs a lookup of its string as it may also be a subject or object.
+fn predicate_term(hdt: &Hdt, id: Id) -> Result<DictTerm, Error> {
+    let s = hdt.dict.id_to_string(id, IdKind::Predicate).map_err(extract_error)?;
+    Ok(lookup(hdt, &s).unwrap_or(DictTerm::Predicate(id)))
+}
+
+/// ID of an internal term at the given position, 0 if it does not occur there.
+fn position_id(hdt: &Hdt, term: &DictTerm, kind: IdKind) -> Result<Id, Error> {
+    Ok(match (term, kind) {
+        (DictTerm::Subject(id), IdKind::Subject)
+        | (DictTerm::Object(id), IdKind::Object)
+        | (DictTerm::Predicate(id), IdKind::Predicate) => *id,
+        (DictTerm::Subject(id), IdKind::Object) => {
+            if *id <= hdt.dict.num_shared() {
+                *id
+            } else {
+                0
+            }
+        }
+        (DictTerm::Subject(id), IdKind::Predicate) => {
+            hdt.dict.string_to_id(&hdt.dict.id_to_string(*id, IdKind::Subject).map_err(extract_error)?, kind)
+        }
+        (DictTerm::Object(id), IdKind::Predicate) => {
+            hdt.dict.string_to_id(&hdt.dict.id_to_string(*id, IdKind::Object).map_err(extract_error)?, kind)
+        }
+        // the first section containing a term is used, so it cannot be in an earlier one
+        (DictTerm::Object(_) | DictTerm::Predicate(_), IdKind::Subject)
+        | (DictTerm::Predicate(_), IdKind::Object)
+        | (DictTerm::Other(_), _) => 0,
+    })
+}
+
+type Quads<'a> = Box<dyn Iterator<Item = Result<InternalQuad<DictTerm>, Error>> + 'a>;
+
+/// Lazily translates the matching triples to internal terms.
+fn quads<'a>(hdt: &'a Hdt, terms: [Option<&DictTerm>; 3], graph_name: Option<Option<&DictTerm>>) -> Quads<'a> {
+    if let Some(Some(graph_name)) = graph_name {
+        let e = Error::new(ErrorKind::InvalidData, format!("HDT does not support named graph: {graph_name:?}"));
+        return Box::new(iter::once(Err(e)));
+    }
+    let mut pattern: TripleId = [0; 3];
+    for (i, term) in terms.iter().enumerate() {
+        if let Some(term) = term {
+            match position_id(hdt, term, IdKind::KINDS[i]) {
+                // the term does not occur at this position
+                Ok(0) => return Box::new(iter::empty()),
+                Ok(id) => pattern[i] = id,
+                Err(e) => return Box::new(iter::once(Err(e))),
+            }
+        }
+    }
+    // there are only few distinct predicates, whose terms are cached for the duration of the scan
+    let bound_predicate = terms[1].cloned();
+    let mut predicates: HashMap<Id, DictTerm> = HashMap::new();
+    Box::new(hdt.triple_ids_with_id_pattern(pattern).map(move |[s, p, o]| {
+        let predicate = match &bound_predicate {
+            Some(term) => term.clone(),
+            None => match predicates.entry(p) {
+                Entry::Occupied(e) => e.get().clone(),
+                Entry::Vacant(e) => e.insert(predicate_term(hdt, p)?).clone(),
+            },
+        };
+        Ok(InternalQuad {
+            subject: DictTerm::Subject(s),
+            predicate,
+            object: object_term(hdt, o),
+            graph_name: None,
+        })
+    }))
+}
+
 impl<'a> QueryableDataset<'a> for &'a Hdt {
-    type InternalTerm = String;
+    type InternalTerm = DictTerm;
     type Error = Error;
 
     fn internal_quads_for_pattern(
-        &self, subject: Option<&String>, predicate: Option<&String>, object: Option<&String>,
-        graph_name: Option<Option<&String>>,
+        &self, subject: Option<&DictTerm>, predicate: Option<&DictTerm>, object: Option<&DictTerm>,
+        graph_name: Option<Option<&DictTerm>>,
     ) -> impl Iterator<Item = Result<InternalQuad<Self::InternalTerm>, Error>> + use<'a> {
-        if let Some(Some(graph_name)) = graph_name {
-            return vec![Err(Error::new(
-                ErrorKind::InvalidData,
-                format!("HDT does not support named graph: {graph_name:?}"),
-            ))]
-            .into_iter();
-        }
-        let [ps, pp, po] = [subject, predicate, object].map(|x| x.map(String::as_str));
-        // Query HDT for BGP by string values.
-        let v: Vec<_> = self
-            .triples_with_pattern(ps, pp, po)
-            .map(|at| at.map(|a| a.to_string()))
-            .map(|[subject, predicate, object]| Ok(InternalQuad { subject, predicate, object, graph_name: None }))
-            .collect();
-        v.into_iter()
+        quads(*self, [subject, predicate, object], graph_name)
     }
 
-    fn internalize_term(&self, term: Term) -> Result<String, Error> {
-        Ok(term_to_hdt_bgp_str(term))
+    fn internalize_term(&self, term: Term) -> Result<DictTerm, Error> {
+        Ok(lookup(self, &term_to_hdt_bgp_str(term.clone())).unwrap_or(DictTerm::Other(term)))
     }
 
-    fn externalize_term(&self, term: String) -> Result<Term, Error> {
-        hdt_bgp_str_to_term(&term)
+    fn externalize_term(&self, term: DictTerm) -> Result<Term, Error> {
+        let (id, kind) = match term {
+            DictTerm::Subject(id) => (id, IdKind::Subject),
+            DictTerm::Object(id) => (id, IdKind::Object),
+            DictTerm::Predicate(id) => (id, IdKind::Predicate),
+            DictTerm::Other(term) => return Ok(term),
+        };
+        hdt_bgp_str_to_term(&self.dict.id_to_string(id, kind).map_err(extract_error)?)
     }
 }
 
+fn timed_out() -> Error {
+    Error::new(ErrorKind::TimedOut, "query timed out")
+}
+
+/// HDT as a dataset whose quad iterators fail with [`ErrorKind::TimedOut`] once the deadline has passed,
+/// so that the deadline also stops the evaluation of operations such as ORDER BY, DISTINCT and aggregates
+/// that consume all solutions before returning the first one, see [`query_with_deadline`].
+#[derive(Clone, Copy)]
+struct WithDeadline<'a> {
+    hdt: &'a Hdt,
+    deadline: Instant,
+}
+
+impl<'a> QueryableDataset<'a> for WithDeadline<'a> {
+    type InternalTerm = DictTerm;
+    type Error = Error;
+
+    fn internal_quads_for_pattern(
+        &self, subject: Option<&DictTerm>, predicate: Option<&DictTerm>, object: Option<&DictTerm>,
+        graph_name: Option<Option<&DictTerm>>,
+    ) -> impl Iterator<Item = Result<InternalQuad<Self::InternalTerm>, Error>> + use<'a> {
+        let deadline = self.deadline;
+        let mut quads = Some(quads(self.hdt, [subject, predicate, object], graph_name));
+        iter::from_fn(move || {
+            if Instant::now() >= deadline {
+                // fail once, then end
+                return quads.take().map(|_| Err(timed_out()));
+            }
+            quads.as_mut()?.next()
+        })
+    }
+
+    fn internalize_term(&self, term: Term) -> Result<DictTerm, Error> {
+        self.hdt.internalize_term(term)
+    }
+
+    fn externalize_term(&self, term: DictTerm) -> Result<Term, Error> {
+        self.hdt.externalize_term(term)
+    }
+}
+
+/// Maximum number of terms that a filter pushed down into a dictionary scan may match,
+/// above which joining them would be more expensive than evaluating the filter on each solution.
+const MAX_PUSHDOWN_TERMS: usize = 10_000;
+
+/// Number of terms that a dictionary scan decodes in about the time it takes to extract the string of a single
+/// solution by ID, which decodes the block of the term up to its position.
+const SCAN_TERMS_PER_SOLUTION: usize = 16;
+
+/// Test of the lexical form of a term, i.e. the label of a literal or an IRI.
+type LexicalFilter = Box<dyn Fn(&str) -> bool>;
+
+/// Lexical form of a string stored in a dictionary section, None for blank nodes.
+fn lexical_form(s: &[u8]) -> Option<&str> {
+    if s.starts_with(b"_:") {
+        return None;
+    }
+    label(s).or_else(|| std::str::from_utf8(s).ok())
+}
+
+/// Splits a conjunction of expressions into its operands.
+fn conjuncts<'e>(expr: &'e Expression, operands: &mut Vec<&'e Expression>) {
+    if let Expression::And(a, b) = expr {
+        conjuncts(a, operands);
+        conjuncts(b, operands);
+    } else {
+        operands.push(expr);
+    }
+}
+
+/// The variable and a test that is true for the lexical forms of all terms that may satisfy the expression,
+/// if it is a call of REGEX, STRSTARTS or CONTAINS on a variable or its string value with constant arguments.
+/// Regular expressions are compiled with the same regex crate and flags that spareval uses.
+fn lexical_filter(expr: &Expression) -> Option<(&Variable, LexicalFilter)> {
+    let Expression::FunctionCall(function, args) = expr else { return None };
+    let variable = match args.first()? {
+        Expression::Variable(v) => v,
+        Expression::FunctionCall(Function::Str, inner) => match inner.as_slice() {
+            [Expression::Variable(v)] => v,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let literal = |i: usize| match args.get(i) {
+        Some(Expression::Literal(l)) => Some(l.value().to_owned()),
+        _ => None,
+    };
+    let filter: LexicalFilter = match (function, args.len()) {
+        (Function::StrStarts, 2) => {
+            let prefix = literal(1)?;
+            Box::new(move |s: &str| s.starts_with(&prefix))
+        }
+        (Function::Contains, 2) => {
+            let part = literal(1)?;
+            Box::new(move |s: &str| s.contains(&part))
+        }
+        (Function::Regex, 2 | 3) => {
+            let mut pattern = literal(1)?;
+            let mut flags = String::new();
+            for flag in literal(2).unwrap_or_default().chars() {
+                match flag {
+                    'i' | 's' | 'm' | 'x' => flags.push(flag),
+                    'q' => pattern = regex::escape(&pattern),
+                    _ => return None,
+                }
+            }
+            if !flags.is_empty() {
+                pattern = format!("(?{flags}){pattern}");
+            }
+            let regex = regex::Regex::new(&pattern).ok()?;
+            Box::new(move |s: &str| regex.is_match(s))
+        }
+        _ => return None,
+    };
+    Some((variable, filter))
+}
+
+/// Whether the variable occurs in the basic graph pattern and only in object position.
+fn only_object(patterns: &[TriplePattern], variable: &Variable) -> bool {
+    let is = |t: &TermPattern| matches!(t, TermPattern::Variable(v) if v == variable);
+    patterns.iter().any(|p| is(&p.object))
+        && !patterns
+            .iter()
+            .any(|p| is(&p.subject) || matches!(&p.predicate, NamedNodePattern::Variable(v) if v == variable))
+}
+
+/// Upper estimate of the number of solutions of the basic graph pattern for a variable that only occurs as object,
+/// which is the smallest estimated number of triples that match a triple pattern with the variable as object.
+/// Constants that don't occur at their position don't match any triple.
+fn estimate_candidates(hdt: &Hdt, patterns: &[TriplePattern], variable: &Variable) -> usize {
+    let position_id = |term: Option<Term>, kind: IdKind| match term {
+        None => Some(0),
+        Some(term) => Some(hdt.dict.string_to_id(&term_to_hdt_bgp_str(term), kind)).filter(|&id| id != 0),
+    };
+    let constant = |t: &TermPattern| match t {
+        TermPattern::NamedNode(n) => Some(Term::NamedNode(n.clone())),
+        TermPattern::Literal(l) => Some(Term::Literal(l.clone())),
+        _ => None,
+    };
+    patterns
+        .iter()
+        .filter(|p| matches!(&p.object, TermPattern::Variable(v) if v == variable))
+        .map(|p| {
+            let predicate = match &p.predicate {
+                NamedNodePattern::NamedNode(n) => Some(Term::NamedNode(n.clone())),
+                NamedNodePattern::Variable(_) => None,
+            };
+            match (position_id(constant(&p.subject), IdKind::Subject), position_id(predicate, IdKind::Predicate)) {
+                (Some(s), Some(p)) => hdt.estimate_count([s, p, 0]).estimate,
+                _ => 0,
+            }
+        })
+        .min()
+        .unwrap_or(usize::MAX)
+}
+
+/// Object terms whose lexical form passes the filter, None if there are more than [`MAX_PUSHDOWN_TERMS`].
+/// The shared and object sections are scanned block by block and the matching terms are converted from the scanned
+/// strings, only adding the datatypes that the PSFC and multiple section dictionaries don't store in the literal.
+fn scan_objects(hdt: &Hdt, filter: &LexicalFilter) -> Option<Vec<GroundTerm>> {
+    let mut sections: Vec<(&DictSectPFC, Option<&str>)> = Vec::new();
+    match &hdt.dict {
+        Dict::Four(d) | Dict::FourPsfc(d) => sections.extend([(&d.shared, None), (&d.objects, None)]),
+        Dict::Mult(d) => {
+            sections.push((&d.shared, None));
+            for (datatype, sect) in &d.objects {
+                let typed = datatype != NO_DATATYPE && datatype != LANG_STRING;
+                sections.push((sect, typed.then_some(datatype.as_str())));
+            }
+        }
+    }
+    let psfc = matches!(hdt.dict, Dict::FourPsfc(_));
+    let mut terms = Vec::new();
+    for (sect, datatype) in sections {
+        for (_, s) in sect.scan(|s| lexical_form(s).is_some_and(|l| filter(l))) {
+            if terms.len() == MAX_PUSHDOWN_TERMS {
+                return None;
+            }
+            let s = String::from_utf8(s).ok()?;
+            let s = match datatype {
+                Some(datatype) => format!("{s}^^{datatype}"),
+                None if psfc => pref_to_lit(&s).into_owned(),
+                None => s,
+            };
+            match hdt_bgp_str_to_term(&s).ok()? {
+                Term::NamedNode(n) => terms.push(GroundTerm::NamedNode(n)),
+                Term::Literal(l) => terms.push(GroundTerm::Literal(l)),
+                Term::BlankNode(_) => {}
+            }
+        }
+    }
+    Some(terms)
+}
+
+/// Pushes filters on the string value of a variable that only occurs as object of a basic graph pattern into a dictionary scan
+/// and joins the pattern with the terms that can pass the filter.
+/// The filter is kept, so it is only evaluated on the remaining solutions instead of extracting the string of every solution.
+fn push_down_filters(hdt: &Hdt, pattern: GraphPattern) -> GraphPattern {
+    let rec = |p: Box<GraphPattern>| Box::new(push_down_filters(hdt, *p));
+    match pattern {
+        GraphPattern::Filter { expr, inner } => {
+            let inner = rec(inner);
+            let mut values = Vec::new();
+            if let GraphPattern::Bgp { patterns } = inner.as_ref() {
+                let mut operands = Vec::new();
+                conjuncts(&expr, &mut operands);
+                for operand in operands {
+                    let Some((variable, filter)) = lexical_filter(operand) else { continue };
+                    // a scan only pays off if it is cheaper than extracting the string of each candidate solution
+                    if !only_object(patterns, variable)
+                        || estimate_candidates(hdt, patterns, variable).saturating_mul(SCAN_TERMS_PER_SOLUTION)
+                            < hdt.dict.num_terms(IdKind::Object)
+                    {
+                        continue;
+                    }
+                    if let Some(terms) = scan_objects(hdt, &filter) {
+                        let bindings = terms.into_iter().map(|t| vec![Some(t)]).collect();
+                        values.push(GraphPattern::Values { variables: vec![variable.clone()], bindings });
+                    }
+                }
+            }
+            let inner = values
+                .into_iter()
+                .fold(inner, |right, left| Box::new(GraphPattern::Join { left: Box::new(left), right }));
+            GraphPattern::Filter { expr, inner }
+        }
+        GraphPattern::Join { left, right } => GraphPattern::Join { left: rec(left), right: rec(right) },
+        GraphPattern::LeftJoin { left, right, expression } => {
+            GraphPattern::LeftJoin { left: rec(left), right: rec(right), expression }
+        }
+        GraphPattern::Union { left, right } => GraphPattern::Union { left: rec(left), right: rec(right) },
+        GraphPattern::Minus { left, right } => GraphPattern::Minus { left: rec(left), right: rec(right) },
+        GraphPattern::Graph { name, inner } => GraphPattern::Graph { name, inner: rec(inner) },
+        GraphPattern::Extend { inner, variable, expression } => {
+            GraphPattern::Extend { inner: rec(inner), variable, expression }
+        }
+        GraphPattern::OrderBy { inner, expression } => GraphPattern::OrderBy { inner: rec(inner), expression },
+        GraphPattern::Project { inner, variables } => GraphPattern::Project { inner: rec(inner), variables },
+        GraphPattern::Distinct { inner } => GraphPattern::Distinct { inner: rec(inner) },
+        GraphPattern::Reduced { inner } => GraphPattern::Reduced { inner: rec(inner) },
+        GraphPattern::Slice { inner, start, length } => GraphPattern::Slice { inner: rec(inner), start, length },
+        GraphPattern::Group { inner, variables, aggregates } => {
+            GraphPattern::Group { inner: rec(inner), variables, aggregates }
+        }
+        other => other,
+    }
+}
+
+/// Parses a SPARQL query and pushes filters on literal values into dictionary scans.
+fn parse(q: &str, hdt: &Hdt) -> Result<Query, QueryEvaluationError> {
+    let mut query = SparqlParser::new().parse_query(q)?;
+    let (Query::Select { pattern, .. }
+    | Query::Construct { pattern, .. }
+    | Query::Describe { pattern, .. }
+    | Query::Ask { pattern, .. }) = &mut query;
+    *pattern = push_down_filters(hdt, std::mem::replace(pattern, GraphPattern::Bgp { patterns: Vec::new() }));
+    Ok(query)
+}
+
+/// Evaluates a SPARQL query, pushing filters on literal values such as REGEX and STRSTARTS into dictionary scans.
 pub fn query<'a>(q: &str, hdt: &'a Hdt) -> Result<spareval::QueryResults<'a>, QueryEvaluationError> {
-    let query = SparqlParser::new().parse_query(q)?;
-    //.unwrap_or_else(|_| panic!("error processing SPARQL query:\n{q}"));
-    QueryEvaluator::new().prepare(&query).execute(hdt)
+    QueryEvaluator::new().prepare(&parse(q, hdt)?).execute(hdt)
+}
+
+/// Like [`query`] but the lazily evaluated results fail once the deadline has passed, even while an operation
+/// such as ORDER BY is still evaluating all solutions before the first one is returned.
+/// Pass the same deadline to [`write_results`] or [`ResultsPage::new`] to get an error of kind [`ErrorKind::TimedOut`].
+pub fn query_with_deadline<'a>(
+    q: &str, hdt: &'a Hdt, deadline: Instant,
+) -> Result<spareval::QueryResults<'a>, QueryEvaluationError> {
+    QueryEvaluator::new().prepare(&parse(q, hdt)?).execute(WithDeadline { hdt, deadline })
+}
+
+/// Serialization format of SPARQL query results, see [`write_results`] and [`ResultsPage::write`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResultsFormat {
+    /// SPARQL 1.1 Query Results JSON Format, the default for solutions and booleans
+    Json,
+    /// SPARQL Query Results XML Format
+    Xml,
+    /// SPARQL 1.1 Query Results CSV Format, only for solutions
+    Csv,
+    /// SPARQL 1.1 Query Results TSV Format, only for solutions
+    Tsv,
+    /// RDF Turtle, the default for the graphs of CONSTRUCT and DESCRIBE queries
+    Turtle,
+    /// N-Triples, only for graphs
+    NTriples,
+}
+
+impl ResultsFormat {
+    /// Negotiates the format for the kind of results from the value of an HTTP Accept header.
+    /// The first supported media type wins, quality values are not considered.
+    pub fn negotiate(accept: &str, results: &QueryResults<'_>) -> Self {
+        let json = [("application/sparql-results+json", Self::Json), ("application/json", Self::Json)];
+        let xml = [("application/sparql-results+xml", Self::Xml), ("application/xml", Self::Xml)];
+        let supported: Vec<(&str, Self)> = match results {
+            QueryResults::Solutions(_) => {
+                [&json[..], &xml, &[("text/csv", Self::Csv), ("text/tab-separated-values", Self::Tsv)]].concat()
+            }
+            QueryResults::Boolean(_) => [json, xml].concat(),
+            QueryResults::Graph(_) => {
+                vec![("text/turtle", Self::Turtle), ("application/n-triples", Self::NTriples)]
+            }
+        };
+        accept
+            .split(',')
+            .filter_map(|m| supported.iter().find(|(t, _)| m.trim().starts_with(t)).map(|(_, f)| *f))
+            .next()
+            .unwrap_or(supported[0].1)
+    }
+
+    /// Media type for the HTTP Content-Type header.
+    pub const fn media_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/sparql-results+json",
+            Self::Xml => "application/sparql-results+xml",
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Tsv => "text/tab-separated-values; charset=utf-8",
+            Self::Turtle => "text/turtle; charset=utf-8",
+            Self::NTriples => "application/n-triples; charset=utf-8",
+        }
+    }
+
+    /// The format of the SPARQL results serializer, None for graphs.
+    const fn results_format(self) -> Option<QueryResultsFormat> {
+        match self {
+            Self::Json => Some(QueryResultsFormat::Json),
+            Self::Xml => Some(QueryResultsFormat::Xml),
+            Self::Csv => Some(QueryResultsFormat::Csv),
+            Self::Tsv => Some(QueryResultsFormat::Tsv),
+            Self::Turtle | Self::NTriples => None,
+        }
+    }
+
+    fn unsupported(self) -> Error {
+        Error::new(ErrorKind::InvalidInput, format!("unsupported format {self:?} for these results"))
+    }
+}
+
+/// Query of a [SPARQL 1.1 Protocol](https://www.w3.org/TR/sparql11-protocol/) request, `None` if there is none.
+/// GET requests pass it as the `query` parameter of the URL query string, POST requests either directly as a body
+/// with the content type `application/sparql-query` or as a parameter of a URL-encoded body.
+pub fn protocol_query(post: bool, content_type: &str, url_query: &str, body: &str) -> Option<String> {
+    let param = |s: &str| {
+        s.split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(k, _)| *k == "query")
+            .map(|(_, v)| percent_decode(v))
+    };
+    if !post {
+        param(url_query)
+    } else if content_type.starts_with("application/sparql-query") {
+        Some(body.to_owned())
+    } else {
+        param(body)
+    }
+}
+
+/// Maps the results of the evaluation, failing with [`ErrorKind::TimedOut`] once the deadline has passed,
+/// which is also the cause of evaluation errors after the deadline with [`query_with_deadline`].
+fn within<T>(deadline: Option<Instant>) -> impl Fn(Result<T, QueryEvaluationError>) -> Result<T, Error> {
+    move |result| {
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            return Err(timed_out());
+        }
+        result.map_err(Error::other)
+    }
+}
+
+fn write_boolean(b: bool, format: ResultsFormat, write: &mut impl Write) -> Result<(), Error> {
+    let f = format
+        .results_format()
+        .filter(|f| matches!(f, QueryResultsFormat::Json | QueryResultsFormat::Xml))
+        .ok_or_else(|| format.unsupported())?;
+    QueryResultsSerializer::from_format(f).serialize_boolean_to_writer(write, b)?;
+    Ok(())
+}
+
+fn write_solutions(
+    variables: Vec<Variable>, solutions: impl Iterator<Item = Result<QuerySolution, Error>>,
+    format: ResultsFormat, write: &mut impl Write,
+) -> Result<(), Error> {
+    let f = format.results_format().ok_or_else(|| format.unsupported())?;
+    let mut serializer = QueryResultsSerializer::from_format(f).serialize_solutions_to_writer(write, variables)?;
+    for solution in solutions {
+        serializer.serialize(&solution?)?;
+    }
+    serializer.finish()?;
+    Ok(())
+}
+
+fn write_triples(
+    triples: impl Iterator<Item = Result<Triple, Error>>, format: ResultsFormat, write: &mut impl Write,
+) -> Result<(), Error> {
+    if format.results_format().is_some() {
+        return Err(format.unsupported());
+    }
+    // N-Triples is a subset of Turtle
+    for triple in triples {
+        writeln!(write, "{} .", triple?)?;
+    }
+    Ok(())
+}
+
+/// Serializes at most `limit` solutions or triples of the results in the given format while they are evaluated,
+/// failing with an error of kind [`ErrorKind::TimedOut`] once the deadline has passed.
+/// Use [`query_with_deadline`] so that the deadline also limits operations such as ORDER BY
+/// that need all solutions before returning the first one.
+pub fn write_results(
+    results: QueryResults<'_>, format: ResultsFormat, limit: usize, deadline: Option<Instant>,
+    write: &mut impl Write,
+) -> Result<(), Error> {
+    match results {
+        QueryResults::Boolean(b) => write_boolean(b, format, write),
+        QueryResults::Graph(triples) => write_triples(triples.take(limit).map(within(deadline)), format, write),
+        QueryResults::Solutions(solutions) => {
+            let variables = solutions.variables().to_vec();
+            write_solutions(variables, solutions.take(limit).map(within(deadline)), format, write)
+        }
+    }
+}
+
+/// Evaluated solutions or triples of a [`ResultsPage`].
+#[derive(Debug)]
+enum PageItems {
+    Boolean(bool),
+    Solutions(Vec<Variable>, Vec<QuerySolution>),
+    Graph(Vec<Triple>),
+}
+
+/// The first solutions or triples of query results up to a limit, which are evaluated before they are serialized,
+/// so that a server knows about errors, timeouts and truncation before it sends the status and headers of a response.
+#[derive(Debug)]
+pub struct ResultsPage {
+    items: PageItems,
+    truncated: bool,
+}
+
+impl ResultsPage {
+    /// Evaluates at most `limit` solutions or triples of the results and whether there are more,
+    /// failing with an error of kind [`ErrorKind::TimedOut`] once the deadline has passed.
+    pub fn new(results: QueryResults<'_>, limit: usize, deadline: Option<Instant>) -> Result<Self, Error> {
+        fn take<T>(
+            mut iter: impl Iterator<Item = Result<T, Error>>, limit: usize,
+        ) -> Result<(Vec<T>, bool), Error> {
+            let items = iter.by_ref().take(limit).collect::<Result<Vec<T>, Error>>()?;
+            Ok((items, iter.next().transpose()?.is_some()))
+        }
+        let (items, truncated) = match results {
+            QueryResults::Boolean(b) => (PageItems::Boolean(b), false),
+            QueryResults::Graph(triples) => {
+                let (triples, truncated) = take(triples.map(within(deadline)), limit)?;
+                (PageItems::Graph(triples), truncated)
+            }
+            QueryResults::Solutions(solutions) => {
+                let variables = solutions.variables().to_vec();
+                let (solutions, truncated) = take(solutions.map(within(deadline)), limit)?;
+                (PageItems::Solutions(variables, solutions), truncated)
+            }
+        };
+        Ok(ResultsPage { items, truncated })
+    }
+
+    /// Whether the results have more solutions or triples than the limit, which are left out.
+    pub const fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Serializes the evaluated solutions or triples in the given format.
+    pub fn write(self, format: ResultsFormat, write: &mut impl Write) -> Result<(), Error> {
+        match self.items {
+            PageItems::Boolean(b) => write_boolean(b, format, write),
+            PageItems::Graph(triples) => write_triples(triples.into_iter().map(Ok), format, write),
+            PageItems::Solutions(variables, solutions) => {
+                write_solutions(variables, solutions.into_iter().map(Ok), format, write)
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -137,6 +727,154 @@ mod tests {
         Ok(())
     }
 
+    /// Compares the results of joins across positions with the BGP evaluation on triple IDs.
+    #[test]
+    fn join() -> Result<()> {
+        use crate::bgp::{PatternTerm, TriplePattern};
+        init();
+        let hdt = crate::hdt::tests::snikmeta()?;
+        let [ty, label] =
+            ["http://www.w3.org/1999/02/22-rdf-syntax-ns#type", "http://www.w3.org/2000/01/rdf-schema#label"];
+        let bgps = [
+            // object bound as subject
+            vec![["?s", ty, "?c"], ["?c", label, "?l"]],
+            // predicate bound as subject
+            vec![["?s", "?p", "?o"], ["?p", ty, "?c"]],
+            vec![["?x", "?p", "?x"]],
+            vec![["?s", label, "\"top class\"@en"], ["?s", "?p", "?o"]],
+        ];
+        for bgp in bgps {
+            let patterns: Vec<TriplePattern> = bgp.iter().map(|p| p.map(PatternTerm::from)).collect();
+            let bindings = hdt.evaluate_bgp(&patterns);
+            let mut expected = bindings.strings(&hdt).collect::<std::result::Result<Vec<_>, _>>()?;
+            expected.sort();
+            let sparql_term = |t: &str| if t.starts_with(['?', '"']) { t.to_owned() } else { format!("<{t}>") };
+            let q = format!(
+                "SELECT * {{ {} }}",
+                bgp.iter().map(|p| p.map(sparql_term).join(" ")).collect::<Vec<_>>().join(" . ")
+            );
+            let spareval::QueryResults::Solutions(solutions) = query(&q, &hdt)? else {
+                panic!("SELECT query results expected but got something else")
+            };
+            let mut actual = Vec::new();
+            for solution in solutions {
+                let solution = solution?;
+                actual.push(
+                    bindings
+                        .variables
+                        .iter()
+                        .map(|v| term_to_hdt_bgp_str(solution.get(v.as_str()).unwrap().clone()))
+                        .collect::<Vec<_>>(),
+                );
+            }
+            actual.sort();
+            assert_eq!(actual, expected, "{q}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn filter_pushdown() -> Result<()> {
+        init();
+        let hdt = crate::hdt::tests::snikmeta()?;
+        let label = "<http://www.w3.org/2000/01/rdf-schema#label>";
+        for (filter, pushed) in [
+            ("REGEX(?l, 'class')", true),
+            ("REGEX(STR(?l), '^top', 'i')", true),
+            ("STRSTARTS(?l, 'Anwenden') && CONTAINS(STR(?l), 'Methode')", true),
+            ("CONTAINS(STR(?s), 'meta')", false),
+            ("LANG(?l) = 'en'", false),
+        ] {
+            let q = format!("SELECT ?s ?l {{ ?s {label} ?l FILTER({filter}) }}");
+            let parsed = SparqlParser::new().parse_query(&q)?;
+            let Query::Select { pattern, .. } = &parsed else { unreachable!() };
+            let rewritten = push_down_filters(&hdt, pattern.clone());
+            assert_eq!(&rewritten != pattern, pushed, "{filter}");
+            let solutions = |results: spareval::QueryResults<'_>| -> Result<Vec<String>> {
+                let spareval::QueryResults::Solutions(solutions) = results else { panic!("solutions expected") };
+                let mut strings = Vec::new();
+                for solution in solutions {
+                    let solution = solution?;
+                    strings.push(format!("{} {}", solution.get("s").unwrap(), solution.get("l").unwrap()));
+                }
+                strings.sort();
+                Ok(strings)
+            };
+            // the original query is evaluated without pushdown
+            let expected = solutions(QueryEvaluator::new().prepare(&parsed).execute(&hdt)?)?;
+            assert_eq!(solutions(query(&q, &hdt)?)?, expected, "{filter}");
+            assert!(!expected.is_empty(), "{filter}");
+        }
+        // extracting the labels of a single subject is cheaper than scanning the dictionary
+        let top = "<http://www.snik.eu/ontology/meta/Top>";
+        let q = format!("SELECT ?l {{ {top} {label} ?l FILTER(REGEX(?l, 'class')) }}");
+        let Query::Select { pattern, .. } = SparqlParser::new().parse_query(&q)? else { unreachable!() };
+        assert_eq!(push_down_filters(&hdt, pattern.clone()), pattern);
+        Ok(())
+    }
+
+    #[test]
+    fn results() -> Result<()> {
+        init();
+        let hdt = crate::hdt::tests::snikmeta()?;
+        let label = "<http://www.w3.org/2000/01/rdf-schema#label>";
+        let select = format!("SELECT ?s ?l {{ ?s {label} ?l }} ORDER BY ?l");
+        let write = |q: &str, accept: &str, limit: usize| -> Result<(ResultsFormat, String)> {
+            let results = query(q, &hdt)?;
+            let format = ResultsFormat::negotiate(accept, &results);
+            let mut buf = Vec::new();
+            write_results(results, format, limit, None, &mut buf)?;
+            Ok((format, String::from_utf8(buf)?))
+        };
+        let (format, json) = write(&select, "text/html, application/sparql-results+json", 2)?;
+        assert_eq!(format, ResultsFormat::Json);
+        assert!(json.starts_with("{\"head\":{\"vars\":[\"s\",\"l\"]}"), "{json}");
+        assert_eq!(json.matches("\"type\":\"uri\"").count(), 2);
+        let (_, xml) = write(&select, "application/sparql-results+xml", 3)?;
+        assert_eq!(xml.matches("<result>").count(), 3);
+        let (_, csv) = write(&select, "text/csv", usize::MAX)?;
+        let (_, tsv) = write(&select, "text/tab-separated-values", usize::MAX)?;
+        let count =
+            hdt.triples_with_pattern(None, Some("http://www.w3.org/2000/01/rdf-schema#label"), None).count();
+        assert_eq!(csv.lines().count(), count + 1);
+        assert_eq!(tsv.lines().next(), Some("?s\t?l"));
+        assert_eq!(tsv.lines().count(), count + 1);
+        let (format, ask) = write(&format!("ASK {{ ?s {label} \"top class\"@en }}"), "text/csv", 10)?;
+        assert_eq!((format, ask.trim()), (ResultsFormat::Json, "{\"head\":{},\"boolean\":true}"));
+        let (format, nt) = write(&format!("CONSTRUCT {{ ?s {label} ?l }} WHERE {{ ?s {label} ?l }}"), "*/*", 5)?;
+        assert_eq!(format, ResultsFormat::Turtle);
+        assert_eq!(nt.lines().count(), 5);
+        // timeout
+        let results = query(&select, &hdt)?;
+        let e =
+            write_results(results, ResultsFormat::Json, 10, Some(Instant::now()), &mut Vec::new()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::TimedOut);
+        // the deadline of the dataset stops ORDER BY before the first solution
+        let results = query_with_deadline(&select, &hdt, Instant::now())?;
+        let e = ResultsPage::new(results, 10, None).unwrap_err();
+        assert!(e.to_string().contains("timed out"), "{e}");
+        // truncation
+        let page = ResultsPage::new(query(&select, &hdt)?, 2, None)?;
+        assert!(page.truncated());
+        let mut buf = Vec::new();
+        page.write(ResultsFormat::Json, &mut buf)?;
+        assert_eq!(String::from_utf8(buf)?.matches("\"type\":\"uri\"").count(), 2);
+        assert!(!ResultsPage::new(query(&select, &hdt)?, usize::MAX, None)?.truncated());
+        // protocol
+        let q = "SELECT * { ?s ?p ?o }";
+        assert_eq!(protocol_query(false, "", "query=SELECT+*+%7B+%3Fs+%3Fp+%3Fo+%7D", ""), Some(q.to_owned()));
+        assert_eq!(protocol_query(true, "application/sparql-query", "", q), Some(q.to_owned()));
+        assert_eq!(
+            protocol_query(
+                true, "application/x-www-form-urlencoded", "",
+                "default-graph-uri=&query=SELECT+*+%7B+%3Fs+%3Fp+%3Fo+%7D"
+            ),
+            Some(q.to_owned())
+        );
+        assert_eq!(protocol_query(false, "", "foo=bar", ""), None);
+        Ok(())
+    }
+
     const MF: Namespace<&str> =
         Namespace::new_unchecked_const("http://www.w3.org/2001/sw/DataAccess/tests/test-manifest#");
     const QT: Namespace<&str> =
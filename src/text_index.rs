@@ -0,0 +1,326 @@
+//! Full-text search over the labels of literal objects, for example to find resources by their `rdfs:label`.
+//! Labels are split into tokens at each character that is not alphanumeric and the tokens are lowercased.
+//! A text matches a literal if each of its tokens is a prefix of a token of the literal, e.g. "leip" matches "Leipzig".
+//!
+//! The [`TextIndex`] maps each token to the IDs of the literals that contain it.
+//! It is optional and not part of the HDT format, so it is built once and persisted in a separate file.
+//! Without it, [`Hdt::objects_matching_text`] scans the whole dictionary.
+use crate::containers::vbyte::{encode_vbyte, read_vbyte};
+use crate::triples::Id;
+use crate::{Dict, DictSectPFC, Dictionary, Hdt};
+use bytesize::ByteSize;
+use std::collections::BTreeMap;
+use std::io::{BufRead, Cursor, Read, Write};
+use std::{fmt, str};
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Identifies the file format, including its version.
+const MAGIC: &[u8] = b"HDTTEXT1";
+
+/// The error type for reading and attaching a text index.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("IO error")]
+    Io(#[from] std::io::Error),
+    #[error("not a text index file or unsupported version")]
+    InvalidMagic,
+    #[error("Invalid CRC32-C checksum {0}, expected {1}")]
+    InvalidCrc32Checksum(u32, u32),
+    #[error(
+        "text index was built from a different dictionary with section checksums {found:?}, expected {expected:?}"
+    )]
+    DictMismatch { expected: Vec<u32>, found: Vec<u32> },
+}
+
+/// Inverted index from the tokens of the labels of literal objects to their object IDs.
+#[cfg_attr(test, derive(PartialEq))]
+pub struct TextIndex {
+    /// checksums of the shared and object sections of the dictionary the index was built from, see [`dict_crcs`]
+    dict_crcs: Vec<u32>,
+    /// distinct tokens in ascending order
+    tokens: Vec<String>,
+    /// IDs of the literals that contain the token with the same index in ascending order
+    postings: Vec<Vec<Id>>,
+}
+
+impl fmt::Debug for TextIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "total size {}, {} tokens, {} postings",
+            ByteSize(self.size_in_bytes() as u64),
+            self.tokens.len(),
+            self.postings.iter().map(Vec::len).sum::<usize>()
+        )
+    }
+}
+
+/// Lowercased tokens of a text, split at each character that is not alphanumeric.
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric()).filter(|token| !token.is_empty()).map(str::to_lowercase)
+}
+
+/// The label of a literal, which is the part between the first and last quotation mark,
+/// or None for IRIs and blank nodes.
+/// Covers the prefixed form of the PSFC dictionary and the labels without datatype of the multiple section dictionary.
+pub(crate) fn label(s: &[u8]) -> Option<&str> {
+    if !(s.starts_with(b"\"") || s.starts_with(b"^^") || s.starts_with(b"@")) {
+        return None;
+    }
+    let start = s.iter().position(|&b| b == b'"')?;
+    let end = s.iter().rposition(|&b| b == b'"')?;
+    str::from_utf8(s.get(start + 1..end)?).ok()
+}
+
+/// Object IDs and stored strings of the terms that only occur as objects, which includes all literals.
+/// The sections are decoded sequentially, which is much faster than extracting each ID.
+fn object_strings(dict: &Dict) -> Box<dyn Iterator<Item = (Id, Vec<u8>)> + '_> {
+    let offset = dict.num_shared() + 1;
+    match dict {
+        Dict::Four(d) | Dict::FourPsfc(d) => {
+            Box::new(d.objects.iter().enumerate().map(move |(i, s)| (offset + i, s)))
+        }
+        Dict::Mult(d) => Box::new(
+            d.objects.iter().flat_map(|(_, sect)| sect.iter()).enumerate().map(move |(i, s)| (offset + i, s)),
+        ),
+    }
+}
+
+/// Fingerprint of a dictionary to detect an index that was built from another one:
+/// the CRC32-C checksums of the shared and object sections, which contain all object terms.
+fn dict_crcs(dict: &Dict) -> Vec<u32> {
+    match dict {
+        Dict::Four(d) | Dict::FourPsfc(d) => vec![d.shared.crc32(), d.objects.crc32()],
+        Dict::Mult(d) => {
+            std::iter::once(&d.shared).chain(d.objects.iter().map(|(_, s)| s)).map(DictSectPFC::crc32).collect()
+        }
+    }
+}
+
+impl TextIndex {
+    /// Tokenizes the labels of all literal objects of the dictionary.
+    pub fn build(dict: &Dict) -> Self {
+        let mut map: BTreeMap<String, Vec<Id>> = BTreeMap::new();
+        for (id, s) in object_strings(dict) {
+            let Some(label) = label(&s) else { continue };
+            for token in tokenize(label) {
+                let ids = map.entry(token).or_default();
+                // IDs ascend, so a token that occurs multiple times in a label is added only once
+                if ids.last() != Some(&id) {
+                    ids.push(id);
+                }
+            }
+        }
+        let (tokens, postings) = map.into_iter().unzip();
+        TextIndex { dict_crcs: dict_crcs(dict), tokens, postings }
+    }
+
+    /// Object IDs of the literals whose label contains a token starting with each token of the text, in ascending order.
+    /// Returns no IDs if the text has no tokens.
+    pub fn search(&self, text: &str) -> Vec<Id> {
+        let mut result: Option<Vec<Id>> = None;
+        for token in tokenize(text) {
+            // tokens with the same prefix are contiguous
+            let start = self.tokens.partition_point(|t| *t < token);
+            let end = start + self.tokens[start..].partition_point(|t| t.starts_with(&token));
+            let mut ids: Vec<Id> = self.postings[start..end].iter().flatten().copied().collect();
+            ids.sort_unstable();
+            ids.dedup();
+            result = Some(match result {
+                None => ids,
+                Some(previous) => previous.into_iter().filter(|id| ids.binary_search(id).is_ok()).collect(),
+            });
+        }
+        result.unwrap_or_default()
+    }
+
+    /// Size in bytes of the in-memory index.
+    pub fn size_in_bytes(&self) -> usize {
+        self.tokens.iter().map(String::len).sum::<usize>()
+            + self.postings.iter().map(|ids| ids.len() * size_of::<Id>()).sum::<usize>()
+    }
+
+    /// Writes the index with delta and vbyte encoded postings, followed by a CRC32-C checksum.
+    pub fn write(&self, write: &mut impl Write) -> Result<()> {
+        let mut buf = MAGIC.to_vec();
+        buf.extend_from_slice(&encode_vbyte(self.dict_crcs.len()));
+        for crc in &self.dict_crcs {
+            buf.extend_from_slice(&crc.to_le_bytes());
+        }
+        buf.extend_from_slice(&encode_vbyte(self.tokens.len()));
+        for (token, ids) in self.tokens.iter().zip(&self.postings) {
+            buf.extend_from_slice(&encode_vbyte(token.len()));
+            buf.extend_from_slice(token.as_bytes());
+            buf.extend_from_slice(&encode_vbyte(ids.len()));
+            let mut previous = 0;
+            for &id in ids {
+                buf.extend_from_slice(&encode_vbyte(id - previous));
+                previous = id;
+            }
+        }
+        let crc32 = crc::Crc::<u32>::new(&crc::CRC_32_ISCSI);
+        write.write_all(&buf)?;
+        write.write_all(&crc32.checksum(&buf).to_le_bytes())?;
+        write.flush()?;
+        Ok(())
+    }
+
+    /// Reads an index written by [`TextIndex::write`] and verifies its checksum.
+    pub fn read<R: BufRead>(reader: &mut R) -> Result<Self> {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        if !buf.starts_with(MAGIC) || buf.len() < MAGIC.len() + 4 {
+            return Err(Error::InvalidMagic);
+        }
+        let (data, crc_code) = buf.split_at(buf.len() - 4);
+        let crc32 = crc::Crc::<u32>::new(&crc::CRC_32_ISCSI);
+        let crc_calculated = crc32.checksum(data);
+        let crc_code = u32::from_le_bytes(crc_code.try_into().unwrap());
+        if crc_calculated != crc_code {
+            return Err(Error::InvalidCrc32Checksum(crc_calculated, crc_code));
+        }
+        let mut cursor = Cursor::new(&data[MAGIC.len()..]);
+        let num_crcs = read_vbyte(&mut cursor)?.0;
+        let mut dict_crcs = Vec::with_capacity(num_crcs);
+        for _ in 0..num_crcs {
+            let mut crc = [0; 4];
+            cursor.read_exact(&mut crc)?;
+            dict_crcs.push(u32::from_le_bytes(crc));
+        }
+        let num_tokens = read_vbyte(&mut cursor)?.0;
+        let mut tokens = Vec::with_capacity(num_tokens);
+        let mut postings = Vec::with_capacity(num_tokens);
+        for _ in 0..num_tokens {
+            let len = read_vbyte(&mut cursor)?.0;
+            let mut token = vec![0; len];
+            cursor.read_exact(&mut token)?;
+            tokens.push(String::from_utf8(token).map_err(std::io::Error::other)?);
+            let num_ids = read_vbyte(&mut cursor)?.0;
+            let mut ids = Vec::with_capacity(num_ids);
+            let mut previous = 0;
+            for _ in 0..num_ids {
+                previous += read_vbyte(&mut cursor)?.0;
+                ids.push(previous);
+            }
+            postings.push(ids);
+        }
+        Ok(TextIndex { dict_crcs, tokens, postings })
+    }
+}
+
+impl Hdt {
+    /// Builds a full-text index over the labels of the literal objects that is used by [`Hdt::objects_matching_text`].
+    /// Persist it with [`TextIndex::write`] to avoid rebuilding it each time the HDT is loaded.
+    pub fn build_text_index(&mut self) -> &TextIndex {
+        self.text_index.insert(TextIndex::build(&self.dict))
+    }
+
+    /// Uses a previously built full-text index, which fails if it was built from a different dictionary.
+    /// The dictionaries are compared by the checksums of their shared and object sections, which are computed in one pass.
+    /// # Example
+    /// ```
+    /// use hdt::text_index::TextIndex;
+    /// let file = std::fs::File::open("tests/resources/snikmeta.hdt").unwrap();
+    /// let mut hdt = hdt::Hdt::read(std::io::BufReader::new(file)).unwrap();
+    /// let mut buf = Vec::new();
+    /// hdt.build_text_index().write(&mut buf).unwrap();
+    /// // later, instead of building it again
+    /// hdt.set_text_index(TextIndex::read(&mut buf.as_slice()).unwrap()).unwrap();
+    /// ```
+    pub fn set_text_index(&mut self, index: TextIndex) -> Result<()> {
+        let expected = dict_crcs(&self.dict);
+        if expected != index.dict_crcs {
+            return Err(Error::DictMismatch { expected, found: index.dict_crcs });
+        }
+        self.text_index = Some(index);
+        Ok(())
+    }
+
+    /// The full-text index if one was built or set.
+    pub const fn text_index(&self) -> Option<&TextIndex> {
+        self.text_index.as_ref()
+    }
+
+    /// Object IDs of the literals whose label contains a token starting with each token of the text, in ascending order.
+    /// The IDs can be used to query the triples, e.g. with [`crate::triples::ObjectIter`].
+    /// Uses the full-text index if there is one and scans all objects of the dictionary otherwise.
+    /// # Example
+    /// ```
+    /// use hdt::{Dictionary, IdKind};
+    /// let file = std::fs::File::open("tests/resources/snikmeta.hdt").unwrap();
+    /// let mut hdt = hdt::Hdt::read(std::io::BufReader::new(file)).unwrap();
+    /// hdt.build_text_index();
+    /// for id in hdt.objects_matching_text("top class") {
+    ///     println!("{}", hdt.dict.id_to_string(id, IdKind::Object).unwrap());
+    /// }
+    /// ```
+    pub fn objects_matching_text(&self, text: &str) -> Vec<Id> {
+        if let Some(index) = &self.text_index {
+            return index.search(text);
+        }
+        let query: Vec<String> = tokenize(text).collect();
+        if query.is_empty() {
+            return Vec::new();
+        }
+        object_strings(&self.dict)
+            .filter_map(|(id, s)| {
+                let tokens: Vec<String> = tokenize(label(&s)?).collect();
+                query.iter().all(|q| tokens.iter().any(|t| t.starts_with(q))).then_some(id)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IdKind;
+    use crate::hdt::tests::snikmeta;
+    use crate::tests::init;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn tokens() {
+        assert_eq!(
+            tokenize("Leipzig, Sachsen-Anhalt 2024!").collect::<Vec<_>>(),
+            ["leipzig", "sachsen", "anhalt", "2024"]
+        );
+        assert_eq!(label(br#""top class"@en"#), Some("top class"));
+        assert_eq!(label(br#"^^<http://www.w3.org/2001/XMLSchema#integer>"1""#), Some("1"));
+        assert_eq!(label(b"http://www.snik.eu/ontology/meta/Top"), None);
+        assert_eq!(label(b"_:b1"), None);
+    }
+
+    #[test]
+    fn search() -> color_eyre::Result<()> {
+        init();
+        let mut hdt = snikmeta()?;
+        let queries = ["top", "TOP CLASS", "class top", "func", "role", "x", "", "  ", "nonexistingtoken"];
+        let scanned: Vec<Vec<Id>> = queries.iter().map(|q| hdt.objects_matching_text(q)).collect();
+        assert!(!scanned[0].is_empty());
+        assert_eq!(scanned[1], scanned[2]);
+        assert!(scanned[6].is_empty() && scanned[7].is_empty() && scanned[8].is_empty());
+        hdt.build_text_index();
+        for (query, expected) in queries.iter().zip(&scanned) {
+            assert_eq!(&hdt.objects_matching_text(query), expected, "{query:?}");
+        }
+        for &id in &scanned[1] {
+            let label = hdt.dict.id_to_string(id, IdKind::Object)?.to_lowercase();
+            assert!(label.contains("top") && label.contains("class"), "{label}");
+            assert!(hdt.triples.triples_with_pattern([0, 0, id]).next().is_some());
+        }
+        let mut buf = Vec::new();
+        hdt.text_index().unwrap().write(&mut buf)?;
+        let index = TextIndex::read(&mut buf.as_slice())?;
+        assert_eq!(Some(&index), hdt.text_index());
+        hdt.set_text_index(index)?;
+        // corrupted data and indexes of other dictionaries are rejected
+        let last = buf.len() - 5;
+        buf[last] ^= 1;
+        assert!(matches!(TextIndex::read(&mut buf.as_slice()), Err(Error::InvalidCrc32Checksum(..))));
+        let other = TextIndex { dict_crcs: vec![0, 0], tokens: Vec::new(), postings: Vec::new() };
+        assert!(matches!(hdt.set_text_index(other), Err(Error::DictMismatch { .. })));
+        Ok(())
+    }
+}
@@ -0,0 +1,333 @@
+//! [Triple Pattern Fragments](https://linkeddatafragments.org/specification/triple-pattern-fragments/) (TPF) of an HDT,
+//! which answer a single triple pattern with a page of matching triples, the estimated total count and hypermedia controls for the next pages and other patterns.
+//! Independent of the HTTP server, see the `hdt serve` command for an example.
+use crate::escape::{percent_decode, percent_encode};
+use crate::four_sect_dict::ExtractError;
+use crate::hdt_graph::{HdtTerm, auto_term};
+use crate::triples::{Cardinality, TripleId};
+use crate::{Dictionary, Hdt, IdKind};
+#[cfg(feature = "jsonld")]
+use sophia::api::graph::Graph;
+#[cfg(feature = "jsonld")]
+use sophia::api::prelude::QuadSerializer;
+use sophia::api::prelude::TripleSerializer;
+use sophia::api::term::BnodeId;
+use sophia::iri::IriRef;
+#[cfg(feature = "jsonld")]
+use sophia::jsonld::JsonLdSerializer;
+use sophia::turtle::serializer::nt::NtSerializer;
+use sophia::turtle::serializer::turtle::{TurtleConfig, TurtleSerializer};
+use std::io::{self, Write};
+use std::sync::Arc;
+
+/// Default number of triples per page.
+pub const PAGE_SIZE: usize = 100;
+
+const RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const HYDRA: &str = "http://www.w3.org/ns/hydra/core#";
+const VOID: &str = "http://rdfs.org/ns/void#";
+const XSD_INTEGER: &str = "http://www.w3.org/2001/XMLSchema#integer";
+const XSD_STRING: &str = "http://www.w3.org/2001/XMLSchema#string";
+/// query parameters of the subject, predicate and object
+const PARAMS: [&str; 3] = ["subject", "predicate", "object"];
+
+/// RDF serialization of a fragment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// RDF Turtle, the default
+    Turtle,
+    /// N-Triples
+    NTriples,
+    /// expanded JSON-LD, only available if HDT is built with the `"jsonld"` feature
+    #[cfg(feature = "jsonld")]
+    JsonLd,
+}
+
+impl Format {
+    /// Negotiates the format from the value of an HTTP Accept header, Turtle if none is supported.
+    pub fn from_accept(accept: &str) -> Self {
+        let supported = [
+            ("text/turtle", Format::Turtle),
+            ("application/n-triples", Format::NTriples),
+            #[cfg(feature = "jsonld")]
+            ("application/ld+json", Format::JsonLd),
+        ];
+        // the first supported media type wins, quality values are not considered
+        accept
+            .split(',')
+            .filter_map(|m| supported.iter().find(|(t, _)| m.trim().starts_with(t)).map(|(_, f)| *f))
+            .next()
+            .unwrap_or(Format::Turtle)
+    }
+
+    /// Media type for the HTTP Content-Type header.
+    pub const fn media_type(self) -> &'static str {
+        match self {
+            Format::Turtle => "text/turtle; charset=utf-8",
+            Format::NTriples => "application/n-triples; charset=utf-8",
+            #[cfg(feature = "jsonld")]
+            Format::JsonLd => "application/ld+json",
+        }
+    }
+}
+
+/// Triple pattern and page number of a fragment request.
+/// Terms are in the string representation of the dictionary, see [`crate::Dictionary`], and `None` stands for a variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FragmentRequest {
+    /// subject, predicate and object of the triple pattern
+    pub pattern: [Option<String>; 3],
+    /// page number starting at 1
+    pub page: usize,
+}
+
+impl Default for FragmentRequest {
+    fn default() -> Self {
+        FragmentRequest { pattern: [None, None, None], page: 1 }
+    }
+}
+
+impl FragmentRequest {
+    /// Parses the URL query string of a request, such as `subject=http%3A%2F%2Fexample.org%2Fs&page=2`.
+    /// Terms use the explicit representation of the hydra core vocabulary, i.e. `http://example.org/s`, `"chat"@fr` and `"5"^^http://www.w3.org/2001/XMLSchema#integer`.
+    /// Missing, empty and variable terms like `?s` are unbound, unknown parameters are ignored.
+    pub fn from_query(query: &str) -> Self {
+        let mut request = FragmentRequest::default();
+        for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+            let value = percent_decode(value);
+            if let Some(i) = PARAMS.iter().position(|p| *p == key) {
+                if !value.is_empty() && !value.starts_with('?') {
+                    request.pattern[i] = Some(dict_term(&value));
+                }
+            } else if key == "page" {
+                request.page = value.parse().unwrap_or(1).max(1);
+            }
+        }
+        request
+    }
+
+    /// URL of the given page of this pattern.
+    fn page_url(&self, base_url: &str, page: usize) -> String {
+        let mut params: Vec<String> = PARAMS
+            .iter()
+            .zip(&self.pattern)
+            .filter_map(|(p, t)| t.as_ref().map(|t| format!("{p}={}", percent_encode(&explicit_term(t)))))
+            .collect();
+        if page > 1 {
+            params.push(format!("page={page}"));
+        }
+        if params.is_empty() { base_url.to_owned() } else { format!("{base_url}?{}", params.join("&")) }
+    }
+}
+
+/// Converts the explicit representation of a term to the string representation of the dictionary.
+fn dict_term(s: &str) -> String {
+    if let Some(iri) = s.strip_prefix('<').and_then(|s| s.strip_suffix('>')) {
+        return iri.to_owned();
+    }
+    match s.rfind("\"^^") {
+        Some(i) if s.starts_with('"') && !s[i + 3..].starts_with('<') => {
+            format!("{}^^<{}>", &s[..=i], &s[i + 3..])
+        }
+        _ => s.to_owned(),
+    }
+}
+
+/// Converts the string representation of the dictionary to the explicit representation of a term.
+fn explicit_term(s: &str) -> String {
+    match s.rfind("\"^^<") {
+        Some(i) if s.starts_with('"') && s.ends_with('>') => format!("{}^^{}", &s[..=i], &s[i + 4..s.len() - 1]),
+        _ => s.to_owned(),
+    }
+}
+
+/// A page of the triples that match a triple pattern.
+#[derive(Debug, Clone)]
+pub struct Fragment {
+    /// pattern and page number
+    pub request: FragmentRequest,
+    /// matching triples of the requested page in the string representation of the dictionary
+    pub triples: Vec<[String; 3]>,
+    /// number of matching triples of all pages
+    pub count: Cardinality,
+    /// whether there is a next page
+    pub has_next: bool,
+    /// maximum number of triples per page
+    pub page_size: usize,
+}
+
+impl Fragment {
+    /// Fetches the requested page from the HDT, which seeks to the offset without iterating over the previous pages.
+    pub fn new(hdt: &Hdt, request: FragmentRequest, page_size: usize) -> Result<Self, ExtractError> {
+        let mut pattern: TripleId = [0; 3];
+        let mut missing = false;
+        for (i, term) in request.pattern.iter().enumerate() {
+            if let Some(term) = term {
+                pattern[i] = hdt.dict.string_to_id(term, IdKind::KINDS[i]);
+                missing |= pattern[i] == 0;
+            }
+        }
+        if missing {
+            let count = Cardinality::exact(0);
+            return Ok(Fragment { request, triples: Vec::new(), count, has_next: false, page_size });
+        }
+        let count = hdt.estimate_count(pattern);
+        let offset = (request.page - 1).saturating_mul(page_size);
+        // one more to find out whether there is a next page, as the count may only be estimated
+        let mut triples = hdt
+            .triple_ids_with_pattern_page(pattern, offset, page_size.saturating_add(1))
+            .map(|t| {
+                let [s, p, o] = [0, 1, 2].map(|i| hdt.dict.id_to_string(t[i], IdKind::KINDS[i]));
+                Ok::<_, ExtractError>([s?, p?, o?])
+            })
+            .collect::<Result<Vec<_>, ExtractError>>()?;
+        let has_next = triples.len() > page_size;
+        triples.truncate(page_size);
+        Ok(Fragment { request, triples, count, has_next, page_size })
+    }
+
+    /// Data triples followed by the metadata and hypermedia control triples.
+    /// `base_url` is the absolute URL of the dataset, which answers the fragment requests.
+    fn graph(&self, base_url: &str) -> io::Result<Vec<[HdtTerm; 3]>> {
+        let mut graph = Vec::with_capacity(self.triples.len() + 32);
+        for triple in &self.triples {
+            let [s, p, o] = triple;
+            graph.push([auto_term(s)?, auto_term(p)?, auto_term(o)?]);
+        }
+        let dataset = iri(&format!("{base_url}#dataset"));
+        let fragment = iri(&self.request.page_url(base_url, self.request.page));
+        let search = blank("search");
+        let page = |n: usize| iri(&self.request.page_url(base_url, n));
+        let mut add = |s: &HdtTerm, p: String, o: HdtTerm| graph.push([s.clone(), iri(&p), o]);
+        add(&dataset, format!("{RDF}type"), iri(&format!("{VOID}Dataset")));
+        add(&dataset, format!("{RDF}type"), iri(&format!("{HYDRA}Collection")));
+        add(&dataset, format!("{VOID}subset"), fragment.clone());
+        add(&dataset, format!("{HYDRA}search"), search.clone());
+        add(&search, format!("{HYDRA}template"), string(&format!("{base_url}{{?subject,predicate,object}}")));
+        add(&search, format!("{HYDRA}variableRepresentation"), iri(&format!("{HYDRA}ExplicitRepresentation")));
+        for param in PARAMS {
+            let mapping = blank(param);
+            add(&search, format!("{HYDRA}mapping"), mapping.clone());
+            add(&mapping, format!("{HYDRA}variable"), string(param));
+            add(&mapping, format!("{HYDRA}property"), iri(&format!("{RDF}{param}")));
+        }
+        add(&fragment, format!("{RDF}type"), iri(&format!("{HYDRA}PartialCollectionView")));
+        add(&fragment, format!("{VOID}triples"), integer(self.count.estimate));
+        add(&fragment, format!("{HYDRA}totalItems"), integer(self.count.estimate));
+        add(&fragment, format!("{HYDRA}itemsPerPage"), integer(self.page_size));
+        add(&fragment, format!("{HYDRA}first"), page(1));
+        if self.request.page > 1 {
+            add(&fragment, format!("{HYDRA}previous"), page(self.request.page - 1));
+        }
+        if self.has_next {
+            add(&fragment, format!("{HYDRA}next"), page(self.request.page + 1));
+        }
+        Ok(graph)
+    }
+
+    /// Serializes the triples of the page together with the metadata and controls.
+    pub fn write(&self, base_url: &str, format: Format, write: &mut impl Write) -> io::Result<()> {
+        let graph = self.graph(base_url)?;
+        match format {
+            Format::Turtle => {
+                TurtleSerializer::new_with_config(write, TurtleConfig::new().with_pretty(true))
+                    .serialize_graph(&graph)
+                    .map_err(io::Error::other)?;
+            }
+            Format::NTriples => {
+                NtSerializer::new(write).serialize_graph(&graph).map_err(io::Error::other)?;
+            }
+            #[cfg(feature = "jsonld")]
+            Format::JsonLd => {
+                JsonLdSerializer::new(write).serialize_dataset(&graph.as_dataset()).map_err(io::Error::other)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn iri(s: &str) -> HdtTerm {
+    HdtTerm::Iri(IriRef::new_unchecked(Arc::from(s)))
+}
+
+fn blank(id: &str) -> HdtTerm {
+    HdtTerm::BlankNode(BnodeId::new_unchecked(Arc::from(id)))
+}
+
+fn string(s: &str) -> HdtTerm {
+    HdtTerm::LiteralDatatype(Arc::from(s), IriRef::new_unchecked(Arc::from(XSD_STRING)))
+}
+
+fn integer(n: usize) -> HdtTerm {
+    HdtTerm::LiteralDatatype(Arc::from(n.to_string()), IriRef::new_unchecked(Arc::from(XSD_INTEGER)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hdt::tests::snikmeta;
+    use crate::tests::init;
+    use pretty_assertions::assert_eq;
+
+    const LABEL: &str = "http://www.w3.org/2000/01/rdf-schema#label";
+
+    #[test]
+    fn request() {
+        let request = FragmentRequest::from_query(
+            "subject=%3Fs&predicate=http%3A%2F%2Fexample.org%2Fp&object=%22a+b%22%5E%5Ehttp%3A%2F%2Fexample.org%2Fdt&page=3",
+        );
+        let pattern =
+            [None, Some("http://example.org/p".to_owned()), Some("\"a b\"^^<http://example.org/dt>".to_owned())];
+        assert_eq!(request, FragmentRequest { pattern, page: 3 });
+        let url = request.page_url("http://localhost/", 2);
+        assert_eq!(
+            FragmentRequest::from_query(url.split_once('?').unwrap().1),
+            FragmentRequest { page: 2, ..request }
+        );
+        assert_eq!(FragmentRequest::from_query("page=0&foo=bar"), FragmentRequest::default());
+        #[cfg(feature = "jsonld")]
+        assert_eq!(Format::from_accept("application/ld+json;q=0.9, text/html"), Format::JsonLd);
+        assert_eq!(Format::from_accept("*/*"), Format::Turtle);
+    }
+
+    #[test]
+    fn fragment() -> color_eyre::Result<()> {
+        init();
+        let hdt = snikmeta()?;
+        let base = "http://localhost:3000/";
+        let mut request = FragmentRequest { pattern: [None, Some(LABEL.to_owned()), None], page: 1 };
+        let mut triples = Vec::new();
+        loop {
+            let fragment = Fragment::new(&hdt, request.clone(), 10)?;
+            assert!(fragment.triples.len() <= 10);
+            triples.extend(fragment.triples.iter().cloned());
+            let mut nt = Vec::new();
+            fragment.write(base, Format::NTriples, &mut nt)?;
+            let nt = String::from_utf8(nt)?;
+            assert_eq!(nt.contains(&format!("<{HYDRA}next>")), fragment.has_next);
+            assert!(nt.contains(&format!("<{base}#dataset> <{VOID}subset> <{base}?predicate=")));
+            for format in [
+                Format::Turtle,
+                #[cfg(feature = "jsonld")]
+                Format::JsonLd,
+            ] {
+                let mut buf = Vec::new();
+                fragment.write(base, format, &mut buf)?;
+                assert!(String::from_utf8(buf)?.contains("totalItems"));
+            }
+            if !fragment.has_next {
+                break;
+            }
+            request.page += 1;
+        }
+        let expected: Vec<[String; 3]> =
+            hdt.triples_with_pattern(None, Some(LABEL), None).map(|t| t.map(|x| x.to_string())).collect();
+        assert_eq!(triples, expected);
+        let missing =
+            FragmentRequest { pattern: [Some("http://example.org/missing".to_owned()), None, None], page: 1 };
+        let fragment = Fragment::new(&hdt, missing, 10)?;
+        assert!(fragment.triples.is_empty() && !fragment.has_next);
+        assert_eq!(fragment.count, Cardinality::exact(0));
+        Ok(())
+    }
+}
@@ -1,13 +1,15 @@
 use crate::ControlInfo;
-use crate::containers::{AdjList, Bitmap, Sequence, bitmap, control_info, sequence};
+use crate::containers::control_info::{ControlType, INDEX_FOQ};
+use crate::containers::{AdjList, Bitmap, BytesReader, Sequence, bitmap, control_info, sequence};
 use bytesize::ByteSize;
-use log::error;
+use log::{error, warn};
 use qwt::QWT512;
 use qwt::mem_dbg::{MemSize, SizeFlags};
-use qwt::{AccessUnsigned, BitVector, BitVectorMut, bitvector::rs_narrow::RSNarrow};
+use qwt::{AccessUnsigned, BitVector, BitVectorMut, RankUnsigned};
 use std::cmp::Ordering;
 use std::fmt;
 use std::io::BufRead;
+use std::sync::OnceLock;
 
 mod subject_iter;
 pub use subject_iter::SubjectIter;
@@ -17,16 +19,23 @@ mod predicate_object_iter;
 pub use predicate_object_iter::PredicateObjectIter;
 mod object_iter;
 pub use object_iter::ObjectIter;
+mod triples_list;
 #[cfg(feature = "cache")]
 use serde::{self, Deserialize, Serialize};
+pub use triples_list::TriplesList;
 
 pub type Result<T> = core::result::Result<T, Error>;
 
+/// Number of entries between two checks for cancellation while building the wavelet matrix and the object index.
+const CANCEL_INTERVAL: usize = 1 << 20;
+
 /// Order of the triple sections.
-/// Only SPO is tested, others probably don't work correctly.
+/// Determines which triple components are stored in the x (bitmap Y), y (wavelet) and z (adjacency list) layers.
+/// The default order used by this library, hdt-cpp and hdt-java is SPO.
+/// Triples sections with an unknown order are rejected when loading.
 #[allow(missing_docs)]
 #[repr(u8)]
-#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[cfg_attr(feature = "cache", derive(serde::Deserialize, serde::Serialize))]
 pub enum Order {
     #[default]
@@ -56,6 +65,37 @@ impl TryFrom<u32> for Order {
     }
 }
 
+impl Order {
+    /// All known orders.
+    pub const ORDERS: [Order; 6] = [Order::SPO, Order::SOP, Order::PSO, Order::POS, Order::OSP, Order::OPS];
+
+    /// Rearrange a triple or triple pattern in subject, predicate, object order into the x, y, z layers of this order.
+    /// `Unknown` is handled like SPO.
+    pub const fn to_coord(self, [s, p, o]: TripleId) -> [Id; 3] {
+        match self {
+            Order::Unknown | Order::SPO => [s, p, o],
+            Order::SOP => [s, o, p],
+            Order::PSO => [p, s, o],
+            Order::POS => [p, o, s],
+            Order::OSP => [o, s, p],
+            Order::OPS => [o, p, s],
+        }
+    }
+
+    /// Rearrange x, y, z layer coordinates of this order into a triple in subject, predicate, object order.
+    /// Inverse of [`Order::to_coord`].
+    pub const fn to_triple(self, [x, y, z]: [Id; 3]) -> TripleId {
+        match self {
+            Order::Unknown | Order::SPO => [x, y, z],
+            Order::SOP => [x, z, y],
+            Order::PSO => [y, x, z],
+            Order::POS => [z, x, y],
+            Order::OSP => [y, z, x],
+            Order::OPS => [z, y, x],
+        }
+    }
+}
+
 /// Inverse index from object id to positions in the object adjacency list.
 /// This object-based index allows to traverse from the leaves and support ??O and ?PO queries.
 /// Used for logarithmic (?) time access instead of linear time sequential search.
@@ -85,6 +125,93 @@ impl fmt::Debug for OpIndex {
 }
 
 impl OpIndex {
+    /// Builds the object index of the given Z layer, sorting the positions of each object by predicate.
+    fn new(adjlist_z: &AdjList, wavelet_y: &WT) -> Self {
+        Self::build(adjlist_z, wavelet_y, &mut || false).expect("construction is not cancelled")
+    }
+
+    /// Like [`OpIndex::new`] but checks every [`CANCEL_INTERVAL`] entries whether to stop and return `None`.
+    fn build(adjlist_z: &AdjList, wavelet_y: &WT, cancelled: &mut dyn FnMut() -> bool) -> Option<Self> {
+        let entries = adjlist_z.sequence.entries;
+        // if it takes too long to calculate, can also pass in as parameter
+        let max_object = adjlist_z.sequence.into_iter().max().unwrap_or(0).to_owned();
+        // limited to < 2^32 objects
+        let mut indicess = vec![Vec::<u32>::with_capacity(4); max_object];
+        // Count the indexes of appearance of each object
+        // In https://github.com/rdfhdt/hdt-cpp/blob/develop/libhdt/src/triples/BitmapTriples.cpp
+        // they count the number of appearances in a sequence instead, which saves memory
+        // temporarily but they need to loop over it an additional time.
+        for pos_z in 0..entries {
+            if pos_z % CANCEL_INTERVAL == 0 && cancelled() {
+                return None;
+            }
+            let object = adjlist_z.sequence.get(pos_z);
+            if object == 0 {
+                error!("ERROR: There is a zero value in the Z level.");
+                continue;
+            }
+            let pos_y = adjlist_z.bitmap.rank(pos_z.to_owned());
+            indicess[object - 1].push(pos_y as u32); // hdt index counts from 1 but we count from 0 for simplicity
+        }
+        // reduce memory consumption of index by using adjacency list
+        let mut bitmap_index_bitvector = BitVectorMut::new();
+        //#[allow(clippy::redundant_closure_for_method_calls)] // false positive, anyhow transitive dep
+        /*let mut cv = CompactVector::with_capacity(entries, sucds::utils::needed_bits(entries))
+        .expect("Failed to create OPS index compact vector.");
+        */
+        let mut cv = Vec::<_>::new();
+        for (o, mut indices) in indicess.into_iter().enumerate() {
+            if o % CANCEL_INTERVAL == 0 && cancelled() {
+                return None;
+            }
+            let mut first = true;
+            // sort by predicate
+            indices.sort_by_cached_key(|pos_y| wavelet_y.get(*pos_y as usize).unwrap());
+            for index in indices {
+                bitmap_index_bitvector.push(first);
+                first = false;
+                cv.push(index as usize);
+            }
+        }
+        let bv = BitVector::from(bitmap_index_bitvector);
+        let bitmap_index = Bitmap::from(bv);
+        Some(OpIndex { sequence: Sequence::new(&cv), bitmap: bitmap_index })
+    }
+
+    /// Read the object index from an index file of hdt-cpp or hdt-java (`<file>.hdt.index.v1-1`) that belongs to triples
+    /// with the given order and number of triples.
+    /// The predicate index and the predicate counts that follow the object index in the file are not read, as they are not needed.
+    pub fn read<R: BufRead>(reader: &mut R, order: Order, num_triples: usize) -> Result<Self> {
+        let index_ci = ControlInfo::read(reader)?;
+        if index_ci.control_type != ControlType::Index || index_ci.format != INDEX_FOQ {
+            return Err(Error::InvalidIndex(format!("unsupported index format {}", index_ci.format)));
+        }
+        if index_ci.get("order") != Some((order as u32).to_string()) {
+            return Err(Error::InvalidIndex(format!("order {:?} instead of {order:?}", index_ci.get("order"))));
+        }
+        if index_ci.get("numTriples").and_then(|n| n.parse::<usize>().ok()) != Some(num_triples) {
+            return Err(Error::InvalidIndex(format!(
+                "{:?} triples instead of {num_triples}",
+                index_ci.get("numTriples")
+            )));
+        }
+        let bitmap_last = Bitmap::read(reader).map_err(|e| Error::Bitmap(Level::Index, e))?;
+        let sequence = Sequence::read(reader).map_err(|e| Error::Sequence(Level::Index, e))?;
+        if sequence.entries != num_triples || bitmap_last.len() < num_triples {
+            return Err(Error::InvalidIndex(format!(
+                "{} entries and {} bits instead of {num_triples}",
+                sequence.entries,
+                bitmap_last.len()
+            )));
+        }
+        // hdt-cpp and hdt-java mark the last position of each object, we mark the first one
+        let mut bitmap_first = BitVectorMut::new();
+        for i in 0..num_triples {
+            bitmap_first.push(i == 0 || bitmap_last.at_last_sibling(i - 1));
+        }
+        Ok(OpIndex { sequence, bitmap: Bitmap::from(bitmap_first) })
+    }
+
     /// Size in bytes on the heap.
     pub fn size_in_bytes(&self) -> usize {
         //self.sequence.len() * self.sequence.width() / 8 + self.bitmap.size_in_bytes()
@@ -102,6 +229,60 @@ impl OpIndex {
 
 type WT = QWT512<usize>;
 
+/// When the object index of a [`TriplesBitmap`] is built.
+/// It is needed for patterns with a bound object and unbound subject, e.g. ??O and ?PO with SPO order,
+/// and takes up a significant part of the loading time and memory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectIndex {
+    /// Build the object index while loading.
+    #[default]
+    Eager,
+    /// Build the object index on the first query that needs it.
+    Lazy,
+    /// Never build the object index, patterns that would need it are answered by scanning the triples instead.
+    Disabled,
+}
+
+/// Serializes the object index only if it has been built.
+#[cfg(feature = "cache")]
+mod serde_op_index {
+    use super::OpIndex;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::sync::OnceLock;
+
+    pub fn serialize<S: Serializer>(op_index: &OnceLock<OpIndex>, serializer: S) -> Result<S::Ok, S::Error> {
+        op_index.get().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<OnceLock<OpIndex>, D::Error> {
+        Ok(Option::<OpIndex>::deserialize(deserializer)?.map_or_else(OnceLock::new, OnceLock::from))
+    }
+}
+
+/// Number of triples that match a triple pattern, see [`TriplesBitmap::estimate_count`].
+/// The count is exact if the lower and upper bounds are equal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cardinality {
+    /// estimated number of matching triples between the lower and the upper bound
+    pub estimate: usize,
+    /// minimum number of matching triples
+    pub lower: usize,
+    /// maximum number of matching triples
+    pub upper: usize,
+}
+
+impl Cardinality {
+    /// Exactly the given number of matching triples.
+    pub const fn exact(count: usize) -> Self {
+        Cardinality { estimate: count, lower: count, upper: count }
+    }
+
+    /// Whether the count is known exactly.
+    pub const fn is_exact(&self) -> bool {
+        self.lower == self.upper
+    }
+}
+
 /// `BitmapTriples` variant of the triples section.
 //#[derive(Clone)]
 #[cfg_attr(feature = "cache", derive(Serialize, Deserialize))]
@@ -112,7 +293,15 @@ pub struct TriplesBitmap {
     /// adjacency list storing the object IDs
     pub adjlist_z: AdjList,
     /// Index for object-based access. Points to the predicate layer.
-    pub op_index: OpIndex,
+    /// Empty until it is built, use [`TriplesBitmap::op_index`], which builds it on demand.
+    #[cfg_attr(feature = "cache", serde(with = "serde_op_index"))]
+    pub op_index: OnceLock<OpIndex>,
+    /// when the object index is built
+    #[cfg_attr(feature = "cache", serde(skip))]
+    object_index: ObjectIndex,
+    /// Offsets for skipping within the triples of a y layer ID. Use [`TriplesBitmap::y_offsets`], which builds it on demand.
+    #[cfg_attr(feature = "cache", serde(skip))]
+    y_offsets: OnceLock<Sequence>,
     /// wavelet matrix for predicate-based access
     pub wavelet_y: WT,
 }
@@ -121,6 +310,8 @@ pub struct TriplesBitmap {
 pub enum Level {
     Y,
     Z,
+    /// object index file of hdt-cpp and hdt-java
+    Index,
 }
 
 /// The error type for the triples bitmap read and write function.
@@ -140,10 +331,14 @@ pub enum Error {
     UnrecognizedTriplesOrder(u32),
     #[error("unknown triples format {0}")]
     UnknownTriplesFormat(String),
-    #[error("triple lists are not supported yet")]
-    TriplesList,
+    #[error("invalid triples list: {0}")]
+    InvalidTriplesList(String),
+    #[error("index file does not belong to the triples section: {0}")]
+    InvalidIndex(String),
     #[error("({0},{1},{2}) none of the components of a triple may be 0.")]
     TripleComponentZero(usize, usize, usize),
+    #[error("IO error")]
+    Io(#[from] std::io::Error),
     #[error("unspecified external library error")]
     External(#[from] Box<dyn std::error::Error + Send + Sync + 'static>),
     #[error("cache decode error")]
@@ -155,7 +350,7 @@ impl fmt::Debug for TriplesBitmap {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "total size {}", ByteSize(self.size_in_bytes() as u64))?;
         writeln!(f, "adjlist_z {:#?}", self.adjlist_z)?;
-        writeln!(f, "op_index {:#?}", self.op_index)?;
+        writeln!(f, "op_index {:#?}", self.op_index.get())?;
         write!(f, "wavelet_y {}", ByteSize(self.wavelet_y.mem_size(SizeFlags::default()) as u64))
     }
 }
@@ -163,54 +358,111 @@ impl fmt::Debug for TriplesBitmap {
 impl TriplesBitmap {
     /// builds the necessary indexes and constructs TriplesBitmap
     pub fn new(order: Order, sequence_y: &Sequence, bitmap_y: Bitmap, adjlist_z: AdjList) -> Self {
+        Self::with_object_index(order, sequence_y, bitmap_y, adjlist_z, ObjectIndex::Eager)
+    }
+
+    /// Like [`TriplesBitmap::new`] but builds the object index only as specified.
+    pub fn with_object_index(
+        order: Order, sequence_y: &Sequence, bitmap_y: Bitmap, adjlist_z: AdjList, object_index: ObjectIndex,
+    ) -> Self {
         //let wavelet_thread = std::thread::spawn(move || WT::from_iter(&sequence_y));
         let wavelet_y = WT::from_iter(sequence_y);
+        Self::from_layers(order, bitmap_y, adjlist_z, wavelet_y, object_index)
+    }
 
-        let entries = adjlist_z.sequence.entries;
-        // if it takes too long to calculate, can also pass in as parameter
-        let max_object = adjlist_z.sequence.into_iter().max().unwrap_or(0).to_owned();
-        // limited to < 2^32 objects
-        let mut indicess = vec![Vec::<u32>::with_capacity(4); max_object];
-        // Count the indexes of appearance of each object
-        // In https://github.com/rdfhdt/hdt-cpp/blob/develop/libhdt/src/triples/BitmapTriples.cpp
-        // they count the number of appearances in a sequence instead, which saves memory
-        // temporarily but they need to loop over it an additional time.
-        for pos_z in 0..entries {
-            let object = adjlist_z.sequence.get(pos_z);
-            if object == 0 {
-                error!("ERROR: There is a zero value in the Z level.");
-                continue;
+    /// Combines the layers with the wavelet matrix built from the Y layer.
+    fn from_layers(
+        order: Order, bitmap_y: Bitmap, adjlist_z: AdjList, wavelet_y: WT, object_index: ObjectIndex,
+    ) -> Self {
+        let mut triples = Self {
+            order,
+            bitmap_y,
+            adjlist_z,
+            op_index: OnceLock::new(),
+            object_index,
+            y_offsets: OnceLock::new(),
+            wavelet_y,
+        };
+        triples.set_object_index(object_index);
+        triples
+    }
+
+    /// The object index, which is built on first access if it is [`ObjectIndex::Lazy`].
+    /// Returns `None` if the object index is [`ObjectIndex::Disabled`].
+    pub fn op_index(&self) -> Option<&OpIndex> {
+        match self.object_index {
+            ObjectIndex::Disabled => None,
+            ObjectIndex::Eager | ObjectIndex::Lazy => {
+                Some(self.op_index.get_or_init(|| OpIndex::new(&self.adjlist_z, &self.wavelet_y)))
             }
-            let pos_y = adjlist_z.bitmap.rank(pos_z.to_owned());
-            indicess[object - 1].push(pos_y as u32); // hdt index counts from 1 but we count from 0 for simplicity
         }
-        // reduce memory consumption of index by using adjacency list
-        let mut bitmap_index_bitvector = BitVectorMut::new();
-        //#[allow(clippy::redundant_closure_for_method_calls)] // false positive, anyhow transitive dep
-        /*let mut cv = CompactVector::with_capacity(entries, sucds::utils::needed_bits(entries))
-        .expect("Failed to create OPS index compact vector.");
-        */
-        let mut cv = Vec::<_>::new();
-        // temporarily disable threading for wasm support
-        //let wavelet_y = wavelet_thread.join().unwrap(); // join as late as possible for max parallelization
-        for mut indices in indicess {
-            let mut first = true;
-            // sort by predicate
-            indices.sort_by_cached_key(|pos_y| wavelet_y.get(*pos_y as usize).unwrap());
-            for index in indices {
-                bitmap_index_bitvector.push(first);
-                first = false;
-                cv.push(index as usize);
+    }
+
+    /// For each position in the y layer, the number of z layer entries of the previous positions with the same ID,
+    /// which allows [`PredicateIter::nth`] to binary search for an offset.
+    /// Built on first access in one pass over the y layer.
+    pub fn y_offsets(&self) -> &Sequence {
+        self.y_offsets.get_or_init(|| {
+            let mut counts = Vec::<usize>::new();
+            let mut offsets = Vec::with_capacity(self.wavelet_y.len());
+            let mut start = 0;
+            for pos_y in 0..self.wavelet_y.len() {
+                let y = self.wavelet_y.get(pos_y).unwrap();
+                if y >= counts.len() {
+                    counts.resize(y + 1, 0);
+                }
+                offsets.push(counts[y]);
+                let end = self.adjlist_z.last(pos_y as Id) + 1;
+                counts[y] += end - start;
+                start = end;
+            }
+            Sequence::new(&offsets)
+        })
+    }
+
+    /// Like [`TriplesBitmap::set_object_index`] but checks every [`CANCEL_INTERVAL`] entries while building the object index
+    /// whether to stop, in which case the object index is left unchanged and false is returned.
+    pub(crate) fn set_object_index_cancellable(
+        &mut self, object_index: ObjectIndex, cancelled: &mut dyn FnMut() -> bool,
+    ) -> bool {
+        if object_index == ObjectIndex::Eager && self.op_index.get().is_none() {
+            let Some(op_index) = OpIndex::build(&self.adjlist_z, &self.wavelet_y, cancelled) else { return false };
+            self.op_index = OnceLock::from(op_index);
+        }
+        self.set_object_index(object_index);
+        true
+    }
+
+    /// Changes when the object index is built.
+    /// [`ObjectIndex::Eager`] builds it now if it doesn't exist yet and [`ObjectIndex::Disabled`] frees it.
+    pub fn set_object_index(&mut self, object_index: ObjectIndex) {
+        self.object_index = object_index;
+        match object_index {
+            ObjectIndex::Eager => {
+                self.op_index();
+            }
+            ObjectIndex::Lazy => {}
+            ObjectIndex::Disabled => {
+                self.op_index.take();
             }
         }
-        let bv = BitVector::from(bitmap_index_bitvector);
-        let bitmap_index = Bitmap { dict: RSNarrow::from(bv) };
-        let op_index = OpIndex { sequence: Sequence::new(&cv), bitmap: bitmap_index };
-        Self { order, bitmap_y, adjlist_z, op_index, wavelet_y }
     }
 
     /// Creates a new TriplesBitmap from a list of sorted RDF triples
     pub fn from_triples(triples: &[TripleId]) -> Self {
+        Self::from_coords(Order::SPO, triples.iter().copied())
+    }
+
+    /// Creates a new TriplesBitmap in the given order from a list of RDF triples, which don't need to be sorted.
+    pub fn from_triples_with_order(triples: &[TripleId], order: Order) -> Self {
+        let mut coords: Vec<[Id; 3]> = triples.iter().map(|t| order.to_coord(*t)).collect();
+        coords.sort_unstable();
+        coords.dedup();
+        Self::from_coords(order, coords)
+    }
+
+    /// Creates a new TriplesBitmap from sorted and deduplicated X, Y, Z coordinates, which are consumed one at a time.
+    pub(crate) fn from_coords(order: Order, triples: impl IntoIterator<Item = [Id; 3]>) -> Self {
         let mut y_bitmap = BitVectorMut::new();
         let mut z_bitmap = BitVectorMut::new();
         let mut array_y = Vec::new();
@@ -222,8 +474,8 @@ impl TriplesBitmap {
         let mut max_y = 1;
         let mut max_z = 1;
 
-        for (i, triple) in triples.iter().enumerate() {
-            let [x, y, z] = *triple;
+        for (i, triple) in triples.into_iter().enumerate() {
+            let [x, y, z] = triple;
 
             assert!(!(x == 0 || y == 0 || z == 0), "triple IDs should never be zero");
             max_y = max_y.max(y);
@@ -270,27 +522,81 @@ impl TriplesBitmap {
         let sequence_y = Sequence::new(&array_y);
         let sequence_z = Sequence::new(&array_z);
         let adjlist_z = AdjList::new(sequence_z, bitmap_z);
-        TriplesBitmap::new(Order::SPO, &sequence_y, bitmap_y, adjlist_z)
+        TriplesBitmap::new(order, &sequence_y, bitmap_y, adjlist_z)
     }
 
     /// read the whole triple section including control information
     // TODO: rename to "read" for consistency with the other components and rename existing read function accordingly
     pub fn read_sect<R: BufRead>(reader: &mut R) -> Result<Self> {
+        Self::read_sect_with_object_index(reader, ObjectIndex::Eager)
+    }
+
+    /// Like `read_sect` but builds the object index only as specified.
+    pub fn read_sect_with_object_index<R: BufRead>(reader: &mut R, object_index: ObjectIndex) -> Result<Self> {
+        let mut triples =
+            Self::read_sect_layers(reader)?.build(&mut || false).expect("construction is not cancelled");
+        triples.set_object_index(object_index);
+        Ok(triples)
+    }
+
+    /// Like `read_sect` but without building the wavelet matrix and the object index yet.
+    pub(crate) fn read_sect_layers<R: BufRead>(reader: &mut R) -> Result<TriplesLayers> {
+        let triples_ci = ControlInfo::read(reader)?;
+
+        match &triples_ci.format[..] {
+            "<http://purl.org/HDT/hdt#triplesBitmap>" => {
+                let order = Self::read_order(&triples_ci)?;
+                let (bitmap_y, sequence_y, adjlist_z) = Self::read_layers(reader)?;
+                Ok(TriplesLayers::Bitmap { order, bitmap_y, sequence_y, adjlist_z })
+            }
+            "<http://purl.org/HDT/hdt#triplesList>" => {
+                let mut triples = TriplesList::read(reader, &triples_ci)?.into_bitmap()?;
+                triples.set_object_index(ObjectIndex::Lazy);
+                Ok(TriplesLayers::List(triples))
+            }
+            f => Err(Error::UnknownTriplesFormat(f.to_owned())),
+        }
+    }
+
+    /// Like `read_sect` but the bitmaps and the sequences borrow their data from the given reader instead of copying it.
+    /// The wavelet matrix, the object index and the rank directories of the bitmaps are still constructed in memory.
+    pub fn read_mapped_sect(reader: &mut BytesReader) -> Result<Self> {
+        let mut triples =
+            Self::read_mapped_sect_layers(reader)?.build(&mut || false).expect("construction is not cancelled");
+        triples.set_object_index(ObjectIndex::Eager);
+        Ok(triples)
+    }
+
+    /// Like `read_mapped_sect` but without building the wavelet matrix and the object index yet.
+    pub(crate) fn read_mapped_sect_layers(reader: &mut BytesReader) -> Result<TriplesLayers> {
         let triples_ci = ControlInfo::read(reader)?;
 
         match &triples_ci.format[..] {
-            "<http://purl.org/HDT/hdt#triplesBitmap>" => TriplesBitmap::read(reader, &triples_ci),
-            "<http://purl.org/HDT/hdt#triplesList>" => Err(Error::TriplesList),
+            "<http://purl.org/HDT/hdt#triplesBitmap>" => {
+                let order = Self::read_order(&triples_ci)?;
+                let bitmap_y = Bitmap::read_mapped(reader).map_err(|e| Error::Bitmap(Level::Y, e))?;
+                let bitmap_z = Bitmap::read_mapped(reader).map_err(|e| Error::Bitmap(Level::Z, e))?;
+                let sequence_y = Sequence::read_mapped(reader).map_err(|e| Error::Sequence(Level::Y, e))?;
+                let sequence_z = Sequence::read_mapped(reader).map_err(|e| Error::Sequence(Level::Z, e))?;
+                let adjlist_z = AdjList::new(sequence_z, bitmap_z);
+                Ok(TriplesLayers::Bitmap { order, bitmap_y, sequence_y, adjlist_z })
+            }
+            "<http://purl.org/HDT/hdt#triplesList>" => {
+                let mut triples = TriplesList::read(reader, &triples_ci)?.into_bitmap()?;
+                triples.set_object_index(ObjectIndex::Lazy);
+                Ok(TriplesLayers::List(triples))
+            }
             f => Err(Error::UnknownTriplesFormat(f.to_owned())),
         }
     }
 
-    /// load the cached HDT index file, only supports TriplesBitmap
+    /// load the cached HDT index file, triple lists are cached in their converted TriplesBitmap form
     #[cfg(feature = "cache")]
     pub fn load_cache<R: BufRead>(reader: &mut R, info: &ControlInfo) -> Result<Self> {
         match &info.format[..] {
-            "<http://purl.org/HDT/hdt#triplesBitmap>" => TriplesBitmap::load(reader),
-            "<http://purl.org/HDT/hdt#triplesList>" => Err(Error::TriplesList),
+            "<http://purl.org/HDT/hdt#triplesBitmap>" | "<http://purl.org/HDT/hdt#triplesList>" => {
+                TriplesBitmap::load(reader)
+            }
             f => Err(Error::UnknownTriplesFormat(f.to_owned())),
         }
     }
@@ -305,7 +611,8 @@ impl TriplesBitmap {
     /// Size in bytes on the heap.
     pub fn size_in_bytes(&self) -> usize {
         self.adjlist_z.size_in_bytes()
-            + self.op_index.size_in_bytes()
+            + self.op_index.get().map_or(0, OpIndex::size_in_bytes)
+            + self.y_offsets.get().map_or(0, Sequence::size_in_bytes)
             + self.wavelet_y.mem_size(SizeFlags::default())
     }
 
@@ -346,6 +653,61 @@ impl TriplesBitmap {
         self.bin_search_y(property_id, self.find_y(subject_id), self.last_y(subject_id) + 1)
     }
 
+    /// Position of the given triple in the adjacency list of the Z layer, which numbers all triples from 0 in the order of the triples section.
+    /// Returns `None` if the triple is not contained.
+    pub fn position(&self, triple: TripleId) -> Option<usize> {
+        let [x, y, z] = self.order.to_coord(triple);
+        if x == 0 || y == 0 || z == 0 || x > self.bitmap_y.num_ones() {
+            return None;
+        }
+        let pos_y = self.search_y(x - 1, y)?;
+        self.adjlist_z.search(pos_y, z)
+    }
+
+    /// Number of triples that fit the given pattern in subject, predicate, object order, where 0 stands for a variable.
+    /// The count is calculated from the bitmaps and indexes without iterating over the triples.
+    /// It is exact except for patterns with only the y layer bound, e.g. ?P? with SPO order,
+    /// where only the number of matching x, y pairs is known, which is multiplied with the average number of z entries per pair.
+    pub fn estimate_count(&self, pattern: TripleId) -> Cardinality {
+        let [x, y, z] = self.order.to_coord(pattern);
+        let num_x = self.bitmap_y.num_ones();
+        // a lazy object index is only built if the pattern needs it anyway
+        let op_index = if x == 0 && z > 0 { self.op_index() } else { self.op_index.get() };
+        if x > num_x || op_index.is_some_and(|op_index| z > op_index.bitmap.num_ones()) {
+            return Cardinality::exact(0);
+        }
+        // number of entries in the z layer for the y layer positions in the given range
+        let z_count = |begin: usize, end: usize| self.adjlist_z.find(end) - self.adjlist_z.find(begin);
+        let count = match (x, y, z) {
+            (0, 0, 0) => self.len(),
+            (1.., 0, 0) => z_count(self.find_y(x - 1), self.find_y(x)),
+            (1.., 1.., 0) => self.search_y(x - 1, y).map_or(0, |pos_y| z_count(pos_y, pos_y + 1)),
+            (1.., 1.., 1..) => {
+                usize::from(self.search_y(x - 1, y).and_then(|pos_y| self.adjlist_z.search(pos_y, z)).is_some())
+            }
+            // only the y entries of x need to be searched
+            (1.., 0, 1..) => (self.find_y(x - 1)..self.find_y(x))
+                .filter(|pos_y| self.adjlist_z.search(*pos_y, z).is_some())
+                .count(),
+            (0, 1.., 1..) => {
+                PredicateObjectIter::try_new(self, y, z).map_or_else(|| self.scan_z(y, z).count(), |i| i.len())
+            }
+            (0, 0, 1..) => ObjectIter::try_new(self, z).map_or_else(|| self.scan_z(y, z).count(), |i| i.len()),
+            (0, 1.., 0) => {
+                let pairs = self.wavelet_y.rank(y, self.wavelet_y.len()).unwrap_or(0);
+                if pairs == 0 {
+                    return Cardinality::exact(0);
+                }
+                // each pair has at least one z entry and the surplus entries of all pairs are distributed arbitrarily
+                let num_pairs = self.wavelet_y.len();
+                let upper = pairs + (self.len() - num_pairs);
+                let estimate = (pairs * self.len() / num_pairs).clamp(pairs, upper);
+                return Cardinality { estimate, lower: pairs, upper };
+            }
+        };
+        Cardinality::exact(count)
+    }
+
     /*
         /// Get the predicate ID for the given z index position.
     fn get_p(bitmap_z:  Bitmap, wavelet_y: WaveletMatrix, pos_z: usize) -> Id {
@@ -354,15 +716,20 @@ impl TriplesBitmap {
     }
     */
 
-    fn read<R: BufRead>(reader: &mut R, triples_ci: &ControlInfo) -> Result<Self> {
+    /// Parse the triple order from the control information of the triples section.
+    fn read_order(triples_ci: &ControlInfo) -> Result<Order> {
         //let order: Order = Order::try_from(triples_ci.get("order").unwrap().parse::<u32>());
-        let order: Order;
-        if let Some(n) = triples_ci.get("order").and_then(|v| v.parse::<u32>().ok()) {
-            order = Order::try_from(n)?;
-        } else {
-            return Err(Error::UnspecifiedTriplesOrder);
+        match triples_ci.get("order").and_then(|v| v.parse::<u32>().ok()) {
+            Some(n) => match Order::try_from(n)? {
+                Order::Unknown => Err(Error::UnknownTriplesOrder),
+                order => Ok(order),
+            },
+            None => Err(Error::UnspecifiedTriplesOrder),
         }
+    }
 
+    /// Read the bitmaps and sequences of the Y and Z layers.
+    fn read_layers<R: BufRead>(reader: &mut R) -> Result<(Bitmap, Sequence, AdjList)> {
         // read bitmaps
         let bitmap_y = Bitmap::read(reader).map_err(|e| Error::Bitmap(Level::Y, e))?;
         let bitmap_z = Bitmap::read(reader).map_err(|e| Error::Bitmap(Level::Z, e))?;
@@ -370,14 +737,42 @@ impl TriplesBitmap {
         // read sequences
         let sequence_y = Sequence::read(reader).map_err(|e| Error::Sequence(Level::Y, e))?;
         let sequence_z = Sequence::read(reader).map_err(|e| Error::Sequence(Level::Z, e))?;
-        let adjlist_z = AdjList::new(sequence_z, bitmap_z);
+        Ok((bitmap_y, sequence_y, AdjList::new(sequence_z, bitmap_z)))
+    }
+
+    /// Like `read_sect` but reads the object index from the given index file of hdt-cpp or hdt-java instead of building it.
+    /// If the index file does not belong to the triples section, a warning is logged and the object index is built as usual.
+    /// The wavelet matrix is always built from the Y layer, as the index file contains no equivalent.
+    pub fn read_sect_with_index<R: BufRead, I: BufRead>(reader: &mut R, index_reader: &mut I) -> Result<Self> {
+        let triples_ci = ControlInfo::read(reader)?;
 
-        let triples_bitmap = TriplesBitmap::new(order, &sequence_y, bitmap_y, adjlist_z);
-        Ok(triples_bitmap)
+        match &triples_ci.format[..] {
+            "<http://purl.org/HDT/hdt#triplesBitmap>" => {
+                let order = Self::read_order(&triples_ci)?;
+                let (bitmap_y, sequence_y, adjlist_z) = Self::read_layers(reader)?;
+                let wavelet_y = WT::from_iter(&sequence_y);
+                let op_index = OpIndex::read(index_reader, order, adjlist_z.len()).unwrap_or_else(|e| {
+                    warn!("ignoring index file, building object index instead: {e}");
+                    OpIndex::new(&adjlist_z, &wavelet_y)
+                });
+                let object_index = ObjectIndex::Eager;
+                Ok(Self {
+                    order,
+                    bitmap_y,
+                    adjlist_z,
+                    op_index: OnceLock::from(op_index),
+                    object_index,
+                    y_offsets: OnceLock::new(),
+                    wavelet_y,
+                })
+            }
+            "<http://purl.org/HDT/hdt#triplesList>" => TriplesList::read(reader, &triples_ci)?.into_bitmap(),
+            f => Err(Error::UnknownTriplesFormat(f.to_owned())),
+        }
     }
 
     pub fn write(&self, write: &mut impl std::io::Write) -> Result<()> {
-        ControlInfo::bitmap_triples(self.order.clone() as u32, self.adjlist_z.len() as u32).write(write)?;
+        ControlInfo::bitmap_triples(self.order as u32, self.adjlist_z.len()).write(write)?;
         self.bitmap_y.write(write).map_err(|e| Error::Bitmap(Level::Y, e))?;
         self.adjlist_z.bitmap.write(write).map_err(|e| Error::Bitmap(Level::Z, e))?;
         let y = self.wavelet_y.iter().collect::<Vec<_>>();
@@ -387,30 +782,160 @@ impl TriplesBitmap {
         Ok(())
     }
 
+    /// Write the object index in the index file format of hdt-cpp and hdt-java (`<file>.hdt.index.v1-1`).
+    /// Those implementations also expect a predicate index and the number of occurrences of each predicate,
+    /// which are derived from the wavelet matrix.
+    pub fn write_index(&self, write: &mut impl std::io::Write) -> Result<()> {
+        let num_triples = self.adjlist_z.len();
+        ControlInfo::object_index(self.order as u32, num_triples).write(write)?;
+        let built;
+        let op_index = if let Some(op_index) = self.op_index() {
+            op_index
+        } else {
+            built = OpIndex::new(&self.adjlist_z, &self.wavelet_y);
+            &built
+        };
+        // hdt-cpp and hdt-java mark the last position of each object, we mark the first one
+        let mut bitmap_last = BitVectorMut::new();
+        for i in 1..=num_triples {
+            bitmap_last.push(i == num_triples || op_index.bitmap.at_last_sibling(i));
+        }
+        Bitmap::from(bitmap_last).write(write).map_err(|e| Error::Bitmap(Level::Index, e))?;
+        op_index.sequence.write(write).map_err(|e| Error::Sequence(Level::Index, e))?;
+
+        // predicate index: the positions in the Y layer grouped by predicate with a one bit marking the last of each predicate
+        let y = self.wavelet_y.iter().collect::<Vec<_>>();
+        let mut counts = vec![0; y.iter().max().copied().unwrap_or(0)];
+        for p in &y {
+            counts[p - 1] += 1;
+        }
+        let mut next = counts
+            .iter()
+            .scan(0, |start, count| {
+                let current = *start;
+                *start += count;
+                Some(current)
+            })
+            .collect::<Vec<_>>();
+        let mut positions = vec![0; y.len()];
+        for (pos_y, p) in y.iter().enumerate() {
+            positions[next[p - 1]] = pos_y;
+            next[p - 1] += 1;
+        }
+        let mut bitmap_predicates = BitVectorMut::new();
+        for count in counts.iter().filter(|count| **count > 0) {
+            bitmap_predicates.extend_with_zeros(count - 1);
+            bitmap_predicates.push(true);
+        }
+        Bitmap::from(bitmap_predicates).write(write).map_err(|e| Error::Bitmap(Level::Index, e))?;
+        Sequence::new(&positions).write(write).map_err(|e| Error::Sequence(Level::Index, e))?;
+        Sequence::new(&counts).write(write).map_err(|e| Error::Sequence(Level::Index, e))?;
+        Ok(())
+    }
+
     /// Transform the given IDs of the layers in triple section order to a triple ID.
-    /// Warning: At the moment only SPO is properly supported anyways, in which case this is equivalent to `[x,y,z]`.
-    /// Other orders may lead to undefined behaviour.
+    /// For the SPO order, this is equivalent to `[x,y,z]`.
     pub const fn coord_to_triple(&self, x: Id, y: Id, z: Id) -> Result<TripleId> {
         if x == 0 || y == 0 || z == 0 {
             return Err(Error::TripleComponentZero(x, y, z));
         }
         match self.order {
-            Order::SPO => Ok([x, y, z]),
-            Order::SOP => Ok([x, z, y]),
-            Order::PSO => Ok([y, x, z]),
-            Order::POS => Ok([y, z, x]),
-            Order::OSP => Ok([z, x, y]),
-            Order::OPS => Ok([z, y, x]),
             Order::Unknown => Err(Error::UnknownTriplesOrder),
+            order => Ok(order.to_triple([x, y, z])),
         }
     }
 
+    /// Order of the triples section.
+    pub const fn order(&self) -> Order {
+        self.order
+    }
+
+    /// Iterate over all triple IDs that fit the given pattern in subject, predicate, object order, where 0 stands for a variable.
+    /// The pattern is rearranged into the layers of the triples order and answered by the matching iterator,
+    /// the resulting triples are again in subject, predicate, object order.
+    pub fn triples_with_pattern(&self, pattern: TripleId) -> Box<dyn Iterator<Item = TripleId> + '_> {
+        let [x, y, z] = self.order.to_coord(pattern);
+        // can't use slice: half_open_range_patterns_in_slices is still unstable, see https://github.com/rust-lang/rust/issues/67264
+        match (x, y, z) {
+            (1.., _, _) => Box::new(SubjectIter::with_pattern(self, [x, y, z])),
+            (0, 1.., 1..) => match PredicateObjectIter::try_new(self, y, z) {
+                Some(iter) => Box::new(iter.map(move |x| self.coord_to_triple(x, y, z).unwrap())),
+                None => self.scan_z(y, z),
+            },
+            (0, 1.., 0) => Box::new(PredicateIter::new(self, y)),
+            (0, 0, 1..) => match ObjectIter::try_new(self, z) {
+                Some(iter) => Box::new(iter),
+                None => self.scan_z(y, z),
+            },
+            (0, 0, 0) => Box::new(SubjectIter::new(self)),
+        }
+    }
+
+    /// Like [`TriplesBitmap::triples_with_pattern`] but skips the first `offset` triples and yields at most `limit` ones.
+    /// The iterators jump to the offset with rank and select queries instead of visiting each skipped triple,
+    /// except for S?O patterns in the layers of the triples order, which skip each subject, predicate pair.
+    pub fn triples_with_pattern_page(
+        &self, pattern: TripleId, offset: usize, limit: usize,
+    ) -> Box<dyn Iterator<Item = TripleId> + '_> {
+        let [x, y, z] = self.order.to_coord(pattern);
+        // skip calls nth on the underlying iterator, so it needs to come before any adapter
+        match (x, y, z) {
+            (1.., _, _) => Box::new(SubjectIter::with_pattern(self, [x, y, z]).skip(offset).take(limit)),
+            (0, 1.., 1..) => match PredicateObjectIter::try_new(self, y, z) {
+                Some(iter) => {
+                    Box::new(iter.skip(offset).take(limit).map(move |x| self.coord_to_triple(x, y, z).unwrap()))
+                }
+                None => Box::new(self.scan_z(y, z).skip(offset).take(limit)),
+            },
+            (0, 1.., 0) => Box::new(PredicateIter::new(self, y).skip(offset).take(limit)),
+            (0, 0, 1..) => match ObjectIter::try_new(self, z) {
+                Some(iter) => Box::new(iter.skip(offset).take(limit)),
+                None => Box::new(self.scan_z(y, z).skip(offset).take(limit)),
+            },
+            (0, 0, 0) => Box::new(SubjectIter::new(self).skip(offset).take(limit)),
+        }
+    }
+
+    /// Triples with the given z layer ID and, if not 0, y layer ID without using the object index.
+    fn scan_z(&self, y: Id, z: Id) -> Box<dyn Iterator<Item = TripleId> + '_> {
+        let iter: Box<dyn Iterator<Item = TripleId> + '_> =
+            if y == 0 { Box::new(SubjectIter::new(self)) } else { Box::new(PredicateIter::new(self, y)) };
+        Box::new(iter.filter(move |t| self.order.to_coord(*t)[2] == z))
+    }
+
     /// Number of triples
     pub const fn len(&self) -> usize {
         self.adjlist_z.sequence.entries
     }
 }
 
+/// A triples section that is read but whose wavelet matrix is not built yet, see [`TriplesLayers::build`].
+pub(crate) enum TriplesLayers {
+    /// The layers of a bitmap triples section.
+    Bitmap { order: Order, bitmap_y: Bitmap, sequence_y: Sequence, adjlist_z: AdjList },
+    /// A triples list, which is already converted while reading.
+    List(TriplesBitmap),
+}
+
+impl TriplesLayers {
+    /// Builds the wavelet matrix with a lazy object index,
+    /// checking every [`CANCEL_INTERVAL`] entries whether to stop and return `None`.
+    pub(crate) fn build(self, cancelled: &mut dyn FnMut() -> bool) -> Option<TriplesBitmap> {
+        match self {
+            TriplesLayers::Bitmap { order, bitmap_y, sequence_y, adjlist_z } => {
+                let mut stopped = false;
+                let wavelet_y = WT::from_iter(sequence_y.into_iter().enumerate().map_while(|(i, y)| {
+                    stopped = i % CANCEL_INTERVAL == 0 && cancelled();
+                    (!stopped).then_some(y)
+                }));
+                (!stopped)
+                    .then(|| TriplesBitmap::from_layers(order, bitmap_y, adjlist_z, wavelet_y, ObjectIndex::Lazy))
+            }
+            TriplesLayers::List(triples) => Some(triples),
+        }
+    }
+}
+
 impl<'a> IntoIterator for &'a TriplesBitmap {
     type Item = TripleId;
     type IntoIter = SubjectIter<'a>;
@@ -506,6 +1031,99 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn orders() -> color_eyre::Result<()> {
+        init();
+        let spo: Vec<TripleId> = crate::hdt::tests::snikmeta()?.triples.into_iter().collect();
+        for order in Order::ORDERS {
+            let triples = TriplesBitmap::from_triples_with_order(&spo, order);
+            assert_eq!(triples.order(), order);
+            // write and read back
+            let mut buf = Vec::<u8>::new();
+            triples.write(&mut buf)?;
+            let triples = TriplesBitmap::read_sect(&mut std::io::Cursor::new(buf))?;
+            assert_eq!(triples.order(), order);
+            let mut all: Vec<TripleId> = triples.into_iter().collect();
+            for (pos, t) in all.iter().enumerate() {
+                assert_eq!(triples.position(*t), Some(pos), "{order:?} {t:?}");
+            }
+            all.sort_unstable();
+            assert_eq!(all, spo, "{order:?}");
+            // every combination of bound and unbound components for a sample of triples
+            for t in spo.iter().step_by(11) {
+                for mask in 0..8 {
+                    let pattern: TripleId = [0, 1, 2].map(|i| if mask & (1 << i) != 0 { t[i] } else { 0 });
+                    let mut expected: Vec<TripleId> = spo
+                        .iter()
+                        .filter(|x| (0..3).all(|i| pattern[i] == 0 || pattern[i] == x[i]))
+                        .copied()
+                        .collect();
+                    expected.sort_unstable();
+                    let mut actual: Vec<TripleId> = triples.triples_with_pattern(pattern).collect();
+                    for offset in (0..=actual.len() + 1).step_by(3) {
+                        let page: Vec<TripleId> = triples.triples_with_pattern_page(pattern, offset, 4).collect();
+                        let expected_page = &actual[offset.min(actual.len())..(offset + 4).min(actual.len())];
+                        assert_eq!(page, expected_page, "{order:?} {pattern:?} offset {offset}");
+                    }
+                    actual.sort_unstable();
+                    assert_eq!(expected, actual, "{order:?} {pattern:?}");
+                    let cardinality = triples.estimate_count(pattern);
+                    assert!(
+                        (cardinality.lower..=cardinality.upper).contains(&actual.len()),
+                        "{order:?} {pattern:?} {cardinality:?}"
+                    );
+                    assert!((cardinality.lower..=cardinality.upper).contains(&cardinality.estimate));
+                    // only a bound y layer is estimated
+                    if order.to_coord(pattern).map(|c| c != 0) != [false, true, false] {
+                        assert_eq!(cardinality, Cardinality::exact(actual.len()), "{order:?} {pattern:?}");
+                    }
+                }
+            }
+            assert_eq!(triples.position([1, 1, 1_000_000]), None);
+            assert_eq!(triples.position([1_000_000, 1, 1]), None);
+            assert_eq!(triples.estimate_count([0, 0, 0]), Cardinality::exact(spo.len()));
+            assert_eq!(triples.estimate_count([1_000_000, 1, 1_000_000]), Cardinality::exact(0));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn object_index() -> color_eyre::Result<()> {
+        init();
+        let mut buf = Vec::<u8>::new();
+        crate::hdt::tests::snikmeta()?.triples.write(&mut buf)?;
+        let eager = TriplesBitmap::read_sect(&mut std::io::Cursor::new(&buf))?;
+        assert!(eager.op_index.get().is_some());
+        for object_index in [ObjectIndex::Lazy, ObjectIndex::Disabled] {
+            let triples =
+                TriplesBitmap::read_sect_with_object_index(&mut std::io::Cursor::new(&buf), object_index)?;
+            assert!(triples.op_index.get().is_none());
+            // subject bound patterns don't need the object index
+            assert_eq!(
+                triples.triples_with_pattern([1, 0, 0]).count(),
+                eager.triples_with_pattern([1, 0, 0]).count()
+            );
+            assert_eq!(triples.estimate_count([1, 0, 1]), eager.estimate_count([1, 0, 1]));
+            assert!(triples.op_index.get().is_none());
+            for pattern in [[0, 0, 1], [0, 0, 100], [0, 1, 1], [0, 2, 57]] {
+                let mut expected: Vec<TripleId> = eager.triples_with_pattern(pattern).collect();
+                expected.sort_unstable();
+                let mut actual: Vec<TripleId> = triples.triples_with_pattern(pattern).collect();
+                actual.sort_unstable();
+                assert_eq!(expected, actual, "{object_index:?} {pattern:?}");
+                assert_eq!(triples.estimate_count(pattern), eager.estimate_count(pattern));
+                assert_eq!(
+                    triples.triples_with_pattern_page(pattern, 1, 2).count(),
+                    expected.len().saturating_sub(1).min(2)
+                );
+            }
+            assert_eq!(ObjectIter::try_new(&triples, 1).is_some(), object_index == ObjectIndex::Lazy);
+            assert_eq!(PredicateObjectIter::try_new(&triples, 1, 1).is_some(), object_index == ObjectIndex::Lazy);
+            assert_eq!(triples.op_index.get().is_some(), object_index == ObjectIndex::Lazy);
+        }
+        Ok(())
+    }
+
     /*
       #[test]
         fn from_triples() -> color_eyre::Result<()> {
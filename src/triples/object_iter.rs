@@ -1,14 +1,15 @@
-use crate::triples::{Id, TripleId, TriplesBitmap};
+use crate::triples::{Id, OpIndex, TripleId, TriplesBitmap};
 use qwt::AccessUnsigned;
 
 // see "Exchange and Consumption of Huge RDF Data" by Martinez et al. 2012
 // https://link.springer.com/chapter/10.1007/978-3-642-30284-8_36
-// actually only an object iterator when SPO order is used
-// TODO test with other orders and fix if broken
+// actually only an object iterator when SPO order is used, in general it iterates over the z layer
 
 /// Iterator over all triples with a given object ID, answering an (?S,?P,O) query.
+/// With other orders than SPO, it answers the pattern with the given ID in the z layer, e.g. (S,?P,?O) for POS.
 pub struct ObjectIter<'a> {
     triples: &'a TriplesBitmap,
+    op_index: &'a OpIndex,
     o: Id,
     pos_index: usize,
     max_index: usize,
@@ -16,13 +17,19 @@ pub struct ObjectIter<'a> {
 
 impl<'a> ObjectIter<'a> {
     /// Create a new iterator over all triples with the given object ID.
-    /// Panics if the object does not exist.
+    /// Panics if the object does not exist or the object index is disabled, see [`ObjectIter::try_new`].
     pub fn new(triples: &'a TriplesBitmap, o: Id) -> Self {
+        Self::try_new(triples, o).expect("object index is disabled")
+    }
+
+    /// Like [`ObjectIter::new`] but returns `None` if the object index is disabled, see [`TriplesBitmap::op_index`].
+    pub fn try_new(triples: &'a TriplesBitmap, o: Id) -> Option<Self> {
         assert!(o != 0, "object 0 does not exist, cant iterate");
-        let pos_index = triples.op_index.find(o);
-        let max_index = triples.op_index.last(o);
+        let op_index = triples.op_index()?;
+        let pos_index = op_index.find(o);
+        let max_index = op_index.last(o);
         //println!("ObjectIter o={} pos_index={} max_index={}", o, pos_index, max_index);
-        ObjectIter { triples, o, pos_index, max_index }
+        Some(ObjectIter { triples, op_index, o, pos_index, max_index })
     }
 }
 
@@ -32,10 +39,23 @@ impl Iterator for ObjectIter<'_> {
         if self.pos_index > self.max_index {
             return None;
         }
-        let pos_y = self.triples.op_index.sequence.get(self.pos_index);
+        let pos_y = self.op_index.sequence.get(self.pos_index);
         let y = self.triples.wavelet_y.get(pos_y).unwrap() as Id;
         let x = self.triples.bitmap_y.rank(pos_y) as Id + 1;
         self.pos_index += 1;
-        Some([x, y, self.o])
+        Some(self.triples.coord_to_triple(x, y, self.o).unwrap())
+    }
+
+    /// Skips to the n-th next triple in constant time, as each triple is one entry of the object index.
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.pos_index = self.pos_index.saturating_add(n);
+        self.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.max_index + 1).saturating_sub(self.pos_index);
+        (len, Some(len))
     }
 }
+
+impl ExactSizeIterator for ObjectIter<'_> {}
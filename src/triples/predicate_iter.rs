@@ -2,6 +2,7 @@ use crate::triples::{Id, TripleId, TriplesBitmap};
 use qwt::{RankUnsigned, SelectUnsigned};
 
 /// Iterator over all triples with a given property ID, answering an (?S,P,?O) query.
+/// With other orders than SPO, it answers the pattern with the given ID in the y layer, e.g. (?S,?P,O) for POS.
 pub struct PredicateIter<'a> {
     triples: &'a TriplesBitmap,
     s: Id,
@@ -31,7 +32,7 @@ impl Iterator for PredicateIter<'_> {
         }
         if self.os == 0 {
             // Algorithm 1 findSubj from Martinez et al. 2012 ******
-            let pos_y = self.triples.wavelet_y.select(self.p as usize, self.i).unwrap();
+            let pos_y = self.select(self.i);
             self.s = self.triples.bitmap_y.rank(pos_y) as Id + 1;
             // *****************************************************
             // SP can have multiple O
@@ -50,4 +51,101 @@ impl Iterator for PredicateIter<'_> {
         }
         Some(self.triples.coord_to_triple(self.s, self.p, o).unwrap())
     }
+
+    /// Skips to the n-th next triple without visiting the skipped triples.
+    /// The subject, predicate pair that contains it is found by binary search over the occurrences of the predicate
+    /// using [`TriplesBitmap::y_offsets`], which is built on the first call.
+    fn nth(&mut self, mut n: usize) -> Option<Self::Item> {
+        // the rest of the current pair
+        if self.os > 0 {
+            if n < self.os {
+                self.pos_z += n;
+                self.os -= n;
+                return self.next();
+            }
+            n -= self.os;
+            self.os = 0;
+            self.i += 1;
+        }
+        if self.i >= self.occs {
+            return None;
+        }
+        let offsets = self.triples.y_offsets();
+        let offset = |i: usize| offsets.get(self.select(i));
+        let target = offset(self.i) + n;
+        // the last occurrence whose pair starts at or before the target, which exists because the current one does
+        let (mut lo, mut hi) = (self.i + 1, self.occs);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if offset(mid) <= target {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        self.i = lo - 1;
+        let pos_y = self.select(self.i);
+        let n = target - offsets.get(pos_y);
+        let pos_z = self.triples.adjlist_z.find(pos_y as Id);
+        let len = self.triples.adjlist_z.last(pos_y as Id) + 1 - pos_z;
+        if n >= len {
+            // beyond the last pair
+            self.i = self.occs;
+            return None;
+        }
+        if n > 0 {
+            // continue inside the pair, so that next() yields the object at pos_z + n
+            self.s = self.triples.bitmap_y.rank(pos_y) as Id + 1;
+            self.pos_z = pos_z + n - 1;
+            self.os = len - n;
+        }
+        self.next()
+    }
+}
+
+impl PredicateIter<'_> {
+    /// Position in the y layer of the i-th occurrence of the predicate.
+    fn select(&self, i: usize) -> usize {
+        self.triples.wavelet_y.select(self.p, i).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hdt::tests::snikmeta;
+    use crate::tests::init;
+    use color_eyre::Result;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn nth() -> Result<()> {
+        init();
+        let triples = snikmeta()?.triples;
+        let num_predicates = triples.into_iter().map(|t| t[1]).max().unwrap();
+        for p in 1..=num_predicates {
+            let all: Vec<TripleId> = PredicateIter::new(&triples, p).collect();
+            for n in 0..=all.len() {
+                let mut iter = PredicateIter::new(&triples, p);
+                assert_eq!(iter.nth(n), all.get(n).copied(), "p {p} n {n}");
+                // the iterator continues after the found triple
+                assert_eq!(iter.next(), all.get(n + 1).copied(), "p {p} after n {n}");
+                // seeking repeatedly by the same step
+                let step = n.max(1);
+                let mut iter = PredicateIter::new(&triples, p);
+                let stepped: Vec<_> = std::iter::from_fn(|| iter.nth(step - 1)).collect();
+                assert_eq!(
+                    stepped,
+                    all.iter().copied().skip(step - 1).step_by(step).collect::<Vec<_>>(),
+                    "p {p} step {step}"
+                );
+                // seeking again from the middle of a pair
+                let mut iter = PredicateIter::new(&triples, p);
+                if iter.next().is_some() {
+                    assert_eq!(iter.nth(n), all.get(n + 1).copied(), "p {p} 1 + n {n}");
+                }
+            }
+        }
+        Ok(())
+    }
 }
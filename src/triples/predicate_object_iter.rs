@@ -1,4 +1,4 @@
-use crate::triples::{Id, TriplesBitmap};
+use crate::triples::{Id, OpIndex, TriplesBitmap};
 use qwt::AccessUnsigned;
 use std::cmp::Ordering;
 
@@ -6,22 +6,31 @@ use std::cmp::Ordering;
 // https://link.springer.com/chapter/10.1007/978-3-642-30284-8_36
 
 /// Iterator over all subject IDs with a given predicate and object ID, answering an (?S,P,O) query.
+/// With other orders than SPO, the IDs refer to the x, y and z layers instead, e.g. it yields predicates for given objects and subjects with POS.
 pub struct PredicateObjectIter<'a> {
     triples: &'a TriplesBitmap,
+    op_index: &'a OpIndex,
     pos_index: usize,
     max_index: usize,
 }
 
 impl<'a> PredicateObjectIter<'a> {
     /// Create a new iterator over all triples with the given predicate and object ID.
-    /// Panics if the predicate or object ID is 0.
+    /// Panics if the predicate or object ID is 0 or the object index is disabled, see [`PredicateObjectIter::try_new`].
     pub fn new(triples: &'a TriplesBitmap, p: Id, o: Id) -> Self {
+        Self::try_new(triples, p, o).expect("object index is disabled")
+    }
+
+    /// Like [`PredicateObjectIter::new`] but returns `None` if the object index is disabled,
+    /// see [`TriplesBitmap::op_index`].
+    pub fn try_new(triples: &'a TriplesBitmap, p: Id, o: Id) -> Option<Self> {
         assert_ne!(0, p, "predicate 0 does not exist, cant iterate");
         assert_ne!(0, o, "object 0 does not exist, cant iterate");
-        let mut low = triples.op_index.find(o);
-        let mut high = triples.op_index.last(o);
+        let op_index = triples.op_index()?;
+        let mut low = op_index.find(o);
+        let mut high = op_index.last(o);
         let get_y = |pos_index| {
-            let pos_y = triples.op_index.sequence.get(pos_index);
+            let pos_y = op_index.sequence.get(pos_index);
             triples.wavelet_y.get(pos_y).unwrap() as Id
         };
         // Binary search with a twist:
@@ -53,7 +62,7 @@ impl<'a> PredicateObjectIter<'a> {
                             _ => right_low = mid,
                         }
                     }
-                    return PredicateObjectIter { triples, pos_index: low, max_index: high };
+                    return Some(PredicateObjectIter { triples, op_index, pos_index: low, max_index: high });
                 }
             }
             if (high == 0 && low == 0) || (high == low && high == mid) {
@@ -61,7 +70,7 @@ impl<'a> PredicateObjectIter<'a> {
             }
         }
         // not found
-        PredicateObjectIter { triples, pos_index: 999, max_index: 0 }
+        Some(PredicateObjectIter { triples, op_index, pos_index: 999, max_index: 0 })
     }
 }
 
@@ -71,11 +80,24 @@ impl Iterator for PredicateObjectIter<'_> {
         if self.pos_index > self.max_index {
             return None;
         }
-        let pos_y = self.triples.op_index.sequence.get(self.pos_index);
+        let pos_y = self.op_index.sequence.get(self.pos_index);
         //let y = self.triples.wavelet_y.get(pos_y as usize) as Id;
         //println!(" op p {y}");
         let s = self.triples.bitmap_y.rank(pos_y) as Id + 1;
         self.pos_index += 1;
         Some(s)
     }
+
+    /// Skips to the n-th next ID in constant time, as each triple is one entry of the object index.
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.pos_index = self.pos_index.saturating_add(n);
+        self.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.max_index + 1).saturating_sub(self.pos_index);
+        (len, Some(len))
+    }
 }
+
+impl ExactSizeIterator for PredicateObjectIter<'_> {}
@@ -2,6 +2,7 @@ use super::{Id, TripleId, TriplesBitmap};
 use qwt::AccessUnsigned;
 
 /// Iterator over triples fitting an SPO, SP? S?? or ??? triple pattern.
+/// With other orders than SPO, patterns are given in the x, y and z layers of that order, e.g. as POS for POS, while the resulting triples are always in subject, predicate, object order.
 //#[derive(Debug)]
 pub struct SubjectIter<'a> {
     // triples data
@@ -63,7 +64,6 @@ impl<'a> SubjectIter<'a> {
         let (min_y, max_y, min_z, max_z);
         let mut x = 1;
         let mut search_z = 0;
-        // only SPO order is supported currently
         if pat_x != 0 {
             // S X X
             if pat_y != 0 {
@@ -146,4 +146,25 @@ impl Iterator for SubjectIter<'_> {
         self.pos_z += 1;
         Some(triple_id)
     }
+
+    /// Skips to the n-th next triple in constant time, as each triple is one entry of the z layer, whose y and x positions are found by rank.
+    /// For the S?O pattern, the predicates of the subject are still searched one by one.
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        if self.search_z > 0 {
+            for _ in 0..n {
+                self.next()?;
+            }
+            return self.next();
+        }
+        if n > 0 {
+            self.pos_z = self.pos_z.saturating_add(n);
+            if self.pos_z >= self.max_z {
+                self.pos_y = self.max_y;
+                return None;
+            }
+            self.pos_y = self.triples.adjlist_z.bitmap.rank(self.pos_z);
+            self.x = self.triples.bitmap_y.rank(self.pos_y) + 1;
+        }
+        self.next()
+    }
 }
@@ -0,0 +1,110 @@
+use super::{Error, Id, Order, Result, TripleId, TriplesBitmap};
+use crate::containers::ControlInfo;
+use std::io::{BufRead, Write};
+use std::mem::size_of;
+
+/// Size of a single serialized ID, which is stored as a 64 bit little endian integer.
+const ID_BYTES: usize = size_of::<u64>();
+
+/// Uncompressed triples section that stores each triple as three fixed width IDs.
+/// See <https://www.rdfhdt.org/hdt-binary-format/#triples>.
+/// This format is not indexed, so it is converted into [`TriplesBitmap`] for querying.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TriplesList {
+    /// Order in which the triples are sorted, the components of each triple are always subject, predicate, object.
+    pub order: Order,
+    /// Triple IDs starting at 1.
+    pub triples: Vec<TripleId>,
+}
+
+impl TriplesList {
+    /// Read the triples after the control information, which must contain the number of triples.
+    pub fn read<R: BufRead>(reader: &mut R, triples_ci: &ControlInfo) -> Result<Self> {
+        let order = TriplesBitmap::read_order(triples_ci)?;
+        let num_triples = triples_ci
+            .get("numTriples")
+            .and_then(|v| v.parse::<usize>().ok())
+            .ok_or_else(|| Error::InvalidTriplesList("missing numTriples property".to_owned()))?;
+        // the number of triples is untrusted, so the vector grows with the triples that are actually read
+        let mut triples = Vec::new();
+        let mut buf = [0u8; 3 * ID_BYTES];
+        for _ in 0..num_triples {
+            reader.read_exact(&mut buf)?;
+            let mut triple = [0; 3];
+            for (id, bytes) in triple.iter_mut().zip(buf.chunks_exact(ID_BYTES)) {
+                *id = u64::from_le_bytes(bytes.try_into().unwrap()) as usize;
+            }
+            triples.push(triple);
+        }
+        Ok(TriplesList { order, triples })
+    }
+
+    /// Write the whole triples section including control information.
+    pub fn write(&self, write: &mut impl Write) -> Result<()> {
+        ControlInfo::list_triples(self.order as u32, self.triples.len()).write(write)?;
+        for triple in &self.triples {
+            for id in triple {
+                write.write_all(&(*id as u64).to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Build the bitmap triples in the same order, including the indexes used for querying.
+    pub fn into_bitmap(self) -> Result<TriplesBitmap> {
+        let mut coords: Vec<[Id; 3]> = self.triples.iter().map(|t| self.order.to_coord(*t)).collect();
+        coords.sort_unstable();
+        coords.dedup();
+        let mut last_x = 0;
+        for &[x, y, z] in &coords {
+            if x == 0 || y == 0 || z == 0 {
+                return Err(Error::TripleComponentZero(x, y, z));
+            }
+            if x > last_x + 1 {
+                return Err(Error::InvalidTriplesList(format!(
+                    "{:?} order ID {} is missing",
+                    self.order,
+                    last_x + 1
+                )));
+            }
+            last_x = x;
+        }
+        Ok(TriplesBitmap::from_coords(self.order, coords))
+    }
+}
+
+impl From<&TriplesBitmap> for TriplesList {
+    fn from(triples: &TriplesBitmap) -> Self {
+        TriplesList { order: triples.order(), triples: triples.into_iter().collect() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hdt::tests::snikmeta;
+    use crate::tests::init;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn write_read() -> color_eyre::Result<()> {
+        init();
+        let hdt = snikmeta()?;
+        let list = TriplesList::from(&hdt.triples);
+        assert_eq!(list.triples.len(), 328);
+        let mut buf = Vec::<u8>::new();
+        list.write(&mut buf)?;
+        let mut reader = std::io::Cursor::new(&buf);
+        let ci = ControlInfo::read(&mut reader)?;
+        let list2 = TriplesList::read(&mut reader, &ci)?;
+        assert_eq!(list, list2);
+        assert_eq!(reader.position(), buf.len() as u64);
+        // read_sect converts to bitmap triples on load
+        let triples = TriplesBitmap::read_sect(&mut std::io::Cursor::new(&buf))?;
+        assert_eq!(list.triples, triples.into_iter().collect::<Vec<_>>());
+        // missing subject
+        let gap = TriplesList { order: Order::SPO, triples: vec![[1, 1, 1], [3, 1, 1]] };
+        assert!(gap.into_bitmap().is_err());
+        Ok(())
+    }
+}
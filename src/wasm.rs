@@ -1,5 +1,5 @@
-use crate::Hdt;
 use crate::IdKind;
+use crate::{Dictionary, Hdt};
 use std::io::Cursor;
 use wasm_bindgen::prelude::*;
 